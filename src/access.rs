@@ -0,0 +1,363 @@
+//! Private and password-protected sections of the content tree.
+//!
+//! An `_access.yml` restricts the directory it sits in and everything
+//! beneath it; the nearest one, found by walking up the tree like
+//! `style.css`, applies. It lists who may read the section — any one of the
+//! grants is enough:
+//!
+//! ```yaml
+//! editors: true          # anyone logged in to the editor
+//! users: [alice, bob]    # these editor accounts
+//! password: hunter2      # visitors who enter this shared password
+//! ```
+//!
+//! A file with no grants makes its subtree public again. `handler::handle`
+//! checks the rule before serving anything — pages, listings, feeds and
+//! static files alike — and site-wide pages (tags, sitemap, search,
+//! backlinks) leave restricted pages out.
+
+use axum::{
+    Form,
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use maud::{DOCTYPE, Markup, html};
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use serde::Deserialize;
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    editor,
+    handler::{self, dir_assets},
+    micropub::sha256_hex,
+    state::AppState,
+    template::DirEntry,
+};
+
+pub const ACCESS_FILE: &str = "_access.yml";
+
+/// Cookie holding proof of a section password, scoped to the section's path.
+const PASS_COOKIE: &str = "md_access";
+/// How long an entered password is remembered (30 days).
+const PASS_TTL_SECS: u64 = 30 * 24 * 3600;
+/// Site setting keying the password cookies, generated on first use.
+pub const SECRET_SETTING: &str = "access_secret";
+
+/// Characters escaped in cookie paths and `next` URLs.
+const PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'%')
+    .add(b';')
+    .add(b',')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'#');
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessRule {
+    /// Any logged-in editor user.
+    pub editors: bool,
+    /// These editor users.
+    pub users: Vec<String>,
+    /// Visitors who know this shared password.
+    pub password: Option<String>,
+    /// Clean URL of the directory holding the rule, ending in `/`.
+    #[serde(skip)]
+    pub dir_url: String,
+}
+
+impl AccessRule {
+    fn is_open(&self) -> bool {
+        !self.editors && self.users.is_empty() && self.password.is_none()
+    }
+}
+
+/// Walk up from `dir` toward `www_root` and load the nearest rule file. A
+/// rule that doesn't parse admits editors only, so a typo never opens a
+/// private section.
+pub async fn find_rule(www_root: &Path, dir: &Path) -> Option<Arc<AccessRule>> {
+    for dir in crate::css::ancestor_dirs(www_root, dir) {
+        let candidate = dir.join(ACCESS_FILE);
+        let Ok(raw) = tokio::fs::read_to_string(&candidate).await else {
+            continue;
+        };
+        let mut rule = match serde_yml::from_str::<Option<AccessRule>>(&raw) {
+            Ok(rule) => rule.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!(
+                    "Invalid {}, allowing editors only: {}",
+                    candidate.display(),
+                    e
+                );
+                AccessRule {
+                    editors: true,
+                    ..Default::default()
+                }
+            }
+        };
+        let rel = handler::rel_path(www_root, &dir);
+        rule.dir_url = if rel.is_empty() {
+            String::from("/")
+        } else {
+            format!("/{}/", rel)
+        };
+        return Some(Arc::new(rule));
+    }
+    None
+}
+
+/// The rule covering `path` (a file or directory that need not exist), unless
+/// it is public.
+async fn restriction(state: &AppState, path: &Path) -> Option<Arc<AccessRule>> {
+    let root = &state.canonical_root;
+    // Requests for files that don't exist (clean URLs, feeds, 404s) are
+    // governed by their nearest existing directory.
+    let mut dir = path.to_path_buf();
+    while !tokio::fs::metadata(&dir).await.is_ok_and(|m| m.is_dir()) {
+        if !dir.pop() {
+            return None;
+        }
+    }
+    let dir = tokio::fs::canonicalize(&dir).await.ok()?;
+    let dir = if dir.starts_with(root) {
+        dir
+    } else {
+        root.clone()
+    };
+    let rule = dir_assets(state, &dir).await.access.clone()?;
+    (!rule.is_open()).then_some(rule)
+}
+
+/// The outcome of checking a request against the access rules.
+pub enum Access {
+    /// No rule applies.
+    Public,
+    /// A rule applies and the visitor satisfies it; the response must not be
+    /// cached by shared caches.
+    Granted,
+    /// The response to send instead: a login redirect, password form or 403.
+    Denied(Response),
+}
+
+/// Check a request for `fs_path` (at `uri_path`) against the nearest rule.
+pub async fn check(
+    state: &AppState,
+    headers: &HeaderMap,
+    fs_path: &Path,
+    uri_path: &str,
+) -> Access {
+    let Some(rule) = restriction(state, fs_path).await else {
+        return Access::Public;
+    };
+    let user = editor::session_user(state, headers).await;
+    if admits(state, headers, &rule, user.as_deref()).await {
+        return Access::Granted;
+    }
+
+    if rule.password.is_some() {
+        let page = password_page(state, &rule.dir_url, uri_path, None).await;
+        return Access::Denied(
+            (StatusCode::UNAUTHORIZED, Html(page.into_string())).into_response(),
+        );
+    }
+    if user.is_some() {
+        let message = "Your account does not have access to this section.";
+        let page = page(state, "403 Forbidden", html! { p { (message) } }).await;
+        return Access::Denied((StatusCode::FORBIDDEN, Html(page.into_string())).into_response());
+    }
+    let next = utf8_percent_encode(uri_path, PATH).to_string();
+    let login = format!(
+        "/edit/login?next={}",
+        form_urlencoded::byte_serialize(next.as_bytes()).collect::<String>()
+    );
+    Access::Denied(Redirect::to(&login).into_response())
+}
+
+/// Whether the visitor — logged in as `user`, with the request's password
+/// cookies — satisfies `rule`.
+async fn admits(
+    state: &AppState,
+    headers: &HeaderMap,
+    rule: &AccessRule,
+    user: Option<&str>,
+) -> bool {
+    if let Some(user) = user
+        && (rule.editors || rule.users.iter().any(|u| u == user))
+    {
+        return true;
+    }
+    match &rule.password {
+        Some(password) => {
+            let proof = pass_token(state, &rule.dir_url, password).await;
+            pass_cookies(headers).any(|v| v == proof)
+        }
+        None => false,
+    }
+}
+
+/// Whether the page or directory at clean URL `url` is readable by everyone.
+pub async fn is_public(state: &AppState, url: &str) -> bool {
+    let path = state.canonical_root.join(url.trim_start_matches('/'));
+    restriction(state, &path).await.is_none()
+}
+
+/// The site's published pages outside restricted sections, for site-wide
+/// listings. Cached along with `handler::load_site_pages`.
+pub async fn public_pages(state: &AppState) -> Arc<Vec<DirEntry>> {
+    if let Some(pages) = state.cache.public_pages() {
+        return pages;
+    }
+    let generation = state.cache.generation();
+    let pages = handler::load_site_pages(state).await;
+    let mut public = Vec::with_capacity(pages.len());
+    for page in pages.iter() {
+        if is_public(state, &page.url).await {
+            public.push(page.clone());
+        }
+    }
+    state.cache.insert_public_pages(public, generation)
+}
+
+/// Drop the subdirectories of a listing that the visitor may not open.
+pub async fn visible_entries(
+    state: &AppState,
+    headers: &HeaderMap,
+    entries: &[DirEntry],
+) -> Vec<DirEntry> {
+    let user = editor::session_user(state, headers).await;
+    let mut visible = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let path = state.canonical_root.join(entry.url.trim_start_matches('/'));
+        let allowed = !entry.is_dir
+            || match restriction(state, &path).await {
+                Some(rule) => admits(state, headers, &rule, user.as_deref()).await,
+                None => true,
+            };
+        if allowed {
+            visible.push(entry.clone());
+        }
+    }
+    visible
+}
+
+// ── Section passwords ─────────────────────────────────────────────────────────
+
+/// Cookie value proving the password for the section at `dir_url` was
+/// entered. Changing the password invalidates it.
+async fn pass_token(state: &AppState, dir_url: &str, password: &str) -> String {
+    let secret = state.access_secret.get(&state.db).await;
+    sha256_hex(&format!("{}\0{}\0{}", secret, dir_url, password))
+}
+
+fn pass_cookies(headers: &HeaderMap) -> impl Iterator<Item = &str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|part| part.trim().strip_prefix(PASS_COOKIE)?.strip_prefix('='))
+}
+
+#[derive(Deserialize)]
+pub struct PasswordForm {
+    /// The page the visitor asked for.
+    next: String,
+    password: String,
+}
+
+/// `POST /_access`: check a section password and remember it in a cookie
+/// scoped to the section.
+pub async fn post_password(
+    State(state): State<AppState>,
+    Form(form): Form<PasswordForm>,
+) -> Response {
+    // Only ever send the visitor back to a page on this site.
+    let Some(location) = editor::safe_next(&form.next).and_then(|n| HeaderValue::from_str(n).ok())
+    else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    let decoded = percent_encoding::percent_decode_str(&form.next).decode_utf8_lossy();
+    if decoded.split('/').any(|seg| seg == "..") {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    let back = (
+        StatusCode::SEE_OTHER,
+        [(header::LOCATION, location.clone())],
+    );
+    let path = state.canonical_root.join(decoded.trim_start_matches('/'));
+    let Some(rule) = restriction(&state, &path).await else {
+        return back.into_response();
+    };
+    let Some(password) = rule.password.as_deref() else {
+        return back.into_response();
+    };
+    if form.password != password {
+        tracing::info!("Wrong password for {}", rule.dir_url);
+        let page = password_page(&state, &rule.dir_url, &decoded, Some("Wrong password.")).await;
+        return (StatusCode::UNAUTHORIZED, Html(page.into_string())).into_response();
+    }
+
+    let cookie = format!(
+        "{}={}; Path={}; HttpOnly; SameSite=Lax; Max-Age={}; Secure",
+        PASS_COOKIE,
+        pass_token(&state, &rule.dir_url, password).await,
+        utf8_percent_encode(&rule.dir_url, PATH),
+        PASS_TTL_SECS
+    );
+    let Ok(cookie) = HeaderValue::from_str(&cookie) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    (
+        StatusCode::SEE_OTHER,
+        [(header::SET_COOKIE, cookie), (header::LOCATION, location)],
+    )
+        .into_response()
+}
+
+// ── Pages ─────────────────────────────────────────────────────────────────────
+
+async fn password_page(state: &AppState, dir_url: &str, next: &str, error: Option<&str>) -> Markup {
+    let body = html! {
+        p { "This section (" code { (dir_url) } ") is password protected." }
+        form method="post" action="/_access" {
+            @if let Some(err) = error {
+                p class="error" { (err) }
+            }
+            input type="hidden" name="next" value=(utf8_percent_encode(next, PATH).to_string());
+            label for="password" { "Password" }
+            " "
+            input type="password" id="password" name="password" autofocus required;
+            " "
+            button type="submit" { "Continue" }
+        }
+    };
+    page(state, "401 Unauthorized", body).await
+}
+
+async fn page(state: &AppState, title: &str, body: Markup) -> Markup {
+    let css = handler::root_css(state).await;
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                meta name="robots" content="noindex";
+                title { (title) }
+                @if let Some(css) = css {
+                    link rel="stylesheet" href=(css);
+                }
+            }
+            body {
+                main {
+                    h1 { (title) }
+                    (body)
+                }
+            }
+        }
+    }
+}
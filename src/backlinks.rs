@@ -0,0 +1,397 @@
+//! Link graph between pages, for "Linked from" sections.
+//!
+//! Every markdown file's outgoing links — markdown links and reference
+//! definitions that point inside the site (relative, root-relative, or under
+//! `--base-url`) and resolved wiki links — are stored in SQLite as clean URL
+//! paths. Like the search index, the graph is brought up to date on startup
+//! and then kept current from the render cache's change stream.
+
+use anyhow::Result;
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use markdown::mdast::Node;
+use tokio::{sync::broadcast::error::RecvError, task::JoinSet};
+
+use crate::{
+    access,
+    db::{self, LinkSource},
+    front_matter::{self, FrontMatter},
+    handler,
+    state::AppState,
+    template::DirEntry,
+    wiki,
+};
+
+/// Site setting that shows backlinks on every page unless its front matter
+/// says otherwise.
+const SETTING: &str = "backlinks";
+
+/// The site setting once read, shared by all requests through `AppState`.
+#[derive(Default)]
+pub struct SiteDefault {
+    value: Mutex<Option<bool>>,
+}
+
+impl SiteDefault {
+    fn get(&self) -> Option<bool> {
+        *self.value.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set(&self, on: bool) {
+        *self.value.lock().unwrap_or_else(|e| e.into_inner()) = Some(on);
+    }
+}
+
+// ── Indexing ──────────────────────────────────────────────────────────────────
+
+/// Bring the link graph up to date and keep it current from the file
+/// watcher.
+pub fn spawn_indexer(state: &AppState, tasks: &mut JoinSet<()>) {
+    let state = state.clone();
+    // Subscribe before the initial scan so no change slips through the gap.
+    let mut changes = state.cache.subscribe();
+
+    tasks.spawn(async move {
+        reindex_all(&state).await;
+        loop {
+            match changes.recv().await {
+                Ok(path) => update_path(&state, &path).await,
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("Link indexer missed {} changes, rescanning", n);
+                    reindex_all(&state).await;
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Reindex `path` (a file or directory under the canonical root, which may no
+/// longer exist) after a write.
+pub async fn reindex(state: &AppState, path: &Path) {
+    update_path(state, path).await;
+}
+
+/// Index every markdown file whose mtime differs from the recorded one and
+/// drop files that no longer exist.
+async fn reindex_all(state: &AppState) {
+    let root = &state.canonical_root;
+    let indexed = match db::link_source_mtimes(&state.db).await {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::error!("Cannot read link graph: {e:#}");
+            return;
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let mut updated = 0usize;
+    for (path, modified) in handler::walk_markdown(root).await {
+        let rel = handler::rel_path(root, &path);
+        if indexed.get(&rel) != Some(&mtime(modified)) {
+            index_file(state, &path).await;
+            updated += 1;
+        }
+        seen.insert(rel);
+    }
+
+    let mut removed = 0usize;
+    for rel in indexed.keys().filter(|rel| !seen.contains(*rel)) {
+        if let Err(e) = db::delete_link_sources(&state.db, rel).await {
+            tracing::warn!("Cannot remove {} from link graph: {e:#}", rel);
+        }
+        removed += 1;
+    }
+
+    if updated > 0 || removed > 0 {
+        refresh_wiki_sources(state).await;
+        tracing::info!("Link graph: {} updated, {} removed", updated, removed);
+    }
+}
+
+async fn update_path(state: &AppState, path: &Path) {
+    let root = &state.canonical_root;
+    if path == root {
+        reindex_all(state).await;
+        return;
+    }
+    let Ok(rel) = path.strip_prefix(root) else {
+        return;
+    };
+    if rel
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    {
+        return;
+    }
+    let rel = handler::rel_path(root, path);
+    let known = db::link_source_mtimes(&state.db)
+        .await
+        .map(|m| m.contains_key(&rel))
+        .unwrap_or(false);
+
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(meta) if meta.is_dir() => {
+            for (file, _) in handler::walk_markdown(path).await {
+                index_file(state, &file).await;
+            }
+            refresh_wiki_sources(state).await;
+        }
+        Ok(meta) if meta.is_file() => {
+            if path.extension().is_some_and(|e| e == "md") {
+                index_file(state, path).await;
+                // A new page may be the target of existing wiki links.
+                if !known {
+                    refresh_wiki_sources(state).await;
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(_) => {
+            // Gone: drop the page, or everything under a removed directory.
+            if let Err(e) = db::delete_link_sources(&state.db, &rel).await {
+                tracing::warn!("Cannot remove {} from link graph: {e:#}", rel);
+            }
+            refresh_wiki_sources(state).await;
+        }
+    }
+}
+
+/// Re-resolve the wiki links of every page that has them, after pages were
+/// added or removed.
+async fn refresh_wiki_sources(state: &AppState) {
+    let sources = match db::wiki_link_sources(&state.db).await {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!("Cannot list wiki link sources: {e:#}");
+            return;
+        }
+    };
+    for rel in sources {
+        let path = state.canonical_root.join(&rel);
+        if path.is_file() {
+            index_file(state, &path).await;
+        }
+    }
+}
+
+/// Record the outgoing links of one markdown file.
+async fn index_file(state: &AppState, path: &Path) {
+    let root = &state.canonical_root;
+    let rel = handler::rel_path(root, path);
+    let raw = match tokio::fs::read_to_string(path).await {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!("Cannot index links of {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let modified = tokio::fs::metadata(path)
+        .await
+        .and_then(|m| m.modified())
+        .ok();
+
+    let doc = front_matter::parse(&raw);
+    let (_, url) = handler::page_url(&rel);
+    let mut targets = markdown_targets(&doc.content, &url, state.base_url.as_deref(), path);
+
+    let wiki = wiki::has_links(&doc.content);
+    if wiki {
+        let pages = handler::load_site_pages(state).await;
+        let resolver = wiki::Resolver::new(&pages, &url);
+        for (_, target) in wiki::links_in_file(&raw, path) {
+            if let Some(target) = resolver.resolve(&target)
+                && let Some(target) = normalize(&target, &url, None)
+            {
+                targets.push(target);
+            }
+        }
+    }
+    targets.retain(|t| *t != link_key(&url));
+    targets.sort();
+    targets.dedup();
+
+    // Hidden pages get mtime 0 so startup rescans them: a scheduled page may
+    // have gone live while the server was down.
+    let draft = doc.front_matter.is_hidden();
+    let source = LinkSource {
+        path: rel,
+        mtime: if draft { 0 } else { mtime(modified) },
+        draft,
+        wiki,
+    };
+    if let Err(e) = db::replace_page_links(&state.db, &source, &targets).await {
+        tracing::warn!("Cannot index links of {}: {e:#}", path.display());
+    }
+}
+
+/// Internal link targets of markdown links and definitions in `content`.
+fn markdown_targets(
+    content: &str,
+    page_url: &str,
+    base_url: Option<&str>,
+    path: &Path,
+) -> Vec<String> {
+    let mut options = markdown::ParseOptions::gfm();
+    options.constructs.frontmatter = false;
+    let tree = match markdown::to_mdast(content, &options) {
+        Ok(tree) => tree,
+        Err(e) => {
+            tracing::warn!("Cannot parse {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut hrefs = Vec::new();
+    collect_hrefs(&tree, &mut hrefs);
+    hrefs
+        .into_iter()
+        .filter_map(|href| normalize(&href, page_url, base_url))
+        .collect()
+}
+
+fn collect_hrefs(node: &Node, hrefs: &mut Vec<String>) {
+    match node {
+        Node::Link(link) => hrefs.push(link.url.clone()),
+        Node::Definition(def) => hrefs.push(def.url.clone()),
+        _ => {}
+    }
+    for child in node.children().into_iter().flatten() {
+        collect_hrefs(child, hrefs);
+    }
+}
+
+/// Turn an `href` on the page at `page_url` into the key of the page it
+/// points at, or `None` for external and same-page links.
+fn normalize(href: &str, page_url: &str, base_url: Option<&str>) -> Option<String> {
+    let href = href.trim();
+    let href = &href[..href.find(['#', '?']).unwrap_or(href.len())];
+
+    let base = base_url
+        .map(|b| b.trim_end_matches('/'))
+        .filter(|b| !b.is_empty());
+    let path = match base.and_then(|b| href.strip_prefix(b)) {
+        Some("") => "/".to_string(),
+        Some(rest) if rest.starts_with('/') => rest.to_string(),
+        Some(_) => return None,
+        None if href.is_empty() || href.starts_with("//") => return None,
+        None if href.starts_with('/') => href.to_string(),
+        // Any scheme (`https:`, `mailto:`, …) before the first `/`.
+        None if href.split('/').next().is_some_and(|s| s.contains(':')) => return None,
+        None => {
+            let dir = &page_url[..page_url.rfind('/').map_or(0, |i| i + 1)];
+            format!("{}{}", dir, href)
+        }
+    };
+
+    let path = percent_encoding::percent_decode_str(&path)
+        .decode_utf8()
+        .ok()?
+        .into_owned();
+    let mut segments: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            part => segments.push(part),
+        }
+    }
+    // `/a/b.md` and `/a/index` (or `index.html`) are served like `/a/b` and `/a/`.
+    if let Some(last) = segments.last_mut() {
+        *last = last.strip_suffix(".md").unwrap_or(last);
+    }
+    if matches!(segments.last(), Some(&"index") | Some(&"index.html")) {
+        segments.pop();
+    }
+    Some(link_key(&format!("/{}", segments.join("/"))))
+}
+
+/// Links to `/a/` and `/a` reach the same page; store both as `/a`.
+fn link_key(url: &str) -> String {
+    match url.trim_end_matches('/') {
+        "" => String::from("/"),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+fn mtime(modified: Option<SystemTime>) -> i64 {
+    modified
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+// ── Querying ──────────────────────────────────────────────────────────────────
+
+/// Whether the page should list its backlinks: its `backlinks` front matter
+/// field, or else the site setting.
+pub async fn enabled(state: &AppState, fm: &FrontMatter) -> bool {
+    match fm.backlinks {
+        Some(show) => show,
+        None => site_default(state).await,
+    }
+}
+
+/// The site-wide setting, off unless turned on in the editor. Read from the
+/// settings on first use.
+pub async fn site_default(state: &AppState) -> bool {
+    if let Some(on) = state.backlinks.get() {
+        return on;
+    }
+    let on = match db::get_site_setting(&state.db, SETTING).await {
+        Ok(value) => value.is_some_and(|v| v == "true"),
+        Err(e) => {
+            // Not cached, so the next request tries again.
+            tracing::warn!("Cannot read backlinks setting: {e:#}");
+            return false;
+        }
+    };
+    state.backlinks.set(on);
+    on
+}
+
+/// Store and start applying the site-wide setting.
+pub async fn set_site_default(state: &AppState, on: bool) -> Result<()> {
+    let value = if on { "true" } else { "false" };
+    db::set_site_setting(&state.db, SETTING, value).await?;
+    state.backlinks.set(on);
+    Ok(())
+}
+
+/// Published pages outside restricted sections linking to the page at `url`.
+pub async fn linked_from(state: &AppState, url: &str) -> Vec<DirEntry> {
+    let sources = match db::backlinks(&state.db, &link_key(url), false).await {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!("Cannot read backlinks of {}: {e:#}", url);
+            return Vec::new();
+        }
+    };
+    let pages = handler::load_site_pages(state).await;
+    let mut linked = Vec::with_capacity(sources.len());
+    for rel in &sources {
+        let (_, source_url) = handler::page_url(rel);
+        if let Some(page) = pages.iter().find(|p| p.url == source_url)
+            && access::is_public(state, &page.url).await
+        {
+            linked.push(page.clone());
+        }
+    }
+    linked
+}
+
+/// Files (relative paths, drafts included) linking to the page at `url`, for
+/// the editor.
+pub async fn linking_files(state: &AppState, url: &str) -> Vec<String> {
+    db::backlinks(&state.db, &link_key(url), true)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Cannot read backlinks of {}: {e:#}", url);
+            Vec::new()
+        })
+}
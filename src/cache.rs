@@ -0,0 +1,325 @@
+//! In-memory cache of rendered pages, directory listings and per-directory
+//! asset lookups, invalidated by a filesystem watcher on the www root.
+//!
+//! When the watcher cannot be started (e.g. the inotify watch limit is
+//! exhausted) the cache stays disabled and every request reads from disk, so
+//! edits are never served stale.
+//!
+//! Changed paths are also broadcast to background consumers via `subscribe`.
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, RwLock, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+use tokio::sync::broadcast;
+
+use crate::{access::{self, AccessRule}, front_matter::FrontMatter, images, layout, template::DirEntry, toc::Heading};
+
+/// Capacity of the change broadcast. Subscribers that fall further behind
+/// than this see `RecvError::Lagged` and should rescan.
+const CHANGE_CHANNEL_CAPACITY: usize = 1024;
+
+/// Listing entries keyed by (canonical directory, URL prefix).
+type ListingMap = HashMap<(PathBuf, String), Arc<Vec<DirEntry>>>;
+
+/// A markdown file after front matter parsing, inference and rendering.
+pub struct CachedPage {
+    pub front_matter: FrontMatter,
+    pub html: String,
+    /// Headings in `html`, for the table of contents.
+    pub headings: Vec<Heading>,
+    /// The source has wiki links, whose targets depend on the rest of the
+    /// tree.
+    pub wiki_links: bool,
+    /// The HTML carries the dimensions of local images.
+    pub local_images: bool,
+    /// Modification time observed when the file was read.
+    pub modified: Option<SystemTime>,
+}
+
+/// Results of the ancestor walks in `css.rs` and `layout.rs` for a single
+/// directory.
+pub struct DirAssets {
+    pub css: Option<String>,
+    pub meta_image: Option<String>,
+    /// Nearest `_layout.html`.
+    pub layout: Option<PathBuf>,
+    /// Nearest `_listing.html`.
+    pub listing_template: Option<PathBuf>,
+    /// Nearest `_access.yml`.
+    pub access: Option<Arc<AccessRule>>,
+}
+
+pub struct RenderCache {
+    /// Set once the watcher is running; nothing is cached before that.
+    enabled: bool,
+    /// Bumped on every invalidation. Inserts carry the generation observed
+    /// before reading from disk and are discarded if it has moved since, so a
+    /// read racing with an edit can never re-insert stale content.
+    generation: AtomicU64,
+    /// Keyed by canonical file path.
+    pages: RwLock<HashMap<PathBuf, Arc<CachedPage>>>,
+    /// Sorted listing entries.
+    listings: RwLock<ListingMap>,
+    /// Every published page in the tree; dropped on any change.
+    site_pages: RwLock<Option<Arc<Vec<DirEntry>>>>,
+    /// `site_pages` outside restricted sections, dropped along with it.
+    public_pages: RwLock<Option<Arc<Vec<DirEntry>>>>,
+    /// Keyed by canonical directory.
+    assets: RwLock<HashMap<PathBuf, Arc<DirAssets>>>,
+    /// Every path reported by the watcher, for background consumers such as
+    /// the search indexer.
+    changes: broadcast::Sender<PathBuf>,
+    /// The watched directory; broadcast as the changed path when the watcher
+    /// asks for a full rescan.
+    root: PathBuf,
+    /// Kept alive for as long as the cache is; dropping it stops the watch.
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl RenderCache {
+    /// Create a cache and start watching `root` recursively. Falls back to a
+    /// disabled (pass-through) cache if the watcher cannot be started.
+    pub fn new(root: &Path) -> Arc<Self> {
+        let cache = Arc::new_cyclic(|weak: &Weak<RenderCache>| {
+            let weak = weak.clone();
+            let watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+                let Some(cache) = weak.upgrade() else {
+                    return;
+                };
+                match res {
+                    Ok(event) => cache.handle_event(&event),
+                    Err(e) => {
+                        tracing::warn!("File watcher error, clearing render cache: {}", e);
+                        cache.rescan();
+                    }
+                }
+            });
+
+            let watcher = watcher.and_then(|mut w| {
+                w.watch(root, RecursiveMode::Recursive)?;
+                Ok(w)
+            });
+
+            match watcher {
+                Ok(w) => RenderCache::with_watcher(root, Some(w)),
+                Err(e) => {
+                    tracing::warn!(
+                        "Cannot watch {} — render cache disabled: {}",
+                        root.display(),
+                        e
+                    );
+                    RenderCache::with_watcher(root, None)
+                }
+            }
+        });
+
+        if cache.enabled {
+            tracing::info!("Render cache enabled, watching {}", root.display());
+        }
+        cache
+    }
+
+    fn with_watcher(root: &Path, watcher: Option<RecommendedWatcher>) -> Self {
+        RenderCache {
+            root: root.to_path_buf(),
+            enabled: watcher.is_some(),
+            generation: AtomicU64::new(0),
+            pages: RwLock::default(),
+            listings: RwLock::default(),
+            site_pages: RwLock::default(),
+            public_pages: RwLock::default(),
+            assets: RwLock::default(),
+            changes: broadcast::channel(CHANGE_CHANNEL_CAPACITY).0,
+            _watcher: watcher,
+        }
+    }
+
+    /// Receive every path the watcher reports as changed. The stream ends
+    /// when the cache (and its watcher) is dropped.
+    pub fn subscribe(&self) -> broadcast::Receiver<PathBuf> {
+        self.changes.subscribe()
+    }
+
+    /// Current invalidation generation; capture before reading from disk.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn page(&self, path: &Path) -> Option<Arc<CachedPage>> {
+        read(&self.pages).get(path).cloned()
+    }
+
+    pub fn insert_page(&self, path: PathBuf, page: CachedPage, generation: u64) -> Arc<CachedPage> {
+        let page = Arc::new(page);
+        // Check under the lock so an invalidation can't slip in between.
+        let mut map = write(&self.pages);
+        if self.is_current(generation) {
+            map.insert(path, Arc::clone(&page));
+        }
+        page
+    }
+
+    pub fn listing(&self, dir: &Path, url_prefix: &str) -> Option<Arc<Vec<DirEntry>>> {
+        read(&self.listings)
+            .get(&(dir.to_path_buf(), url_prefix.to_string()))
+            .cloned()
+    }
+
+    pub fn insert_listing(
+        &self,
+        dir: PathBuf,
+        url_prefix: String,
+        entries: Vec<DirEntry>,
+        generation: u64,
+    ) -> Arc<Vec<DirEntry>> {
+        let entries = Arc::new(entries);
+        let mut map = write(&self.listings);
+        if self.is_current(generation) {
+            map.insert((dir, url_prefix), Arc::clone(&entries));
+        }
+        entries
+    }
+
+    pub fn site_pages(&self) -> Option<Arc<Vec<DirEntry>>> {
+        read(&self.site_pages).clone()
+    }
+
+    pub fn insert_site_pages(&self, pages: Vec<DirEntry>, generation: u64) -> Arc<Vec<DirEntry>> {
+        let pages = Arc::new(pages);
+        let mut slot = write(&self.site_pages);
+        if self.is_current(generation) {
+            *slot = Some(Arc::clone(&pages));
+        }
+        pages
+    }
+
+    pub fn public_pages(&self) -> Option<Arc<Vec<DirEntry>>> {
+        read(&self.public_pages).clone()
+    }
+
+    pub fn insert_public_pages(&self, pages: Vec<DirEntry>, generation: u64) -> Arc<Vec<DirEntry>> {
+        let pages = Arc::new(pages);
+        let mut slot = write(&self.public_pages);
+        if self.is_current(generation) {
+            *slot = Some(Arc::clone(&pages));
+        }
+        pages
+    }
+
+    pub fn assets(&self, dir: &Path) -> Option<Arc<DirAssets>> {
+        read(&self.assets).get(dir).cloned()
+    }
+
+    pub fn insert_assets(
+        &self,
+        dir: PathBuf,
+        assets: DirAssets,
+        generation: u64,
+    ) -> Arc<DirAssets> {
+        let assets = Arc::new(assets);
+        let mut map = write(&self.assets);
+        if self.is_current(generation) {
+            map.insert(dir, Arc::clone(&assets));
+        }
+        assets
+    }
+
+    /// Drop every cached entry.
+    pub fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        write(&self.pages).clear();
+        write(&self.listings).clear();
+        write(&self.site_pages).take();
+        write(&self.public_pages).take();
+        write(&self.assets).clear();
+    }
+
+    /// Invalidate `path` and notify subscribers as if the watcher had
+    /// reported it, for changes that aren't file writes (a scheduled page
+    /// going live).
+    pub fn touch(&self, path: &Path) {
+        self.invalidate(path);
+        let _ = self.changes.send(path.to_path_buf());
+    }
+
+    /// Events may have been lost: drop everything and tell subscribers the
+    /// whole tree changed.
+    fn rescan(&self) {
+        self.clear();
+        let _ = self.changes.send(self.root.clone());
+    }
+
+    fn is_current(&self, generation: u64) -> bool {
+        self.enabled && self.generation() == generation
+    }
+
+    fn handle_event(&self, event: &Event) {
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        if event.need_rescan() {
+            self.rescan();
+            return;
+        }
+        for path in &event.paths {
+            self.invalidate(path);
+            // No subscribers is not an error.
+            let _ = self.changes.send(path.clone());
+        }
+    }
+
+    /// Invalidate everything that may depend on `path`, which may be a file or
+    /// a directory and may no longer exist.
+    fn invalidate(&self, path: &Path) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        // Adding, removing or retitling a page (or moving a directory) can
+        // change where any wiki link points.
+        let pages_changed = path.extension().is_none_or(|e| e == "md");
+        // A replaced image can have other dimensions.
+        let image_changed = images::is_resizable(path);
+        write(&self.pages).retain(|k, page| {
+            !(k.starts_with(path)
+                || (pages_changed && page.wiki_links)
+                || (image_changed && page.local_images))
+        });
+        write(&self.site_pages).take();
+        write(&self.public_pages).take();
+
+        // A listing depends on its direct children and on each child
+        // directory's `index.md`, so changes two levels down still count.
+        let parent = path.parent();
+        let grandparent = parent.and_then(Path::parent);
+        write(&self.listings).retain(|(dir, _), _| {
+            !dir.starts_with(path)
+                && Some(dir.as_path()) != parent
+                && Some(dir.as_path()) != grandparent
+        });
+
+        // style.css / meta.* / templates / access rules cascade to every
+        // descendant directory.
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let is_template = name == layout::PAGE_TEMPLATE || name == layout::LISTING_TEMPLATE;
+        if name == "style.css" || name.starts_with("meta.") || is_template || name == access::ACCESS_FILE {
+            write(&self.assets).clear();
+        } else {
+            write(&self.assets).retain(|dir, _| !dir.starts_with(path));
+        }
+    }
+}
+
+// A poisoned lock only means another thread panicked mid-update; the maps are
+// still structurally valid, so keep serving rather than propagate the panic.
+fn read<T>(lock: &RwLock<T>) -> std::sync::RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> std::sync::RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
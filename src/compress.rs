@@ -0,0 +1,378 @@
+//! Response compression.
+//!
+//! Text responses — pages, listings, feeds, CSS, JS, JSON — are compressed
+//! with brotli or gzip, whichever the client's `Accept-Encoding` prefers.
+//! Compressed bodies of responses with an `ETag` are kept in a bounded
+//! in-memory cache (`AppState::compressed`) keyed by path, tag and encoding,
+//! so a page that hasn't changed is only compressed once. A compressed
+//! response's `ETag` is made weak, since its bytes differ from the
+//! uncompressed one's; `If-None-Match` compares weakly, so revalidation
+//! still works.
+//!
+//! Static files can also be shipped precompressed: `serve_static` sends
+//! `style.css.br` or `style.css.gz` in place of `style.css` when a client
+//! accepts it, and such responses are passed through untouched.
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::state::AppState;
+
+/// Bodies smaller than this aren't worth compressing.
+const MIN_SIZE: u64 = 1024;
+
+/// Bodies larger than this are sent as they are rather than buffered.
+const MAX_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Total size of the compressed bodies kept.
+const CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+const BROTLI_QUALITY: u32 = 6;
+const BROTLI_WINDOW: u32 = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// In order of preference when the client rates them equally.
+    const ALL: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
+
+    fn token(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Extension of a precompressed sibling file.
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut out = Vec::with_capacity(data.len() / 4);
+                let mut writer =
+                    brotli::CompressorWriter::new(&mut out, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                writer.write_all(data)?;
+                drop(writer);
+                Ok(out)
+            }
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::with_capacity(data.len() / 4),
+                    flate2::Compression::default(),
+                );
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// The encoding out of `available` the request's `Accept-Encoding` rates
+/// highest, if it accepts any.
+pub fn negotiate(headers: &HeaderMap, available: &[Encoding]) -> Option<Encoding> {
+    let accept = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    let quality = |encoding: Encoding| {
+        let mut wildcard = None;
+        for item in accept.split(',') {
+            let mut parts = item.split(';').map(str::trim);
+            let name = parts.next().unwrap_or("");
+            let q = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if name.eq_ignore_ascii_case(encoding.token())
+                || (encoding == Encoding::Gzip && name.eq_ignore_ascii_case("x-gzip"))
+            {
+                return q;
+            }
+            if name == "*" {
+                wildcard = Some(q);
+            }
+        }
+        wildcard.unwrap_or(0.0)
+    };
+    Encoding::ALL
+        .into_iter()
+        .filter(|e| available.contains(e))
+        .map(|e| (e, quality(e)))
+        .filter(|(_, q)| *q > 0.0)
+        // `max_by` keeps the last of equals; reverse so brotli wins ties.
+        .rev()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(e, _)| e)
+}
+
+// ── Precompressed files ───────────────────────────────────────────────────────
+
+/// A precompressed sibling of a static file.
+pub struct Precompressed {
+    pub path: PathBuf,
+    pub encoding: Encoding,
+}
+
+impl Precompressed {
+    pub fn content_encoding(&self) -> &'static str {
+        self.encoding.token()
+    }
+}
+
+/// For the static file at the canonical `path`: whether it has precompressed
+/// siblings (`file.css.br`, `file.css.gz`), and the one to send for this
+/// request. Siblings that resolve outside the file's directory are ignored.
+pub async fn precompressed(headers: &HeaderMap, path: &Path) -> (bool, Option<Precompressed>) {
+    let Some(dir) = path.parent() else {
+        return (false, None);
+    };
+    let mut available = Vec::new();
+    for encoding in Encoding::ALL {
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(".");
+        sibling.push(encoding.extension());
+        let Ok(sibling) = tokio::fs::canonicalize(PathBuf::from(sibling)).await else {
+            continue;
+        };
+        if sibling.starts_with(dir)
+            && tokio::fs::metadata(&sibling)
+                .await
+                .is_ok_and(|m| m.is_file())
+        {
+            available.push((encoding, sibling));
+        }
+    }
+    let encodings: Vec<Encoding> = available.iter().map(|(e, _)| *e).collect();
+    let chosen = negotiate(headers, &encodings).and_then(|encoding| {
+        available
+            .into_iter()
+            .find(|(e, _)| *e == encoding)
+            .map(|(encoding, path)| Precompressed { path, encoding })
+    });
+    (!encodings.is_empty(), chosen)
+}
+
+// ── Middleware ────────────────────────────────────────────────────────────────
+
+type CacheKey = (String, String, Encoding);
+
+/// Compressed bodies, shared by all requests through `AppState`.
+#[derive(Default)]
+pub struct CompressedCache {
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    bodies: HashMap<CacheKey, Bytes>,
+    /// Insertion order, oldest first, for eviction.
+    order: VecDeque<CacheKey>,
+    bytes: usize,
+}
+
+impl CompressedCache {
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, key: &CacheKey) -> Option<Bytes> {
+        self.lock().bodies.get(key).cloned()
+    }
+
+    fn insert(&self, key: CacheKey, body: Bytes) {
+        self.lock().insert(key, body);
+    }
+}
+
+impl Entries {
+    fn insert(&mut self, key: CacheKey, body: Bytes) {
+        if body.len() > CACHE_BYTES / 8 || self.bodies.contains_key(&key) {
+            return;
+        }
+        while self.bytes + body.len() > CACHE_BYTES {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            if let Some(old) = self.bodies.remove(&oldest) {
+                self.bytes -= old.len();
+            }
+        }
+        self.bytes += body.len();
+        self.order.push_back(key.clone());
+        self.bodies.insert(key, body);
+    }
+}
+
+/// Compress text responses the client accepts compressed.
+pub async fn compress(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let encoding = negotiate(request.headers(), &Encoding::ALL);
+    let path = request.uri().path().to_string();
+    let mut response = next.run(request).await;
+
+    if !is_compressible(&response) {
+        return response;
+    }
+    add_vary(response.headers_mut());
+    let Some(encoding) = encoding else {
+        return response;
+    };
+    let etag = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|tag| !tag.starts_with("W/"))
+        .map(str::to_string);
+    let key = etag.as_ref().map(|tag| (path, tag.clone(), encoding));
+    let cache = &state.compressed;
+    let cached = key.as_ref().and_then(|key| cache.get(key));
+
+    if response.status() == StatusCode::NOT_MODIFIED {
+        // Repeat the tag of the compressed copy the client holds.
+        if cached.is_some() {
+            set_weak_etag(response.headers_mut(), etag);
+        }
+        return response;
+    }
+    if response.status() != StatusCode::OK
+        || response.headers().contains_key(header::CONTENT_ENCODING)
+        || response
+            .headers()
+            .get(header::CACHE_CONTROL)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("no-transform"))
+    {
+        return response;
+    }
+    let size = body_size(&response);
+    if !size.is_some_and(|size| (MIN_SIZE..=MAX_SIZE).contains(&size)) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let compressed = match cached {
+        Some(compressed) => compressed,
+        None => {
+            let data = match axum::body::to_bytes(body, MAX_SIZE as usize).await {
+                Ok(data) => data,
+                Err(e) => {
+                    // The body is lost; don't send what's left as the page.
+                    tracing::error!("Cannot read response body to compress: {e}");
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            };
+            let input = data.clone();
+            let result = tokio::task::spawn_blocking(move || encoding.compress(&input)).await;
+            let compressed = match result {
+                Ok(Ok(compressed)) => Bytes::from(compressed),
+                Ok(Err(e)) => {
+                    tracing::warn!("Cannot compress response: {e}");
+                    return Response::from_parts(parts, Body::from(data));
+                }
+                Err(e) => {
+                    tracing::error!("Compression task failed: {e}");
+                    return Response::from_parts(parts, Body::from(data));
+                }
+            };
+            if let Some(key) = key {
+                cache.insert(key, compressed.clone());
+            }
+            compressed
+        }
+    };
+
+    let headers = &mut parts.headers;
+    headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.token()),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+    // Ranges would refer to the uncompressed bytes.
+    headers.remove(header::ACCEPT_RANGES);
+    set_weak_etag(headers, etag);
+    Response::from_parts(parts, Body::from(compressed))
+}
+
+fn set_weak_etag(headers: &mut HeaderMap, etag: Option<String>) {
+    if let Some(weak) = etag.and_then(|tag| HeaderValue::from_str(&format!("W/{}", tag)).ok()) {
+        headers.insert(header::ETAG, weak);
+    }
+}
+
+/// Whether the response is text worth compressing. Not-modified responses
+/// count, so they carry the same `Vary` as the full ones.
+fn is_compressible(response: &Response) -> bool {
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return true;
+    }
+    let Some(content_type) = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(
+            mime.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "image/x-icon"
+                | "font/ttf"
+                | "font/otf"
+                | "application/vnd.ms-fontobject"
+        )
+}
+
+/// The body's length, from `Content-Length` or the body itself.
+fn body_size(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+        .or_else(|| response.body().size_hint().exact())
+}
+
+fn add_vary(headers: &mut HeaderMap) {
+    let present = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case("accept-encoding") || v.trim() == "*");
+    if !present {
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
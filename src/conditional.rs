@@ -0,0 +1,137 @@
+//! HTTP validators (`ETag` / `Last-Modified`) and conditional GET handling.
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::AppError;
+
+/// Strong ETag for an in-memory body: a truncated SHA-256 of its bytes.
+pub fn content_etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// ETag for a file on disk derived from its modification time and size, so
+/// static files can be validated without reading them.
+pub fn file_etag(modified: Option<SystemTime>, len: u64) -> String {
+    let nanos = modified
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("\"{:x}-{:x}\"", nanos, len)
+}
+
+/// Format a timestamp as an IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`).
+pub fn http_date(t: SystemTime) -> String {
+    let dt: DateTime<Utc> = t.into();
+    dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Insert `ETag` and (when known) `Last-Modified` into response headers.
+pub fn set_validators(headers: &mut HeaderMap, etag: &str, last_modified: Option<SystemTime>) {
+    if let Ok(v) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, v);
+    }
+    if let Some(v) = last_modified.and_then(|t| HeaderValue::from_str(&http_date(t)).ok()) {
+        headers.insert(header::LAST_MODIFIED, v);
+    }
+}
+
+/// A 200 response with `body` as `content_type`, carrying an `ETag` of the
+/// body and, when known, `Last-Modified`.
+pub fn respond(
+    content_type: &str,
+    body: String,
+    modified: Option<SystemTime>,
+) -> Result<Response, AppError> {
+    let etag = content_etag(body.as_bytes());
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    set_validators(response.headers_mut(), &etag, modified);
+    Ok(response)
+}
+
+/// Evaluate `If-None-Match` / `If-Modified-Since` from the request against the
+/// validators on a successful (200 or 206) response, replacing it with an
+/// empty 304 when the client's copy is still current.
+///
+/// Per RFC 9110 §13.2.2, `If-Modified-Since` is ignored whenever
+/// `If-None-Match` is present.
+pub fn apply(req_headers: &HeaderMap, response: Response) -> Response {
+    if !matches!(response.status(), StatusCode::OK | StatusCode::PARTIAL_CONTENT) {
+        return response;
+    }
+
+    let etag = response.headers().get(header::ETAG);
+    let last_modified = response.headers().get(header::LAST_MODIFIED);
+
+    let not_modified = if let Some(inm) = req_headers.get(header::IF_NONE_MATCH) {
+        match (inm.to_str(), etag.and_then(|e| e.to_str().ok())) {
+            (Ok(inm), Some(etag)) => etag_matches(inm, etag),
+            _ => false,
+        }
+    } else if let Some(ims) = req_headers.get(header::IF_MODIFIED_SINCE) {
+        match (
+            ims.to_str().ok().and_then(parse_http_date),
+            last_modified.and_then(|v| v.to_str().ok()).and_then(parse_http_date),
+        ) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    } else {
+        false
+    };
+
+    if !not_modified {
+        return response;
+    }
+
+    let mut builder = Response::builder().status(StatusCode::NOT_MODIFIED);
+    for name in [header::ETAG, header::LAST_MODIFIED, header::CACHE_CONTROL, header::VARY] {
+        if let Some(v) = response.headers().get(&name) {
+            builder = builder.header(name, v.clone());
+        }
+    }
+    builder.body(Body::empty()).unwrap_or(response)
+}
+
+/// Whether the request's `If-Match` precondition (RFC 9110 §13.1.1) holds for
+/// a resource whose current ETag is `etag`, or which doesn't exist when
+/// `None`. Holds when the header is absent.
+pub fn if_match(req_headers: &HeaderMap, etag: Option<&str>) -> bool {
+    let Some(value) = req_headers.get(header::IF_MATCH) else {
+        return true;
+    };
+    let (Ok(value), Some(etag)) = (value.to_str(), etag) else {
+        return false;
+    };
+    // Strong comparison: weak tags never match.
+    value
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+/// Weak comparison of an `If-None-Match` list against our ETag.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let ours = etag.trim_start_matches("W/");
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == ours
+    })
+}
+
+fn parse_http_date(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(s.trim())
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
@@ -2,7 +2,7 @@ use std::path::{Path, PathBuf};
 
 /// Walk up the directory tree from `file_path` toward `www_root` and return
 /// the sequence of directories to check, starting closest to the file.
-fn ancestor_dirs(www_root: &Path, file_path: &Path) -> Vec<PathBuf> {
+pub(crate) fn ancestor_dirs(www_root: &Path, file_path: &Path) -> Vec<PathBuf> {
     let start: PathBuf = if file_path.is_dir() {
         file_path.to_path_buf()
     } else {
@@ -6,7 +6,7 @@ use argon2::{
 // rand_core 0.6 is what password-hash/argon2 depends on; must match that version.
 use rand_core::OsRng;
 use sqlx::{Row, SqlitePool, sqlite::SqliteConnectOptions};
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
 #[derive(Debug, Default, Clone)]
 pub struct RequestStats {
@@ -137,6 +137,95 @@ async fn init_schema(pool: &SqlitePool) -> Result<()> {
     .await
     .context("Failed to seed media_dir setting")?;
 
+    // Full-text search over published pages. `path` is relative to the www
+    // root; `mtime` lets startup indexing skip unchanged files.
+    sqlx::query(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+            path    UNINDEXED,
+            url     UNINDEXED,
+            mtime   UNINDEXED,
+            title,
+            summary,
+            body,
+            tokenize = 'porter unicode61'
+        )",
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create search_index table")?;
+
+    // Link graph: one row per (linking page, linked URL). `link_sources`
+    // records every indexed page, including those without links, so
+    // startup indexing can skip unchanged files.
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS link_sources (
+            path  TEXT    PRIMARY KEY,
+            mtime INTEGER NOT NULL,
+            draft INTEGER NOT NULL DEFAULT 0,
+            wiki  INTEGER NOT NULL DEFAULT 0
+        )",
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create link_sources table")?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS page_links (
+            source TEXT NOT NULL,
+            target TEXT NOT NULL,
+            PRIMARY KEY (source, target)
+        )",
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create page_links table")?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_page_links_target ON page_links(target)")
+        .execute(pool)
+        .await
+        .context("Failed to create page_links index")?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS site_settings (
+            key   TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create site_settings table")?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS preview_links (
+            id         INTEGER PRIMARY KEY AUTOINCREMENT,
+            path       TEXT    NOT NULL,
+            expires_at INTEGER NOT NULL,
+            created_at TEXT    NOT NULL DEFAULT (datetime('now'))
+        )",
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create preview_links table")?;
+
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS revisions (
+            id         INTEGER PRIMARY KEY AUTOINCREMENT,
+            path       TEXT    NOT NULL,
+            content    TEXT    NOT NULL,
+            author     TEXT    NOT NULL,
+            source     TEXT    NOT NULL,
+            created_at TEXT    NOT NULL DEFAULT (datetime('now'))
+        )",
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create revisions table")?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_revisions_path ON revisions(path, id)")
+        .execute(pool)
+        .await
+        .context("Failed to create revisions index")?;
+
     Ok(())
 }
 
@@ -366,6 +455,7 @@ pub struct MicropubToken {
 #[derive(Debug, Clone)]
 pub struct TokenRecord {
     pub id: i64,
+    pub name: String,
     pub scope: String,
 }
 
@@ -375,7 +465,7 @@ pub async fn verify_micropub_token(pool: &SqlitePool, token_hash: &str) -> Resul
     let row = sqlx::query(
         "UPDATE micropub_tokens SET last_used = datetime('now')
          WHERE token_hash = ?
-         RETURNING id, scope",
+         RETURNING id, name, scope",
     )
     .bind(token_hash)
     .fetch_optional(pool)
@@ -384,6 +474,7 @@ pub async fn verify_micropub_token(pool: &SqlitePool, token_hash: &str) -> Resul
 
     Ok(row.map(|r| TokenRecord {
         id: r.get::<i64, _>("id"),
+        name: r.get::<String, _>("name"),
         scope: r.get::<String, _>("scope"),
     }))
 }
@@ -465,6 +556,117 @@ pub async fn set_micropub_setting(pool: &SqlitePool, key: &str, value: &str) ->
     Ok(())
 }
 
+// ── Search index ──────────────────────────────────────────────────────────────
+
+/// A page as stored in the full-text index.
+#[derive(Debug, Clone)]
+pub struct SearchDoc {
+    pub path: String,
+    pub url: String,
+    pub mtime: i64,
+    pub title: String,
+    pub summary: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub url: String,
+    pub title: String,
+    pub summary: String,
+    /// Plain-text excerpt; matched terms are wrapped in the `mark` delimiters
+    /// passed to `search`.
+    pub snippet: String,
+}
+
+/// Map of indexed path → mtime, used to skip unchanged files when reindexing.
+pub async fn search_indexed_mtimes(pool: &SqlitePool) -> Result<HashMap<String, i64>> {
+    let rows = sqlx::query("SELECT path, mtime FROM search_index")
+        .fetch_all(pool)
+        .await
+        .context("Failed to list search index")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.get::<String, _>("path"), r.get::<i64, _>("mtime")))
+        .collect())
+}
+
+/// Insert or replace the indexed copy of a page.
+pub async fn upsert_search_doc(pool: &SqlitePool, doc: &SearchDoc) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM search_index WHERE path = ?")
+        .bind(&doc.path)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete stale search entry")?;
+    sqlx::query(
+        "INSERT INTO search_index (path, url, mtime, title, summary, body) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&doc.path)
+    .bind(&doc.url)
+    .bind(doc.mtime)
+    .bind(&doc.title)
+    .bind(&doc.summary)
+    .bind(&doc.body)
+    .execute(&mut *tx)
+    .await
+    .context("Failed to insert search entry")?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Remove `path` and, if it was a directory, every page beneath it.
+pub async fn delete_search_docs(pool: &SqlitePool, path: &str) -> Result<()> {
+    let prefix = format!("{}/", path.trim_end_matches('/'));
+    sqlx::query("DELETE FROM search_index WHERE path = ? OR substr(path, 1, length(?)) = ?")
+        .bind(path)
+        .bind(&prefix)
+        .bind(&prefix)
+        .execute(pool)
+        .await
+        .context("Failed to delete search entries")?;
+    Ok(())
+}
+
+/// Run an FTS5 `MATCH` query and return ranked hits. Title matches weigh
+/// more than summary matches, which weigh more than body matches.
+pub async fn search(
+    pool: &SqlitePool,
+    fts_query: &str,
+    mark: (&str, &str),
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<SearchHit>> {
+    let rows = sqlx::query(
+        "SELECT url, title, summary,
+                snippet(search_index, 5, ?, ?, '…', 24) AS snippet,
+                bm25(search_index, 0.0, 0.0, 0.0, 10.0, 4.0, 1.0) AS rank
+         FROM search_index
+         WHERE search_index MATCH ?
+         ORDER BY rank
+         LIMIT ? OFFSET ?",
+    )
+    .bind(mark.0)
+    .bind(mark.1)
+    .bind(fts_query)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await
+    .context("Search query failed")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| SearchHit {
+            url: r.get::<String, _>("url"),
+            title: r.get::<String, _>("title"),
+            summary: r.get::<String, _>("summary"),
+            snippet: r.get::<String, _>("snippet"),
+        })
+        .collect())
+}
+
 /// Verify a username/password pair against the database.
 /// Returns `false` on any error or if credentials are wrong.
 ///
@@ -497,3 +699,392 @@ pub async fn verify_user(pool: &SqlitePool, username: &str, password: &str) -> b
         .verify_password(password.as_bytes(), &parsed)
         .is_ok()
 }
+
+// ── Link graph ────────────────────────────────────────────────────────────────
+
+/// A page as recorded in the link graph.
+pub struct LinkSource {
+    /// Path relative to the www root.
+    pub path: String,
+    pub mtime: i64,
+    /// Not public: a draft, or scheduled for later.
+    pub draft: bool,
+    /// The page has wiki links, which must be re-resolved when pages are
+    /// added or removed.
+    pub wiki: bool,
+}
+
+/// `path → mtime` for every page in the link graph.
+pub async fn link_source_mtimes(pool: &SqlitePool) -> Result<HashMap<String, i64>> {
+    let rows = sqlx::query("SELECT path, mtime FROM link_sources")
+        .fetch_all(pool)
+        .await
+        .context("Failed to list link sources")?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.get::<String, _>("path"), r.get::<i64, _>("mtime")))
+        .collect())
+}
+
+/// Pages with wiki links.
+pub async fn wiki_link_sources(pool: &SqlitePool) -> Result<Vec<String>> {
+    let rows = sqlx::query("SELECT path FROM link_sources WHERE wiki = 1")
+        .fetch_all(pool)
+        .await
+        .context("Failed to list wiki link sources")?;
+    Ok(rows.into_iter().map(|r| r.get("path")).collect())
+}
+
+/// Replace the outgoing links of `source`. `targets` are clean URL paths.
+pub async fn replace_page_links(
+    pool: &SqlitePool,
+    source: &LinkSource,
+    targets: &[String],
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO link_sources (path, mtime, draft, wiki) VALUES (?, ?, ?, ?)
+         ON CONFLICT(path) DO UPDATE SET
+            mtime = excluded.mtime, draft = excluded.draft, wiki = excluded.wiki",
+    )
+    .bind(&source.path)
+    .bind(source.mtime)
+    .bind(source.draft)
+    .bind(source.wiki)
+    .execute(&mut *tx)
+    .await
+    .context("Failed to record link source")?;
+    sqlx::query("DELETE FROM page_links WHERE source = ?")
+        .bind(&source.path)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete stale links")?;
+    for target in targets {
+        sqlx::query("INSERT OR IGNORE INTO page_links (source, target) VALUES (?, ?)")
+            .bind(&source.path)
+            .bind(target)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to insert link")?;
+    }
+    tx.commit().await.context("Failed to commit links")?;
+    Ok(())
+}
+
+/// Remove a page, or every page under a directory, from the link graph.
+pub async fn delete_link_sources(pool: &SqlitePool, path: &str) -> Result<()> {
+    let prefix = format!("{}/", path.trim_end_matches('/'));
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM page_links WHERE source = ? OR substr(source, 1, length(?)) = ?")
+        .bind(path)
+        .bind(&prefix)
+        .bind(&prefix)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete links")?;
+    sqlx::query("DELETE FROM link_sources WHERE path = ? OR substr(path, 1, length(?)) = ?")
+        .bind(path)
+        .bind(&prefix)
+        .bind(&prefix)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to delete link sources")?;
+    tx.commit().await.context("Failed to commit link deletion")?;
+    Ok(())
+}
+
+/// Paths of the pages linking to `url`, sorted. Drafts are left out unless
+/// `include_drafts`.
+pub async fn backlinks(pool: &SqlitePool, url: &str, include_drafts: bool) -> Result<Vec<String>> {
+    let rows = sqlx::query(
+        "SELECT l.source FROM page_links l
+         JOIN link_sources s ON s.path = l.source
+         WHERE l.target = ? AND (s.draft = 0 OR ?)
+         ORDER BY l.source",
+    )
+    .bind(url)
+    .bind(include_drafts)
+    .fetch_all(pool)
+    .await
+    .context("Failed to query backlinks")?;
+    Ok(rows.into_iter().map(|r| r.get("source")).collect())
+}
+
+// ── Site settings ─────────────────────────────────────────────────────────────
+
+/// Read a site setting; `None` when it has never been set.
+pub async fn get_site_setting(pool: &SqlitePool, key: &str) -> Result<Option<String>> {
+    let row = sqlx::query("SELECT value FROM site_settings WHERE key = ?")
+        .bind(key)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to read site setting")?;
+    Ok(row.map(|r| r.get("value")))
+}
+
+/// Upsert a site setting.
+pub async fn set_site_setting(pool: &SqlitePool, key: &str, value: &str) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO site_settings (key, value) VALUES (?, ?)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+    )
+    .bind(key)
+    .bind(value)
+    .execute(pool)
+    .await
+    .context("Failed to set site setting")?;
+    Ok(())
+}
+
+// ── Draft preview links ───────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct PreviewLinkRow {
+    pub id: i64,
+    /// Markdown file relative to the www root.
+    pub path: String,
+    /// Unix seconds.
+    pub expires_at: i64,
+}
+
+/// Record a preview link for `path` and return its id.
+pub async fn create_preview_link(pool: &SqlitePool, path: &str, expires_at: i64) -> Result<i64> {
+    let result = sqlx::query("INSERT INTO preview_links (path, expires_at) VALUES (?, ?)")
+        .bind(path)
+        .bind(expires_at)
+        .execute(pool)
+        .await
+        .context("Failed to create preview link")?;
+    Ok(result.last_insert_rowid())
+}
+
+/// Whether preview link `id` exists for `path` with this expiry (so it has
+/// not been revoked).
+pub async fn preview_link_exists(
+    pool: &SqlitePool,
+    id: i64,
+    path: &str,
+    expires_at: i64,
+) -> Result<bool> {
+    let row = sqlx::query("SELECT 1 FROM preview_links WHERE id = ? AND path = ? AND expires_at = ?")
+        .bind(id)
+        .bind(path)
+        .bind(expires_at)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to look up preview link")?;
+    Ok(row.is_some())
+}
+
+/// Preview links that expire after `now`, soonest first.
+pub async fn list_preview_links(pool: &SqlitePool, now: i64) -> Result<Vec<PreviewLinkRow>> {
+    let rows = sqlx::query(
+        "SELECT id, path, expires_at FROM preview_links
+         WHERE expires_at > ? ORDER BY expires_at",
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await
+    .context("Failed to list preview links")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| PreviewLinkRow {
+            id: r.get("id"),
+            path: r.get("path"),
+            expires_at: r.get("expires_at"),
+        })
+        .collect())
+}
+
+pub async fn delete_preview_link(pool: &SqlitePool, id: i64) -> Result<()> {
+    sqlx::query("DELETE FROM preview_links WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await
+        .context("Failed to delete preview link")?;
+    Ok(())
+}
+
+/// Drop links that expired before `now`.
+pub async fn delete_expired_preview_links(pool: &SqlitePool, now: i64) -> Result<()> {
+    sqlx::query("DELETE FROM preview_links WHERE expires_at <= ?")
+        .bind(now)
+        .execute(pool)
+        .await
+        .context("Failed to prune preview links")?;
+    Ok(())
+}
+
+// ── Revision history ──────────────────────────────────────────────────────────
+
+/// A stored revision without its content, for listings.
+#[derive(Debug, Clone)]
+pub struct RevisionMeta {
+    pub id: i64,
+    /// Who made the write that replaced this content.
+    pub author: String,
+    /// `editor` or `micropub`.
+    pub source: String,
+    pub created_at: String,
+    /// Content length in bytes.
+    pub size: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub id: i64,
+    pub path: String,
+    pub content: String,
+    pub author: String,
+    pub source: String,
+    pub created_at: String,
+}
+
+/// A file with stored revisions, for the history overview.
+#[derive(Debug, Clone)]
+pub struct RevisedFile {
+    pub path: String,
+    pub revisions: i64,
+    pub last_changed: String,
+    pub last_author: String,
+}
+
+/// Store `content` as a revision of `path`, then keep only its newest `keep`
+/// revisions.
+pub async fn insert_revision(
+    pool: &SqlitePool,
+    path: &str,
+    content: &str,
+    author: &str,
+    source: &str,
+    keep: i64,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("INSERT INTO revisions (path, content, author, source) VALUES (?, ?, ?, ?)")
+        .bind(path)
+        .bind(content)
+        .bind(author)
+        .bind(source)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to insert revision")?;
+    sqlx::query(
+        "DELETE FROM revisions WHERE path = ? AND id NOT IN
+            (SELECT id FROM revisions WHERE path = ? ORDER BY id DESC LIMIT ?)",
+    )
+    .bind(path)
+    .bind(path)
+    .bind(keep)
+    .execute(&mut *tx)
+    .await
+    .context("Failed to prune revisions")?;
+    tx.commit().await.context("Failed to commit revision")?;
+    Ok(())
+}
+
+/// Revisions of `path`, newest first.
+pub async fn list_revisions(pool: &SqlitePool, path: &str) -> Result<Vec<RevisionMeta>> {
+    let rows = sqlx::query(
+        "SELECT id, author, source, created_at, length(CAST(content AS BLOB)) AS size
+         FROM revisions WHERE path = ? ORDER BY id DESC",
+    )
+    .bind(path)
+    .fetch_all(pool)
+    .await
+    .context("Failed to list revisions")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| RevisionMeta {
+            id: r.get("id"),
+            author: r.get("author"),
+            source: r.get("source"),
+            created_at: r.get("created_at"),
+            size: r.get("size"),
+        })
+        .collect())
+}
+
+pub async fn get_revision(pool: &SqlitePool, id: i64) -> Result<Option<Revision>> {
+    let row = sqlx::query(
+        "SELECT id, path, content, author, source, created_at FROM revisions WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .context("Failed to read revision")?;
+    Ok(row.map(|r| Revision {
+        id: r.get("id"),
+        path: r.get("path"),
+        content: r.get("content"),
+        author: r.get("author"),
+        source: r.get("source"),
+        created_at: r.get("created_at"),
+    }))
+}
+
+/// Revisions of `path` with their content, newest first.
+pub async fn revision_contents(pool: &SqlitePool, path: &str) -> Result<Vec<Revision>> {
+    let rows = sqlx::query(
+        "SELECT id, path, content, author, source, created_at
+         FROM revisions WHERE path = ? ORDER BY id DESC",
+    )
+    .bind(path)
+    .fetch_all(pool)
+    .await
+    .context("Failed to read revisions")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| Revision {
+            id: r.get("id"),
+            path: r.get("path"),
+            content: r.get("content"),
+            author: r.get("author"),
+            source: r.get("source"),
+            created_at: r.get("created_at"),
+        })
+        .collect())
+}
+
+/// Files with revisions, most recently changed first.
+pub async fn revised_files(pool: &SqlitePool, limit: i64) -> Result<Vec<RevisedFile>> {
+    let rows = sqlx::query(
+        "SELECT r.path, c.revisions, r.created_at, r.author
+         FROM revisions r
+         JOIN (SELECT path, COUNT(*) AS revisions, MAX(id) AS last_id
+               FROM revisions GROUP BY path) c ON r.id = c.last_id
+         ORDER BY r.id DESC LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .context("Failed to list revised files")?;
+    Ok(rows
+        .into_iter()
+        .map(|r| RevisedFile {
+            path: r.get("path"),
+            revisions: r.get("revisions"),
+            last_changed: r.get("created_at"),
+            last_author: r.get("author"),
+        })
+        .collect())
+}
+
+/// Carry the history of `from` (a file, or every file under a directory) over
+/// to `to` after a rename.
+pub async fn move_revisions(pool: &SqlitePool, from: &str, to: &str) -> Result<()> {
+    let prefix = format!("{}/", from.trim_end_matches('/'));
+    sqlx::query(
+        "UPDATE revisions SET path = ? || substr(path, length(?) + 1)
+         WHERE path = ? OR substr(path, 1, length(?)) = ?",
+    )
+    .bind(to.trim_end_matches('/'))
+    .bind(from.trim_end_matches('/'))
+    .bind(from)
+    .bind(&prefix)
+    .bind(&prefix)
+    .execute(pool)
+    .await
+    .context("Failed to move revisions")?;
+    Ok(())
+}
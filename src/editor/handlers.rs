@@ -1,31 +1,48 @@
 use axum::{
-    Form,
-    extract::{Query, State},
+    Extension, Form, Json,
+    extract::{Multipart, Query, State},
     http::StatusCode,
     response::{Html, IntoResponse, Redirect, Response},
 };
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use std::{
     io,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
 use crate::{
+    backlinks,
+    conditional,
     css::find_css,
     db,
     error::AppError,
     front_matter::{self, ParsedDoc},
-    micropub,
-    state::AppState,
+    git, handler, headers,
+    highlight,
+    history::{self, Source},
+    micropub::{
+        self,
+        media::{self, StoreError},
+    },
+    preview, redirects,
+    search,
+    state::{AppState, RenderConfig},
+    toc,
+    wiki,
 };
 
-use super::template::{self, FileNode};
+use super::{
+    EditorUser,
+    template::{self, FileNode, MediaFile},
+};
 
 // ── Dashboard ─────────────────────────────────────────────────────────────────
 
 pub async fn get_dashboard(State(state): State<AppState>) -> Response {
     match build_file_tree(&state.canonical_root, &state.canonical_root).await {
-        Ok(tree) => Html(template::dashboard(&tree).into_string()).into_response(),
+        Ok(tree) => Html(template::dashboard(nav(&state, &tree)).into_string()).into_response(),
         Err(e) => AppError::Io(e).into_response(),
     }
 }
@@ -68,7 +85,7 @@ pub async fn get_analytics(
         }
     };
 
-    Html(template::analytics_page(&tree, &data).into_string()).into_response()
+    Html(template::analytics_page(nav(&state, &tree), &data).into_string()).into_response()
 }
 
 // ── Editor page ───────────────────────────────────────────────────────────────
@@ -98,7 +115,30 @@ pub async fn get_editor(
         Err(e) => return AppError::Io(e).into_response(),
     };
 
-    Html(template::editor_page(&params.path, &content, &tree).into_string()).into_response()
+    let is_markdown = params.path.ends_with(".md");
+    let backlinks = if is_markdown {
+        let (_, url) = handler::page_url(&params.path);
+        Some(backlinks::linking_files(&state, &url).await)
+    } else {
+        None
+    };
+    // Drafts and scheduled pages get a "share preview" control.
+    let hidden = is_markdown && front_matter::parse(&content).front_matter.is_hidden();
+
+    let version = conditional::content_etag(content.as_bytes());
+
+    Html(
+        template::editor_page(
+            &params.path,
+            &content,
+            &version,
+            nav(&state, &tree),
+            backlinks.as_deref(),
+            hidden,
+        )
+        .into_string(),
+    )
+    .into_response()
 }
 
 // ── Save ──────────────────────────────────────────────────────────────────────
@@ -107,9 +147,16 @@ pub async fn get_editor(
 pub struct SaveForm {
     pub path: String,
     pub content: String,
+    /// ETag of the file as the editor loaded or last saved it. When it no
+    /// longer matches the file, someone else has saved in between.
+    pub version: Option<String>,
 }
 
-pub async fn post_save(State(state): State<AppState>, Form(form): Form<SaveForm>) -> Response {
+pub async fn post_save(
+    State(state): State<AppState>,
+    Extension(EditorUser(user)): Extension<EditorUser>,
+    Form(form): Form<SaveForm>,
+) -> Response {
     let fs_path = match resolve_write_path(&state, &form.path).await {
         Ok(p) => p,
         Err(r) => return r,
@@ -121,11 +168,61 @@ pub async fn post_save(State(state): State<AppState>, Form(form): Form<SaveForm>
         }
     }
 
+    // Held until the write, so the version check can't go stale.
+    let _write = state.writes.lock(&fs_path).await;
+    let current = tokio::fs::read_to_string(&fs_path).await.ok();
+    let current_version = current
+        .as_deref()
+        .map(|c| conditional::content_etag(c.as_bytes()))
+        .unwrap_or_default();
+    if let Some(version) = &form.version
+        && *version != current_version
+    {
+        let theirs = current.as_deref().unwrap_or("");
+        return save_conflict(&state, &fs_path, version, &form.content, theirs, &current_version)
+            .await;
+    }
+
+    let existed = current.is_some();
+    history::record(&state, &fs_path, Some(&form.content), &user, Source::Editor).await;
     if let Err(e) = tokio::fs::write(&fs_path, form.content.as_bytes()).await {
         return AppError::Io(e).into_response();
     }
+    search::reindex(&state, &fs_path).await;
+    backlinks::reindex(&state, &fs_path).await;
+    let rel = handler::rel_path(&state.canonical_root, &fs_path);
+    let verb = if existed { "Update" } else { "Create" };
+    git::commit(&state, &[&fs_path], &user, format!("{} {}", verb, rel)).await;
+
+    let version = conditional::content_etag(form.content.as_bytes());
+    Html(template::saved(&version).into_string()).into_response()
+}
 
-    Html(r#"<span id="save-status" class="save-ok">Saved</span>"#.to_string()).into_response()
+/// Refuse a save made from a stale copy: show what changed on disk, and offer
+/// to overwrite it or to merge the two edits. The merge needs the copy the
+/// editor started from, which history has when the other write went through
+/// the editor or Micropub.
+async fn save_conflict(
+    state: &AppState,
+    fs_path: &Path,
+    base_version: &str,
+    mine: &str,
+    theirs: &str,
+    current_version: &str,
+) -> Response {
+    let rel = handler::rel_path(&state.canonical_root, fs_path);
+    let base = history::find_version(state, &rel, base_version).await;
+    let merge = base.as_ref().map(|b| history::merge(&b.content, mine, theirs));
+    let diff = history::side_by_side(theirs, mine);
+    tracing::info!("Rejected stale save of {}", rel);
+    (
+        StatusCode::CONFLICT,
+        Html(
+            template::save_conflict(&rel, base.as_ref(), &diff, merge.as_ref(), current_version)
+                .into_string(),
+        ),
+    )
+        .into_response()
 }
 
 // ── Preview ───────────────────────────────────────────────────────────────────
@@ -149,7 +246,14 @@ pub async fn post_preview(
         content,
     } = front_matter::parse(&form.content);
 
-    let html = render_markdown_safe(&content);
+    let pages = if wiki::has_links(&content) {
+        handler::load_site_pages(&state).await
+    } else {
+        Default::default()
+    };
+    let (_, url) = handler::page_url(form.path.trim_start_matches('/'));
+    let links = wiki::Resolver::new(&pages, &url);
+    let html = render_markdown_safe(&content, &links, &state.render);
     Html(template::preview_doc(&html, css.as_deref()).into_string()).into_response()
 }
 
@@ -190,6 +294,7 @@ pub struct NewFileForm {
 
 pub async fn post_new_file(
     State(state): State<AppState>,
+    Extension(EditorUser(user)): Extension<EditorUser>,
     Form(form): Form<NewFileForm>,
 ) -> Response {
     // Ensure .md extension.
@@ -222,6 +327,10 @@ pub async fn post_new_file(
     if let Err(e) = tokio::fs::write(&fs_path, b"---\ndraft: true\n---\n").await {
         return AppError::Io(e).into_response();
     }
+    search::reindex(&state, &fs_path).await;
+    backlinks::reindex(&state, &fs_path).await;
+    let rel = handler::rel_path(&state.canonical_root, &fs_path);
+    git::commit(&state, &[&fs_path], &user, format!("Create {}", rel)).await;
 
     Redirect::to(&format!("/edit/open?path={}", urlencoded(&path))).into_response()
 }
@@ -250,6 +359,7 @@ pub async fn post_new_dir(State(state): State<AppState>, Form(form): Form<NewDir
 
 pub async fn delete_file(
     State(state): State<AppState>,
+    Extension(EditorUser(user)): Extension<EditorUser>,
     Query(params): Query<PathParam>,
 ) -> Response {
     let fs_path = match resolve_read_path(&state, &params.path).await {
@@ -273,14 +383,20 @@ pub async fn delete_file(
     };
 
     let result = if meta.is_dir() {
+        history::record_tree(&state, &fs_path, &user, Source::Editor).await;
         tokio::fs::remove_dir_all(&fs_path).await
     } else {
+        history::record(&state, &fs_path, None, &user, Source::Editor).await;
         tokio::fs::remove_file(&fs_path).await
     };
 
     if let Err(e) = result {
         return AppError::Io(e).into_response();
     }
+    search::reindex(&state, &fs_path).await;
+    backlinks::reindex(&state, &fs_path).await;
+    let rel = handler::rel_path(&state.canonical_root, &fs_path);
+    git::commit(&state, &[&fs_path], &user, format!("Delete {}", rel)).await;
 
     Redirect::to("/edit").into_response()
 }
@@ -291,9 +407,16 @@ pub async fn delete_file(
 pub struct RenameForm {
     pub old_path: String,
     pub new_path: String,
+    /// `"true"` to redirect the old URL to the new one.
+    #[serde(default)]
+    pub redirect: String,
 }
 
-pub async fn post_rename(State(state): State<AppState>, Form(form): Form<RenameForm>) -> Response {
+pub async fn post_rename(
+    State(state): State<AppState>,
+    Extension(EditorUser(user)): Extension<EditorUser>,
+    Form(form): Form<RenameForm>,
+) -> Response {
     let src = match resolve_read_path(&state, &form.old_path).await {
         Ok(p) => p,
         Err(r) => return r,
@@ -322,10 +445,44 @@ pub async fn post_rename(State(state): State<AppState>, Form(form): Form<RenameF
     if let Err(e) = tokio::fs::rename(&src, &dst).await {
         return AppError::Io(e).into_response();
     }
+    history::moved(&state, &src, &dst).await;
+    search::reindex(&state, &src).await;
+    search::reindex(&state, &dst).await;
+    backlinks::reindex(&state, &src).await;
+    backlinks::reindex(&state, &dst).await;
+    let root = &state.canonical_root;
+    let (old_rel, new_rel) = (handler::rel_path(root, &src), handler::rel_path(root, &dst));
+    let redirects_path = redirects::file_path(&state);
+    let mut changed: Vec<&Path> = vec![&src, &dst];
+    if form.redirect == "true" {
+        let is_dir = tokio::fs::metadata(&dst).await.is_ok_and(|m| m.is_dir());
+        let (from, to) = if is_dir {
+            (format!("/{}/*", old_rel), format!("/{}/:splat", new_rel))
+        } else {
+            (served_url(&old_rel), served_url(&new_rel))
+        };
+        if from != to {
+            match redirects::add(&state, &from, &to).await {
+                Ok(()) => changed.push(&redirects_path),
+                Err(e) => tracing::warn!("Cannot add redirect from {}: {e:#}", from),
+            }
+        }
+    }
+    let message = format!("Rename {} to {}", old_rel, new_rel);
+    git::commit(&state, &changed, &user, message).await;
 
     Redirect::to("/edit").into_response()
 }
 
+/// The URL a file under the www root is served at.
+fn served_url(rel: &str) -> String {
+    if rel.ends_with(".md") {
+        handler::page_url(rel).1
+    } else {
+        format!("/{}", rel)
+    }
+}
+
 // ── Path helpers ──────────────────────────────────────────────────────────────
 
 /// Resolve a client-supplied relative path for **reading** (file must exist).
@@ -414,6 +571,14 @@ fn io_err_to_response(e: std::io::Error) -> Response {
 
 const MAX_TREE_DEPTH: usize = 10;
 
+/// The sidebar for an editor page showing `tree`.
+fn nav<'a>(state: &AppState, tree: &'a [FileNode]) -> template::Nav<'a> {
+    template::Nav {
+        tree,
+        git: state.git.is_some(),
+    }
+}
+
 /// Recursively build a file tree under `dir`, rooted at `root`.
 /// Returns nodes sorted: directories first, then files, both alphabetically.
 /// Stops at `MAX_TREE_DEPTH` levels to bound memory and prevent runaway recursion.
@@ -475,11 +640,16 @@ async fn build_file_tree_inner(root: &Path, dir: &Path, depth: usize) -> io::Res
 
 /// Safe markdown rendering for the editor preview: raw HTML passthrough is
 /// disabled to prevent XSS in the preview pane.
-fn render_markdown_safe(content: &str) -> String {
+fn render_markdown_safe(content: &str, links: &wiki::Resolver, render: &RenderConfig) -> String {
     let mut opts = markdown::Options::gfm();
     opts.parse.constructs.frontmatter = false;
     opts.compile.allow_dangerous_html = false;
-    markdown::to_html_with_options(content, &opts).unwrap_or_else(|_| markdown::to_html(content))
+    let html = markdown::to_html_with_options(content, &opts)
+        .unwrap_or_else(|_| markdown::to_html(content));
+    let html = highlight::code_blocks(content, &opts.parse, html, &render.highlight);
+    let html = wiki::link(&html, links);
+    let (html, headings) = toc::add_heading_ids(&html, render.heading_anchors);
+    toc::expand_marker(html, &headings)
 }
 
 /// Percent-encode a path for safe use in URL query strings.
@@ -487,18 +657,371 @@ pub fn urlencoded(s: &str) -> String {
     percent_encoding::utf8_percent_encode(s, percent_encoding::NON_ALPHANUMERIC).to_string()
 }
 
+// ── Links report ──────────────────────────────────────────────────────────────
+
+pub async fn get_links(State(state): State<AppState>) -> Response {
+    let (tree, broken) = tokio::join!(
+        build_file_tree(&state.canonical_root, &state.canonical_root),
+        wiki::broken_links(&state),
+    );
+    let tree = match tree {
+        Ok(t) => t,
+        Err(e) => return AppError::Io(e).into_response(),
+    };
+    Html(template::links_page(nav(&state, &tree), &broken).into_string()).into_response()
+}
+
+// ── Redirects ─────────────────────────────────────────────────────────────────
+
+/// `/edit/redirects`: edit `_redirects` and review page aliases.
+pub async fn get_redirects(State(state): State<AppState>) -> Response {
+    render_redirects_page(&state, None).await
+}
+
+pub async fn post_redirects(
+    State(state): State<AppState>,
+    Extension(EditorUser(user)): Extension<EditorUser>,
+    Form(form): Form<SettingForm>,
+) -> Response {
+    match redirects::save(&state, &form.value).await {
+        Ok(()) => {
+            let path = redirects::file_path(&state);
+            let message = format!("Update {}", redirects::REDIRECTS_FILE);
+            git::commit(&state, &[&path], &user, message).await;
+            Redirect::to("/edit/redirects").into_response()
+        }
+        Err(e) => render_redirects_page(&state, Some((&form.value, &format!("{e:#}")))).await,
+    }
+}
+
+/// Render the redirects page; `rejected` is submitted text that failed to
+/// save, with the reason.
+async fn render_redirects_page(state: &AppState, rejected: Option<(&str, &str)>) -> Response {
+    let (tree, source, aliases) = tokio::join!(
+        build_file_tree(&state.canonical_root, &state.canonical_root),
+        redirects::source(state),
+        redirects::aliases(state),
+    );
+    let tree = match tree {
+        Ok(t) => t,
+        Err(e) => return AppError::Io(e).into_response(),
+    };
+    let (source, error, status) = match rejected {
+        Some((text, error)) => (text, Some(error), StatusCode::BAD_REQUEST),
+        None => (source.as_str(), None, StatusCode::OK),
+    };
+    let page = template::redirects_page(nav(state, &tree), source, error, &aliases);
+    (status, Html(page.into_string())).into_response()
+}
+
+// ── History ───────────────────────────────────────────────────────────────────
+
+/// Files listed on the history overview.
+const HISTORY_FILES: i64 = 200;
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub path: Option<String>,
+    /// Revision to compare with the current file; the newest by default.
+    pub rev: Option<i64>,
+}
+
+/// `/edit/history` lists recently changed files, deleted ones included;
+/// `?path=` shows one file's revisions and a diff against the current file.
+pub async fn get_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    let tree = match build_file_tree(&state.canonical_root, &state.canonical_root).await {
+        Ok(t) => t,
+        Err(e) => return AppError::Io(e).into_response(),
+    };
+    let Some(path) = query.path else {
+        let files = db::revised_files(&state.db, HISTORY_FILES)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!("Cannot list history: {e:#}");
+                Vec::new()
+            });
+        return Html(template::history_index(nav(&state, &tree), &files).into_string())
+            .into_response();
+    };
+
+    // The file may have been deleted, so resolve it like a write.
+    let fs_path = match resolve_write_path(&state, &path).await {
+        Ok(p) => p,
+        Err(r) => return r,
+    };
+    let rel = handler::rel_path(&state.canonical_root, &fs_path);
+    let revisions = match db::list_revisions(&state.db, &rel).await {
+        Ok(r) => r,
+        Err(e) => return AppError::Internal(format!("{e:#}")).into_response(),
+    };
+    let selected = match query.rev.or_else(|| revisions.first().map(|r| r.id)) {
+        Some(id) => match db::get_revision(&state.db, id).await {
+            Ok(Some(rev)) if rev.path == rel => Some(rev),
+            Ok(_) => return AppError::NotFound.into_response(),
+            Err(e) => return AppError::Internal(format!("{e:#}")).into_response(),
+        },
+        None => None,
+    };
+    let current = tokio::fs::read_to_string(&fs_path).await.ok();
+    let diff = match &selected {
+        Some(rev) => history::side_by_side(&rev.content, current.as_deref().unwrap_or("")),
+        None => Vec::new(),
+    };
+
+    Html(
+        template::history_page(
+            nav(&state, &tree),
+            &rel,
+            &revisions,
+            selected.as_ref(),
+            &diff,
+            current.is_some(),
+        )
+        .into_string(),
+    )
+    .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct RestoreForm {
+    pub path: String,
+    pub id: i64,
+}
+
+/// Put a revision's content back, recording the current content first so the
+/// restore itself can be undone.
+pub async fn post_restore(
+    State(state): State<AppState>,
+    Extension(EditorUser(user)): Extension<EditorUser>,
+    Form(form): Form<RestoreForm>,
+) -> Response {
+    let fs_path = match resolve_write_path(&state, &form.path).await {
+        Ok(p) => p,
+        Err(r) => return r,
+    };
+    let rel = handler::rel_path(&state.canonical_root, &fs_path);
+    let revision = match db::get_revision(&state.db, form.id).await {
+        Ok(Some(rev)) if rev.path == rel => rev,
+        Ok(_) => return AppError::NotFound.into_response(),
+        Err(e) => return AppError::Internal(format!("{e:#}")).into_response(),
+    };
+
+    if let Some(parent) = fs_path.parent()
+        && let Err(e) = tokio::fs::create_dir_all(parent).await
+    {
+        return AppError::Io(e).into_response();
+    }
+    history::record(&state, &fs_path, Some(&revision.content), &user, Source::Editor).await;
+    if let Err(e) = tokio::fs::write(&fs_path, revision.content.as_bytes()).await {
+        return AppError::Io(e).into_response();
+    }
+    search::reindex(&state, &fs_path).await;
+    backlinks::reindex(&state, &fs_path).await;
+    tracing::info!("Restored {} to revision {}", rel, revision.id);
+    let message = format!("Restore {} to revision of {}", rel, revision.created_at);
+    git::commit(&state, &[&fs_path], &user, message).await;
+
+    Redirect::to(&format!("/edit/open?path={}", urlencoded(&rel))).into_response()
+}
+
+// ── Media ─────────────────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+pub struct MediaQuery {
+    /// Render just the grid, for the editor's insert-media dialog.
+    pub pick: Option<String>,
+}
+
+/// `GET /edit/media`: the media library, with how many pages use each file.
+pub async fn get_media(State(state): State<AppState>, Query(query): Query<MediaQuery>) -> Response {
+    let media_dir = media::media_dir(&state).await;
+    let mut files = list_media(&state.canonical_root, &media_dir).await;
+    count_media_uses(&state.canonical_root, &mut files).await;
+
+    if query.pick.is_some() {
+        return Html(template::media_grid(&files, true).into_string()).into_response();
+    }
+    let tree = match build_file_tree(&state.canonical_root, &state.canonical_root).await {
+        Ok(t) => t,
+        Err(e) => return AppError::Io(e).into_response(),
+    };
+    Html(template::media_page(nav(&state, &tree), &media_dir, &files).into_string()).into_response()
+}
+
+/// `POST /edit/media`: store an uploaded `file` the way Micropub does and
+/// return its URL and the markdown to insert.
+pub async fn post_upload_media(
+    State(state): State<AppState>,
+    Extension(EditorUser(user)): Extension<EditorUser>,
+    mut multipart: Multipart,
+) -> Response {
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let name = field.file_name().unwrap_or("upload").to_string();
+        let content_type = field
+            .content_type()
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        if !media::is_allowed_media_type(&content_type) {
+            return (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Only images, video, audio and PDF files can be uploaded.",
+            )
+                .into_response();
+        }
+        let data = match field.bytes().await {
+            Ok(b) => b,
+            Err(_) => {
+                return (StatusCode::BAD_REQUEST, "Failed to read uploaded file.").into_response();
+            }
+        };
+
+        let stored = match media::store_media(&state, &name, data).await {
+            Ok(stored) => stored,
+            Err(StoreError::Io(e)) => return AppError::Io(e).into_response(),
+            Err(StoreError::OutsideRoot) => {
+                return (StatusCode::FORBIDDEN, "The media directory is outside the www root.")
+                    .into_response();
+            }
+        };
+        git::commit(
+            &state,
+            &[&stored.path],
+            &user,
+            format!("Upload {}", stored.url_path),
+        )
+        .await;
+        tracing::info!("Editor upload: saved {}", stored.path.display());
+
+        let file_name = stored.url_path.rsplit('/').next().unwrap_or_default();
+        let markdown = template::media_markdown(file_name, &stored.url_path, is_image(&stored.path));
+        return (
+            StatusCode::CREATED,
+            Json(serde_json::json!({ "url": stored.url_path, "markdown": markdown })),
+        )
+            .into_response();
+    }
+    (StatusCode::BAD_REQUEST, "No file in the upload.").into_response()
+}
+
+/// Every file under the media directory, newest first.
+async fn list_media(root: &Path, media_dir: &str) -> Vec<MediaFile> {
+    let Ok(dir) = tokio::fs::canonicalize(root.join(media_dir.trim_matches('/'))).await else {
+        return Vec::new();
+    };
+    if !dir.starts_with(root) {
+        return Vec::new();
+    }
+
+    let mut files = Vec::new();
+    let mut stack = vec![dir];
+    while let Some(dir) = stack.pop() {
+        let Ok(mut read_dir) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(meta) = entry.metadata().await else {
+                continue;
+            };
+            let path = entry.path();
+            if meta.is_dir() {
+                stack.push(path);
+            } else if meta.is_file() {
+                files.push(MediaFile {
+                    url: format!("/{}", handler::rel_path(root, &path)),
+                    is_image: is_image(&path),
+                    name,
+                    size: meta.len(),
+                    modified: meta.modified().ok().map(DateTime::<Utc>::from),
+                    uses: 0,
+                });
+            }
+        }
+    }
+    files.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.url.cmp(&b.url)));
+    files
+}
+
+/// Count the markdown files that mention each media file's path.
+async fn count_media_uses(root: &Path, files: &mut [MediaFile]) {
+    if files.is_empty() {
+        return;
+    }
+    for (path, _) in handler::walk_markdown(root).await {
+        let Ok(content) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        for file in files.iter_mut() {
+            // Matches absolute paths and full URLs alike.
+            if content.contains(file.url.trim_start_matches('/')) {
+                file.uses += 1;
+            }
+        }
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    mime_guess::from_path(path)
+        .first()
+        .is_some_and(|m| m.type_() == mime_guess::mime::IMAGE)
+}
+
+// ── Git ───────────────────────────────────────────────────────────────────────
+
+/// Commits shown on the git page.
+const GIT_LOG_LIMIT: usize = 100;
+
+pub async fn get_git(State(state): State<AppState>) -> Response {
+    render_git_page(&state, None).await
+}
+
+/// Pull from the remote now rather than waiting for the timer.
+pub async fn post_git_pull(State(state): State<AppState>) -> Response {
+    let result = git::pull(&state).await.map_err(|e| format!("{e:#}"));
+    render_git_page(&state, Some(result)).await
+}
+
+async fn render_git_page(state: &AppState, pull: Option<Result<String, String>>) -> Response {
+    let status = match git::status(state, GIT_LOG_LIMIT).await.transpose() {
+        Ok(status) => status,
+        Err(e) => return AppError::Internal(format!("{e:#}")).into_response(),
+    };
+    let tree = build_file_tree(&state.canonical_root, &state.canonical_root)
+        .await
+        .unwrap_or_default();
+    let page = template::git_page(nav(state, &tree), status.as_ref(), pull.as_ref());
+    Html(page.into_string()).into_response()
+}
+
 // ── Micropub settings ─────────────────────────────────────────────────────────
 
 pub async fn get_settings(State(state): State<AppState>) -> Response {
-    render_settings_page(&state, None).await
+    render_settings_page(&state, None, None).await
 }
 
-/// Render the settings page, optionally showing a newly-created token.
-async fn render_settings_page(state: &AppState, new_token: Option<&str>) -> Response {
-    let (tokens_result, post_dir_result, media_dir_result, tree_result) = tokio::join!(
+/// Render the settings page, optionally showing a newly-created token, or
+/// rejected header rules (the submitted text and the error).
+async fn render_settings_page(
+    state: &AppState,
+    new_token: Option<&str>,
+    rejected_headers: Option<(&str, &str)>,
+) -> Response {
+    let (tokens_result, post_dir_result, media_dir_result, backlinks, header_rules, previews, tree_result) = tokio::join!(
         db::list_micropub_tokens(&state.db),
         db::get_micropub_setting(&state.db, "post_dir"),
         db::get_micropub_setting(&state.db, "media_dir"),
+        backlinks::site_default(state),
+        headers::current(state),
+        preview::active(state),
         build_file_tree(&state.canonical_root, &state.canonical_root),
     );
 
@@ -506,11 +1029,27 @@ async fn render_settings_page(state: &AppState, new_token: Option<&str>) -> Resp
     let post_dir = post_dir_result.unwrap_or_else(|_| "posts".to_string());
     let media_dir = media_dir_result.unwrap_or_else(|_| "_media".to_string());
     let tree = tree_result.unwrap_or_default();
+    let site = template::SiteSettings {
+        backlinks,
+        header_rules: rejected_headers.map_or(header_rules.source.as_str(), |(text, _)| text),
+        header_error: rejected_headers.map(|(_, error)| error),
+    };
+    let status = if rejected_headers.is_some() {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::OK
+    };
 
-    Html(
-        template::settings_page(&tree, &tokens, &post_dir, &media_dir, new_token).into_string(),
-    )
-    .into_response()
+    let page = template::settings_page(
+        nav(state, &tree),
+        &tokens,
+        &post_dir,
+        &media_dir,
+        &site,
+        &previews,
+        new_token,
+    );
+    (status, Html(page.into_string())).into_response()
 }
 
 #[derive(Deserialize)]
@@ -542,7 +1081,7 @@ pub async fn post_create_token(
             .into_response();
     }
 
-    render_settings_page(&state, Some(&raw_token)).await
+    render_settings_page(&state, Some(&raw_token), None).await
 }
 
 #[derive(Deserialize)]
@@ -558,6 +1097,64 @@ pub async fn post_delete_token(
     Redirect::to("/edit/settings").into_response()
 }
 
+// ── Draft preview links ───────────────────────────────────────────────────────
+
+/// Longest validity the editor offers for a preview link.
+const MAX_PREVIEW_DAYS: u64 = 90;
+
+#[derive(Deserialize)]
+pub struct CreatePreviewLinkForm {
+    pub path: String,
+    /// How long the link stays valid.
+    pub days: u64,
+}
+
+/// Create a shareable preview link for a draft, then show it on the settings
+/// page.
+pub async fn post_create_preview_link(
+    State(state): State<AppState>,
+    Form(form): Form<CreatePreviewLinkForm>,
+) -> Response {
+    let fs_path = match resolve_read_path(&state, &form.path).await {
+        Ok(p) => p,
+        Err(r) => return r,
+    };
+    if fs_path.extension().is_none_or(|e| e != "md") || !fs_path.is_file() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Html("Preview links are for Markdown files.".to_string()),
+        )
+            .into_response();
+    }
+    let days = form.days.clamp(1, MAX_PREVIEW_DAYS);
+    let rel = handler::rel_path(&state.canonical_root, &fs_path);
+
+    match preview::create(&state, &rel, Duration::from_secs(days * 24 * 3600)).await {
+        Ok(_) => Redirect::to("/edit/settings#preview-links").into_response(),
+        Err(e) => {
+            tracing::error!("Failed to create preview link: {e:#}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Html("Failed to create preview link.".to_string()),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RevokePreviewLinkForm {
+    pub id: i64,
+}
+
+pub async fn post_revoke_preview_link(
+    State(state): State<AppState>,
+    Form(form): Form<RevokePreviewLinkForm>,
+) -> Response {
+    preview::revoke(&state, form.id).await.ok();
+    Redirect::to("/edit/settings#preview-links").into_response()
+}
+
 #[derive(Deserialize)]
 pub struct SettingForm {
     pub value: String,
@@ -577,6 +1174,31 @@ pub async fn post_set_media_dir(
     set_micropub_dir_setting(&state, "media_dir", &form.value).await
 }
 
+/// Show backlinks on pages that don't set `backlinks` in their front matter.
+/// The form sends a hidden `false` before the checkbox, so the last value wins.
+pub async fn post_set_backlinks(
+    State(state): State<AppState>,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Response {
+    let on = form.iter().rev().find(|(k, _)| k == "value").is_some_and(|(_, v)| v == "true");
+    if let Err(e) = backlinks::set_site_default(&state, on).await {
+        tracing::error!("Failed to save backlinks setting: {e:#}");
+    }
+    Redirect::to("/edit/settings").into_response()
+}
+
+/// Replace the response header rules; invalid rules are shown back with the
+/// error and not saved.
+pub async fn post_set_header_rules(
+    State(state): State<AppState>,
+    Form(form): Form<SettingForm>,
+) -> Response {
+    match headers::save(&state, &form.value).await {
+        Ok(()) => Redirect::to("/edit/settings#response-headers").into_response(),
+        Err(e) => render_settings_page(&state, None, Some((&form.value, &format!("{e:#}")))).await,
+    }
+}
+
 async fn set_micropub_dir_setting(state: &AppState, key: &str, raw: &str) -> Response {
     let value = raw.trim().to_string();
     if value.contains("..") || value.starts_with('/') {
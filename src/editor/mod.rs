@@ -3,7 +3,7 @@ mod template;
 
 use axum::{
     Form, Router,
-    extract::{DefaultBodyLimit, Request, State},
+    extract::{DefaultBodyLimit, Query, Request, State},
     http::{StatusCode, header},
     middleware::{self, Next},
     response::{Html, IntoResponse, Redirect, Response},
@@ -12,7 +12,7 @@ use axum::{
 use serde::Deserialize;
 use std::time::{Duration, Instant};
 
-use crate::state::AppState;
+use crate::state::{AppState, Session};
 
 /// Session cookie name.
 const SESSION_COOKIE: &str = "ed_session";
@@ -38,13 +38,30 @@ pub fn router(state: AppState) -> Router<AppState> {
         .route("/edit/new-dir", post(handlers::post_new_dir))
         .route("/edit/delete", delete(handlers::delete_file))
         .route("/edit/rename", post(handlers::post_rename))
+        .route("/edit/links", get(handlers::get_links))
+        .route("/edit/redirects", get(handlers::get_redirects).post(handlers::post_redirects))
+        .route("/edit/history", get(handlers::get_history))
+        .route("/edit/history/restore", post(handlers::post_restore))
+        .route("/edit/git", get(handlers::get_git))
+        .route("/edit/git/pull", post(handlers::post_git_pull))
         .route("/edit/settings", get(handlers::get_settings))
         .route("/edit/settings/token", post(handlers::post_create_token))
         .route("/edit/settings/token/delete", post(handlers::post_delete_token))
         .route("/edit/settings/post-dir", post(handlers::post_set_post_dir))
         .route("/edit/settings/media-dir", post(handlers::post_set_media_dir))
+        .route("/edit/settings/backlinks", post(handlers::post_set_backlinks))
+        .route("/edit/settings/headers", post(handlers::post_set_header_rules))
+        .route("/edit/preview-link", post(handlers::post_create_preview_link))
+        .route("/edit/settings/preview-link/delete", post(handlers::post_revoke_preview_link))
         .route("/edit/logout", post(post_logout))
         .layer(DefaultBodyLimit::max(10 * 1024 * 1024)) // 10 MB
+        // Media uploads get the same allowance as Micropub's media endpoint.
+        .route(
+            "/edit/media",
+            get(handlers::get_media)
+                .post(handlers::post_upload_media)
+                .layer(DefaultBodyLimit::max(50 * 1024 * 1024)),
+        )
         .route_layer(middleware::from_fn_with_state(state, require_auth));
 
     Router::new().merge(public).merge(protected)
@@ -52,28 +69,34 @@ pub fn router(state: AppState) -> Router<AppState> {
 
 // ── Auth middleware ───────────────────────────────────────────────────────────
 
-async fn require_auth(State(state): State<AppState>, req: Request, next: Next) -> Response {
+/// The logged-in editor user, added to each authenticated request's
+/// extensions by `require_auth`.
+#[derive(Clone)]
+pub(crate) struct EditorUser(pub String);
+
+async fn require_auth(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
     let token = extract_session_cookie(req.headers());
 
     if let Some(tok) = token {
         // Use a single write lock for both the validity check and the expiry slide
         // to avoid a TOCTOU race where a concurrent logout removes the session
         // between the read and write.
-        let valid = {
+        let user = {
             let mut sessions = state.sessions.write().await;
-            if let Some(exp) = sessions.get(&tok) {
-                if exp.elapsed() < SESSION_TTL {
-                    sessions.insert(tok.clone(), Instant::now());
-                    true
+            if let Some(session) = sessions.get_mut(&tok) {
+                if session.last_used.elapsed() < SESSION_TTL {
+                    session.last_used = Instant::now();
+                    Some(session.user.clone())
                 } else {
                     sessions.remove(&tok);
-                    false
+                    None
                 }
             } else {
-                false
+                None
             }
         };
-        if valid {
+        if let Some(user) = user {
+            req.extensions_mut().insert(EditorUser(user));
             return next.run(req).await;
         }
     }
@@ -81,16 +104,45 @@ async fn require_auth(State(state): State<AppState>, req: Request, next: Next) -
     Redirect::to("/edit/login").into_response()
 }
 
+/// The editor user logged in with the request's session cookie, if any.
+/// Used by `access` to admit editors to private sections.
+pub(crate) async fn session_user(state: &AppState, headers: &header::HeaderMap) -> Option<String> {
+    let tok = extract_session_cookie(headers)?;
+    let sessions = state.sessions.read().await;
+    let session = sessions.get(&tok)?;
+    (session.last_used.elapsed() < SESSION_TTL).then(|| session.user.clone())
+}
+
 // ── Login / logout ────────────────────────────────────────────────────────────
 
-async fn get_login() -> Response {
-    Html(template::login_page(None).into_string()).into_response()
+#[derive(Deserialize)]
+struct LoginQuery {
+    #[serde(default)]
+    next: String,
+}
+
+async fn get_login(Query(query): Query<LoginQuery>) -> Response {
+    Html(template::login_page(None, safe_next(&query.next)).into_string()).into_response()
 }
 
 #[derive(Deserialize)]
 struct LoginForm {
     username: String,
     password: String,
+    /// Where to go after logging in (a private page that sent us here).
+    #[serde(default)]
+    next: String,
+}
+
+/// A local path to return to after login; anything else (absolute or
+/// protocol-relative URLs) is dropped. Control characters are refused too:
+/// browsers strip tabs and newlines, so `/\t/host` would become `//host`.
+pub(crate) fn safe_next(next: &str) -> Option<&str> {
+    (next.starts_with('/')
+        && !next.starts_with("//")
+        && !next.contains('\\')
+        && !next.contains(|c: char| c.is_ascii_control()))
+    .then_some(next)
 }
 
 async fn post_login(State(state): State<AppState>, Form(form): Form<LoginForm>) -> Response {
@@ -98,14 +150,18 @@ async fn post_login(State(state): State<AppState>, Form(form): Form<LoginForm>)
 
     if ok {
         let token = new_session_token();
-        state
-            .sessions
-            .write()
-            .await
-            .insert(token.clone(), Instant::now());
+        state.sessions.write().await.insert(
+            token.clone(),
+            Session {
+                user: form.username.clone(),
+                last_used: Instant::now(),
+            },
+        );
 
+        // Sent site-wide so private sections (see `access`) know who is
+        // logged in.
         let cookie = format!(
-            "{}={}; Path=/edit; HttpOnly; SameSite=Strict; Max-Age={}; Secure",
+            "{}={}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}; Secure",
             SESSION_COOKIE,
             token,
             SESSION_TTL.as_secs()
@@ -114,13 +170,16 @@ async fn post_login(State(state): State<AppState>, Form(form): Form<LoginForm>)
             StatusCode::SEE_OTHER,
             [
                 (header::SET_COOKIE, cookie),
-                (header::LOCATION, "/edit".to_string()),
+                (
+                    header::LOCATION,
+                    safe_next(&form.next).unwrap_or("/edit").to_string(),
+                ),
             ],
         )
             .into_response()
     } else {
-        Html(template::login_page(Some("Invalid username or password.")).into_string())
-            .into_response()
+        let error = Some("Invalid username or password.");
+        Html(template::login_page(error, safe_next(&form.next)).into_string()).into_response()
     }
 }
 
@@ -129,7 +188,7 @@ async fn post_logout(State(state): State<AppState>, req: Request) -> Response {
         state.sessions.write().await.remove(&tok);
     }
     let clear = format!(
-        "{}=; Path=/edit; HttpOnly; SameSite=Strict; Max-Age=0; Secure",
+        "{}=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0; Secure",
         SESSION_COOKIE
     );
     (
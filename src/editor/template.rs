@@ -1,7 +1,32 @@
 use super::handlers::urlencoded;
-use crate::db::{AnalyticsData, MicropubToken};
+use crate::db::{AnalyticsData, MicropubToken, RevisedFile, Revision, RevisionMeta};
+use crate::git;
+use crate::history::{DiffRow, Merge, RowKind};
+use crate::preview::PreviewLink;
+use crate::wiki::BrokenLink;
+use chrono::{DateTime, Utc};
 use maud::{DOCTYPE, Markup, PreEscaped, html};
 
+/// A file in the media library.
+pub struct MediaFile {
+    /// URL path, e.g. `/_media/2025/01/photo.jpg`.
+    pub url: String,
+    pub name: String,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    pub is_image: bool,
+    /// Number of markdown files that reference it.
+    pub uses: usize,
+}
+
+/// What the sidebar shows on every editor page.
+#[derive(Clone, Copy)]
+pub struct Nav<'a> {
+    pub tree: &'a [FileNode],
+    /// Whether git mode is on, so the git page is linked.
+    pub git: bool,
+}
+
 /// A node in the www-root file tree.
 pub enum FileNode {
     Dir {
@@ -94,13 +119,14 @@ fn hidden_forms_el() -> Markup {
         form id="hidden-rename-form" method="post" action="/edit/rename" style="display:none" {
             input type="hidden" name="old_path" value="";
             input type="hidden" name="new_path" value="";
+            input type="hidden" name="redirect" value="";
         }
     }
 }
 
 // ── Login page ─────────────────────────────────────────────────────────────────
 
-pub fn login_page(error: Option<&str>) -> Markup {
+pub fn login_page(error: Option<&str>, next: Option<&str>) -> Markup {
     shell(
         "Login",
         html! {},
@@ -112,6 +138,9 @@ pub fn login_page(error: Option<&str>) -> Markup {
                     @if let Some(err) = error {
                         p class="error" { (err) }
                     }
+                    @if let Some(next) = next {
+                        input type="hidden" name="next" value=(next);
+                    }
                     div class="form-group" {
                         label for="username" { "Username" }
                         input type="text" id="username" name="username"
@@ -131,13 +160,13 @@ pub fn login_page(error: Option<&str>) -> Markup {
 
 // ── Dashboard ──────────────────────────────────────────────────────────────────
 
-pub fn dashboard(tree: &[FileNode]) -> Markup {
+pub fn dashboard(nav: Nav) -> Markup {
     shell(
         "Dashboard",
         htmx_head(),
         html! {
             div class="layout" {
-                (sidebar(tree, None, false, false))
+                (sidebar(nav, None, Section::Content))
                 main class="main-content" {
                     div class="page-topbar" {
                         button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
@@ -180,7 +209,16 @@ pub fn dashboard(tree: &[FileNode]) -> Markup {
 
 // ── Editor page ────────────────────────────────────────────────────────────────
 
-pub fn editor_page(rel_path: &str, content: &str, tree: &[FileNode]) -> Markup {
+/// `backlinks` are the files linking to this page (markdown files only), so
+/// authors can see what a rename would break.
+pub fn editor_page(
+    rel_path: &str,
+    content: &str,
+    version: &str,
+    nav: Nav,
+    backlinks: Option<&[String]>,
+    hidden: bool,
+) -> Markup {
     shell(
         rel_path,
         html! {
@@ -189,7 +227,7 @@ pub fn editor_page(rel_path: &str, content: &str, tree: &[FileNode]) -> Markup {
         },
         html! {
             div class="layout" {
-                (sidebar(tree, Some(rel_path), false, false))
+                (sidebar(nav, Some(rel_path), Section::Content))
                 main class="main-content editor-main" {
                     div class="editor-toolbar" {
                         button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
@@ -209,6 +247,16 @@ pub fn editor_page(rel_path: &str, content: &str, tree: &[FileNode]) -> Markup {
                             type="button"
                             data-path=(rel_path)
                         { "Rename" }
+                        button
+                            id="toolbar-media"
+                            class="toolbar-btn"
+                            type="button"
+                            title="Insert an image or file"
+                        { "Media" }
+                        a
+                            class="toolbar-btn"
+                            href=(format!("/edit/history?path={}", urlencoded(rel_path)))
+                        { "History" }
                         button
                             id="toolbar-delete"
                             class="toolbar-btn toolbar-btn-danger"
@@ -220,6 +268,33 @@ pub fn editor_page(rel_path: &str, content: &str, tree: &[FileNode]) -> Markup {
                             " Delete"
                         }
                     }
+                    @if let Some(backlinks) = backlinks {
+                        div class="backlinks-bar" {
+                            span class="backlinks-label" { "Linked from" }
+                            @if backlinks.is_empty() {
+                                span class="backlinks-none" { "no pages" }
+                            }
+                            @for file in backlinks {
+                                a class="backlinks-file"
+                                    href=(format!("/edit/open?path={}", urlencoded(file)))
+                                { (file) }
+                            }
+                        }
+                    }
+                    @if hidden {
+                        form class="backlinks-bar preview-link-bar" method="post" action="/edit/preview-link" {
+                            span class="backlinks-label" { "Unpublished" }
+                            input type="hidden" name="path" value=(rel_path);
+                            label class="backlinks-none" for="preview-days" { "Share a preview link valid for" }
+                            select id="preview-days" class="preview-link-days" name="days" {
+                                option value="1" { "1 day" }
+                                option value="7" selected { "7 days" }
+                                option value="30" { "30 days" }
+                            }
+                            button class="settings-save-btn" type="submit" { "Create link" }
+                        }
+                    }
+                    div id="save-conflict" class="save-conflict" {}
                     div class="pane-tabs" {
                         button class="pane-tab active" data-pane="editor" type="button" { "Editor" }
                         button class="pane-tab" data-pane="preview" type="button" { "Preview" }
@@ -228,6 +303,7 @@ pub fn editor_page(rel_path: &str, content: &str, tree: &[FileNode]) -> Markup {
                         div class="pane pane-editor" {
                             form id="editor-form" {
                                 input type="hidden" name="path" value=(rel_path);
+                                input type="hidden" id="editor-version" name="version" value=(version);
                                 textarea
                                     id="editor-content"
                                     name="content"
@@ -247,14 +323,271 @@ pub fn editor_page(rel_path: &str, content: &str, tree: &[FileNode]) -> Markup {
                     }
                 }
             }
+            (media_picker_el())
+            script { (PreEscaped(MEDIA_JS)) }
             (codemirror_init())
         },
     )
 }
 
+/// Response to a successful save: the status, the new version for the next
+/// save, and a cleared conflict panel.
+pub fn saved(version: &str) -> Markup {
+    html! {
+        span id="save-status" class="save-ok" { "Saved" }
+        input type="hidden" id="editor-version" name="version" value=(version) hx-swap-oob="true";
+        div id="save-conflict" class="save-conflict" hx-swap-oob="true" {}
+    }
+}
+
+/// Response to a save made from a stale copy. `base` is the copy the editor
+/// started from, if history has it, with `merge` the merged text; `diff`
+/// compares the file on disk with the rejected content, and `version` is the
+/// file's current ETag, to save over it with.
+pub fn save_conflict(
+    rel_path: &str,
+    base: Option<&Revision>,
+    diff: &[DiffRow],
+    merge: Option<&Merge>,
+    version: &str,
+) -> Markup {
+    html! {
+        span id="save-status" class="save-status save-conflict-status" { "Not saved" }
+        div id="save-conflict" class="save-conflict" hx-swap-oob="true" data-version=(version) {
+            p class="save-conflict-message" {
+                @if let Some(rev) = base {
+                    (rev.author) " changed this file via " (rev.source) " at " (rev.created_at)
+                    " UTC, after you opened it."
+                } @else {
+                    "This file changed after you opened it."
+                }
+                " Your edit has not been saved."
+            }
+            div class="save-conflict-actions" {
+                @if let Some(merge) = merge {
+                    textarea id="conflict-merged" hidden { (merge.text) }
+                    button class="settings-save-btn" type="button" data-conflict-action="merge"
+                        data-conflicts=(merge.conflicts)
+                    { "Merge" }
+                    span class="settings-hint" {
+                        @match merge.conflicts {
+                            0 => "The edits don’t overlap and can be combined.",
+                            1 => "One overlapping change will be marked for you to resolve.",
+                            n => { (n) " overlapping changes will be marked for you to resolve." }
+                        }
+                    }
+                } @else {
+                    span class="settings-hint" {
+                        "Merging needs the version you started from, which isn’t in the history."
+                    }
+                }
+                button class="toolbar-btn toolbar-btn-danger" type="button" data-conflict-action="overwrite" {
+                    "Overwrite with yours"
+                }
+                a class="toolbar-btn" href=(format!("/edit/open?path={}", urlencoded(rel_path))) {
+                    "Discard yours"
+                }
+            }
+            @if !diff.is_empty() {
+                p class="save-conflict-legend" { "Current file ← → yours" }
+                div class="save-conflict-diff" {
+                    table class="diff" {
+                        @for row in diff {
+                            (diff_row(row))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ── Media ──────────────────────────────────────────────────────────────────────
+
+/// The media library: every file under `media_dir`, newest first.
+pub fn media_page(nav: Nav, media_dir: &str, files: &[MediaFile]) -> Markup {
+    shell(
+        "Media",
+        html! {},
+        html! {
+            div class="layout" {
+                (sidebar(nav, None, Section::Media))
+                main class="main-content" {
+                    div class="page-topbar" {
+                        button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
+                            (PreEscaped(HAMBURGER_SVG))
+                        }
+                        span class="topbar-title" { "Media" }
+                    }
+                    div class="settings-page media-page" {
+                        section class="settings-section" {
+                            h3 class="settings-heading" { "Upload" }
+                            div class="settings-group" {
+                                div class="settings-row" {
+                                    input id="media-upload" class="media-upload-input" type="file" multiple
+                                        accept=(MEDIA_ACCEPT);
+                                    span id="media-upload-status" class="settings-hint" {
+                                        "Saved under " code { (media_dir.trim_matches('/')) "/YYYY/MM/" }
+                                        ". You can also drop or paste files into the editor."
+                                    }
+                                }
+                            }
+                        }
+                        section class="settings-section" {
+                            h3 class="settings-heading" { "Library" }
+                            (media_grid(files, false))
+                        }
+                    }
+                }
+            }
+            script { (PreEscaped(MEDIA_JS)) }
+            script { (PreEscaped(r#"
+(function () {
+  var input = document.getElementById('media-upload');
+  var status = document.getElementById('media-upload-status');
+  input.addEventListener('change', function () {
+    var files = Array.prototype.slice.call(input.files);
+    if (!files.length) return;
+    status.textContent = 'Uploading ' + files.length + (files.length === 1 ? ' file…' : ' files…');
+    Promise.all(files.map(window.uploadMedia))
+      .then(function () { location.reload(); })
+      .catch(function (err) { status.textContent = err.message; });
+  });
+})();
+"#)) }
+        },
+    )
+}
+
+/// Thumbnails of `files`, each with a button that copies its markdown or,
+/// when `pick` is set, inserts it into the open editor.
+pub fn media_grid(files: &[MediaFile], pick: bool) -> Markup {
+    html! {
+        @if files.is_empty() {
+            div class="settings-group" {
+                p class="settings-empty" { "No media yet." }
+            }
+        } @else {
+            div class="media-grid" {
+                @for file in files {
+                    div class="media-item" {
+                        a class="media-thumb" href=(file.url) target="_blank" title=(file.url) {
+                            @if file.is_image {
+                                img src=(file.url) alt=(file.name) loading="lazy";
+                            } @else {
+                                span class="media-ext" {
+                                    (file.name.rsplit_once('.').map_or("file", |(_, ext)| ext).to_uppercase())
+                                }
+                            }
+                        }
+                        div class="media-info" {
+                            span class="media-name" title=(file.name) { (file.name) }
+                            span class="media-meta" {
+                                (human_size(file.size)) " · "
+                                @match file.uses {
+                                    0 => "unused",
+                                    1 => "used on 1 page",
+                                    n => { "used on " (n) " pages" }
+                                }
+                            }
+                            @if let Some(modified) = file.modified {
+                                span class="media-meta" { (modified.format("%Y-%m-%d")) }
+                            }
+                        }
+                        @let markdown = media_markdown(&file.name, &file.url, file.is_image);
+                        @if pick {
+                            button class="settings-save-btn media-action" type="button"
+                                data-media-insert=(markdown)
+                            { "Insert" }
+                        } @else {
+                            button class="toolbar-btn media-action" type="button"
+                                data-media-copy=(markdown)
+                            { "Copy markdown" }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The editor's insert-media dialog; its grid is loaded when it opens.
+fn media_picker_el() -> Markup {
+    html! {
+        div id="media-picker" class="modal-overlay" {
+            div class="modal media-picker" {
+                div class="media-picker-head" {
+                    p class="modal-title" { "Insert media" }
+                    label class="toolbar-btn" {
+                        "Upload…"
+                        input id="media-picker-upload" class="media-upload-input" type="file" multiple
+                            accept=(MEDIA_ACCEPT) hidden;
+                    }
+                    button id="media-picker-close" class="btn-secondary" type="button" { "Close" }
+                }
+                div id="media-picker-grid" class="media-picker-grid" {}
+            }
+        }
+    }
+}
+
+const MEDIA_ACCEPT: &str = "image/*,video/*,audio/*,application/pdf";
+
+/// Markdown linking to a media file: an image, or a plain link for other
+/// files.
+pub fn media_markdown(name: &str, url: &str, is_image: bool) -> String {
+    let label = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    if is_image {
+        format!("![{}]({})", label, url)
+    } else {
+        format!("[{}]({})", label, url)
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.0} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// Uploading from the editor and the media page, and the media buttons.
+const MEDIA_JS: &str = r#"
+(function () {
+  // Upload one file to the media directory; resolves to its markdown.
+  window.uploadMedia = function (file) {
+    var body = new FormData();
+    body.append('file', file);
+    return fetch('/edit/media', { method: 'POST', body: body }).then(function (r) {
+      if (!r.ok) {
+        return r.text().then(function (t) { throw new Error(t || 'Upload failed (' + r.status + ')'); });
+      }
+      return r.json();
+    }).then(function (res) { return res.markdown; });
+  };
+
+  document.addEventListener('click', function (e) {
+    var copy = e.target.closest('[data-media-copy]');
+    if (copy) {
+      navigator.clipboard.writeText(copy.dataset.mediaCopy).then(function () {
+        copy.textContent = 'Copied';
+        setTimeout(function () { copy.textContent = 'Copy markdown'; }, 1500);
+      });
+      return;
+    }
+    var insert = e.target.closest('[data-media-insert]');
+    if (insert && window.insertMedia) {
+      window.insertMedia(insert.dataset.mediaInsert);
+      document.getElementById('media-picker').classList.remove('active');
+    }
+  });
+})();
+"#;
+
 // ── Analytics page ─────────────────────────────────────────────────────────────
 
-pub fn analytics_page(tree: &[FileNode], data: &AnalyticsData) -> Markup {
+pub fn analytics_page(nav: Nav, data: &AnalyticsData) -> Markup {
     // Align visitor counts to the same time buckets as the traffic data.
     let visitor_map: std::collections::HashMap<&str, i64> = data
         .visitors_by_period
@@ -272,7 +605,7 @@ pub fn analytics_page(tree: &[FileNode], data: &AnalyticsData) -> Markup {
         chartjs_head(),
         html! {
             div class="layout" {
-                (sidebar(tree, None, true, false))
+                (sidebar(nav, None, Section::Analytics))
                 main class="main-content" {
                     div class="page-topbar" {
                         button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
@@ -517,11 +850,22 @@ fn chartjs_init(data: &AnalyticsData, aligned_visitors: &[i64]) -> Markup {
 
 // ── Settings page ──────────────────────────────────────────────────────────────
 
+/// Values for the settings page's site-wide sections.
+pub struct SiteSettings<'a> {
+    pub backlinks: bool,
+    /// Response header rules, as saved or as just submitted.
+    pub header_rules: &'a str,
+    /// Why the submitted header rules were rejected.
+    pub header_error: Option<&'a str>,
+}
+
 pub fn settings_page(
-    tree: &[FileNode],
+    nav: Nav,
     tokens: &[MicropubToken],
     post_dir: &str,
     media_dir: &str,
+    site: &SiteSettings,
+    previews: &[PreviewLink],
     new_token: Option<&str>,
 ) -> Markup {
     shell(
@@ -529,7 +873,7 @@ pub fn settings_page(
         html! {},
         html! {
             div class="layout" {
-                (sidebar(tree, None, false, true))
+                (sidebar(nav, None, Section::Settings))
                 main class="main-content" {
                     div class="page-topbar" {
                         button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
@@ -552,6 +896,92 @@ pub fn settings_page(
                             }
                         }
 
+                        // ── Site settings ─────────────────────────────────────
+                        section class="settings-section" {
+                            h3 class="settings-heading" { "Site" }
+                            div class="settings-group" {
+                                div class="settings-row" {
+                                    label class="settings-label" for="backlinks" { "Backlinks" }
+                                    form method="post" action="/edit/settings/backlinks"
+                                        class="settings-inline-form"
+                                    {
+                                        input type="hidden" name="value" value="false";
+                                        input id="backlinks" type="checkbox" name="value"
+                                            value="true" checked[site.backlinks];
+                                        span class="settings-hint" {
+                                            "Show “Linked from” on every page unless its front matter sets "
+                                            code { "backlinks: false" }
+                                        }
+                                        button class="settings-save-btn" type="submit" { "Save" }
+                                    }
+                                }
+                            }
+                        }
+
+                        // ── Response headers ──────────────────────────────────
+                        section id="response-headers" class="settings-section" {
+                            h3 class="settings-heading" { "Response Headers" }
+                            div class="settings-group" {
+                                form method="post" action="/edit/settings/headers"
+                                    class="settings-block-form"
+                                {
+                                    p class="settings-hint" {
+                                        "Headers sent with site pages and files, by path: a pattern such as "
+                                        code { "/_media/**" }
+                                        " on its own line, then indented "
+                                        code { "Name: value" }
+                                        " lines. "
+                                        code { "*" }
+                                        " matches within a path segment and "
+                                        code { "**" }
+                                        " across segments. Later blocks override earlier ones."
+                                    }
+                                    @if let Some(err) = site.header_error {
+                                        p class="settings-error" { (err) }
+                                    }
+                                    textarea class="settings-textarea" name="value" rows="10"
+                                        spellcheck="false" { (site.header_rules) }
+                                    div {
+                                        button class="settings-save-btn" type="submit" { "Save" }
+                                    }
+                                }
+                            }
+                        }
+
+                        // ── Draft preview links ───────────────────────────────
+                        section id="preview-links" class="settings-section" {
+                            h3 class="settings-heading" { "Preview Links" }
+                            div class="settings-group" {
+                                @if previews.is_empty() {
+                                    p class="settings-empty" {
+                                        "No active links. Open a draft in the editor to share a preview."
+                                    }
+                                } @else {
+                                    div class="token-list" {
+                                        @for link in previews {
+                                            div class="token-row" {
+                                                div class="token-info" {
+                                                    a class="token-name"
+                                                        href=(format!("/edit/open?path={}", urlencoded(&link.path)))
+                                                    { (link.path) }
+                                                    code class="preview-link-url" { (link.url) }
+                                                    span class="token-meta" {
+                                                        "expires " (link.expires_at.format("%Y-%m-%d %H:%M UTC"))
+                                                    }
+                                                }
+                                                form method="post" action="/edit/settings/preview-link/delete"
+                                                    class="token-revoke-form"
+                                                {
+                                                    input type="hidden" name="id" value=(link.id);
+                                                    button class="token-revoke-btn" type="submit" { "Revoke" }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
                         // ── Micropub settings ─────────────────────────────────
                         section class="settings-section" {
                             h3 class="settings-heading" { "Micropub" }
@@ -637,10 +1067,414 @@ function copyToken() {
     )
 }
 
+// ── Links report ───────────────────────────────────────────────────────────────
+
+pub fn links_page(nav: Nav, broken: &[BrokenLink]) -> Markup {
+    let files = broken
+        .iter()
+        .map(|l| l.file.as_str())
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    shell(
+        "Links",
+        html! {},
+        html! {
+            div class="layout" {
+                (sidebar(nav, None, Section::Links))
+                main class="main-content" {
+                    div class="page-topbar" {
+                        button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
+                            (PreEscaped(HAMBURGER_SVG))
+                        }
+                        span class="topbar-title" { "Links" }
+                    }
+                    div class="settings-page" {
+                        section class="settings-section" {
+                            h3 class="settings-heading" { "Broken wiki links" }
+                            div class="settings-group" {
+                                @if broken.is_empty() {
+                                    p class="settings-empty" { "Every [[wiki link]] resolves to a page." }
+                                } @else {
+                                    p class="settings-empty" {
+                                        (broken.len())
+                                        @if broken.len() == 1 { " broken link" } @else { " broken links" }
+                                        " in " (files)
+                                        @if files == 1 { " file" } @else { " files" }
+                                    }
+                                    @for link in broken {
+                                        div class="settings-row" {
+                                            a class="link-source"
+                                                href=(format!("/edit/open?path={}", urlencoded(&link.file)))
+                                            {
+                                                (link.file) ":" (link.line)
+                                            }
+                                            code class="link-target" { "[[" (link.target) "]]" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+// ── Redirects ──────────────────────────────────────────────────────────────────
+
+/// The `_redirects` rules, editable, and the page aliases from front matter.
+/// `error` is why the submitted rules in `source` were rejected.
+pub fn redirects_page(
+    nav: Nav,
+    source: &str,
+    error: Option<&str>,
+    aliases: &[(String, String)],
+) -> Markup {
+    shell(
+        "Redirects",
+        html! {},
+        html! {
+            div class="layout" {
+                (sidebar(nav, None, Section::Redirects))
+                main class="main-content" {
+                    div class="page-topbar" {
+                        button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
+                            (PreEscaped(HAMBURGER_SVG))
+                        }
+                        span class="topbar-title" { "Redirects" }
+                    }
+                    div class="settings-page" {
+                        section class="settings-section" {
+                            h3 class="settings-heading" { "Redirect rules" }
+                            div class="settings-group" {
+                                form method="post" action="/edit/redirects"
+                                    class="settings-block-form"
+                                {
+                                    p class="settings-hint" {
+                                        "Consulted for paths that would otherwise be 404s, first match first. One rule per line: "
+                                        code { "/old /new" }
+                                        ", optionally followed by a status ("
+                                        code { "301" } ", " code { "302" } ", " code { "307" } ", " code { "308" }
+                                        "), or "
+                                        code { "/old 410" }
+                                        " for a page that is gone. "
+                                        code { ":name" }
+                                        " matches one path segment and a trailing "
+                                        code { "*" }
+                                        " the rest, used in the target as "
+                                        code { ":splat" }
+                                        "."
+                                    }
+                                    @if let Some(err) = error {
+                                        p class="settings-error" { (err) }
+                                    }
+                                    textarea class="settings-textarea" name="value" rows="14"
+                                        spellcheck="false" placeholder="/old-post /blog/new-post" { (source) }
+                                    div {
+                                        button class="settings-save-btn" type="submit" { "Save" }
+                                    }
+                                }
+                            }
+                        }
+                        section class="settings-section" {
+                            h3 class="settings-heading" { "Page aliases" }
+                            div class="settings-group" {
+                                @if aliases.is_empty() {
+                                    p class="settings-empty" {
+                                        "No page lists former URLs. Add them to a page's front matter as "
+                                        code { "aliases: [/old-url]" }
+                                        "."
+                                    }
+                                } @else {
+                                    @for (alias, url) in aliases {
+                                        div class="settings-row" {
+                                            code class="link-target" { (alias) }
+                                            a class="link-source" href=(url) target="_blank" { "→ " (url) }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+// ── History ────────────────────────────────────────────────────────────────────
+
+/// Files with recorded revisions, including deleted ones.
+pub fn history_index(nav: Nav, files: &[RevisedFile]) -> Markup {
+    shell(
+        "History",
+        html! {},
+        html! {
+            div class="layout" {
+                (sidebar(nav, None, Section::History))
+                main class="main-content" {
+                    div class="page-topbar" {
+                        button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
+                            (PreEscaped(HAMBURGER_SVG))
+                        }
+                        span class="topbar-title" { "History" }
+                    }
+                    div class="settings-page" {
+                        section class="settings-section" {
+                            h3 class="settings-heading" { "Recently changed files" }
+                            div class="settings-group" {
+                                @if files.is_empty() {
+                                    p class="settings-empty" {
+                                        "No revisions yet. Saving a file keeps a copy of what it replaced."
+                                    }
+                                }
+                                @for file in files {
+                                    div class="settings-row" {
+                                        a class="link-source"
+                                            href=(format!("/edit/history?path={}", urlencoded(&file.path)))
+                                        { (file.path) }
+                                        span class="link-target" {
+                                            (file.revisions)
+                                            @if file.revisions == 1 { " revision" } @else { " revisions" }
+                                            " · last by " (file.last_author) " · " (file.last_changed) " UTC"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Revisions of one file, with a side-by-side diff of the `selected` one
+/// against the current content.
+pub fn history_page(
+    nav: Nav,
+    rel_path: &str,
+    revisions: &[RevisionMeta],
+    selected: Option<&Revision>,
+    diff: &[DiffRow],
+    exists: bool,
+) -> Markup {
+    shell(
+        &format!("History · {}", rel_path),
+        html! {},
+        html! {
+            div class="layout" {
+                (sidebar(nav, exists.then_some(rel_path), Section::History))
+                main class="main-content" {
+                    div class="page-topbar" {
+                        button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
+                            (PreEscaped(HAMBURGER_SVG))
+                        }
+                        span class="topbar-title" { "History" }
+                        span class="editor-path" { (rel_path) }
+                        @if exists {
+                            a class="toolbar-btn" href=(format!("/edit/open?path={}", urlencoded(rel_path))) {
+                                "Open in editor"
+                            }
+                        }
+                    }
+                    div class="settings-page history-page" {
+                        section class="settings-section" {
+                            h3 class="settings-heading" { "Revisions" }
+                            div class="settings-group" {
+                                @if revisions.is_empty() {
+                                    p class="settings-empty" { "No revisions recorded for this file yet." }
+                                }
+                                @for rev in revisions {
+                                    @let active = selected.is_some_and(|s| s.id == rev.id);
+                                    div class=(if active { "settings-row revision-row active" } else { "settings-row revision-row" }) {
+                                        a class="link-source"
+                                            href=(format!("/edit/history?path={}&rev={}", urlencoded(rel_path), rev.id))
+                                        { (rev.created_at) " UTC" }
+                                        span class="link-target" {
+                                            "replaced by " (rev.author) " via " (rev.source)
+                                            " · " (rev.size) " bytes"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        @if let Some(rev) = selected {
+                            section class="settings-section" {
+                                div class="history-diff-head" {
+                                    h3 class="settings-heading" {
+                                        "Before " (rev.author) "’s " (rev.source) " edit of " (rev.created_at) " UTC → "
+                                        @if exists { "current file" } @else { "deleted" }
+                                    }
+                                    form method="post" action="/edit/history/restore" {
+                                        input type="hidden" name="path" value=(rel_path);
+                                        input type="hidden" name="id" value=(rev.id);
+                                        button class="settings-save-btn" type="submit" { "Restore this revision" }
+                                    }
+                                }
+                                div class="settings-group" {
+                                    @if diff.is_empty() {
+                                        p class="settings-empty" { "Identical to the current file." }
+                                    } @else {
+                                        table class="diff" {
+                                            @for row in diff {
+                                                (diff_row(row))
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+fn diff_row(row: &DiffRow) -> Markup {
+    let class = match row.kind {
+        RowKind::Same => "diff-same",
+        RowKind::Removed => "diff-removed",
+        RowKind::Added => "diff-added",
+        RowKind::Changed => "diff-changed",
+        RowKind::Skipped(_) => "diff-skipped",
+    };
+    html! {
+        tr class=(class) {
+            @if let RowKind::Skipped(lines) = row.kind {
+                td colspan="4" {
+                    "⋯ " (lines) @if lines == 1 { " unchanged line" } @else { " unchanged lines" }
+                }
+            } @else {
+                @match row.old {
+                    Some((n, text)) => { td class="diff-ln" { (n) } td class="diff-old" { (text) } }
+                    None => { td class="diff-ln" {} td class="diff-old diff-empty" {} }
+                }
+                @match row.new {
+                    Some((n, text)) => { td class="diff-ln" { (n) } td class="diff-new" { (text) } }
+                    None => { td class="diff-ln" {} td class="diff-new diff-empty" {} }
+                }
+            }
+        }
+    }
+}
+
+// ── Git ────────────────────────────────────────────────────────────────────────
+
+/// The git repository behind the content and its recent commits; `status` is
+/// `None` when git mode is off. `pull` is the outcome of a pull just made.
+pub fn git_page(
+    nav: Nav,
+    status: Option<&git::Status>,
+    pull: Option<&Result<String, String>>,
+) -> Markup {
+    shell(
+        "Git",
+        html! {},
+        html! {
+            div class="layout" {
+                (sidebar(nav, None, Section::Git))
+                main class="main-content" {
+                    div class="page-topbar" {
+                        button id="sidebar-toggle" class="hamburger" type="button" aria-label="Toggle sidebar" {
+                            (PreEscaped(HAMBURGER_SVG))
+                        }
+                        span class="topbar-title" { "Git" }
+                    }
+                    div class="settings-page" {
+                        @match pull {
+                            Some(Ok(summary)) => p class="git-notice" { (summary) },
+                            Some(Err(e)) => p class="error git-notice" { "Pull failed: " (e) },
+                            None => {}
+                        }
+                        @if let Some(status) = status {
+                            section class="settings-section" {
+                                h3 class="settings-heading" { "Repository" }
+                                div class="settings-group" {
+                                    div class="settings-row" {
+                                        span class="settings-label" { "Working tree" }
+                                        code class="link-target" { (status.workdir.display()) }
+                                    }
+                                    div class="settings-row" {
+                                        span class="settings-label" { "Branch" }
+                                        code class="link-target" { (status.branch) }
+                                    }
+                                    div class="settings-row" {
+                                        span class="settings-label" { "Remote" }
+                                        @if let Some(remote) = &status.remote {
+                                            code class="link-target" { (remote) }
+                                            span class="settings-hint" {
+                                                @if status.pull_interval.is_zero() {
+                                                    "pulled on request"
+                                                } @else {
+                                                    "pulled every " (status.pull_interval.as_secs()) " s"
+                                                }
+                                            }
+                                            form method="post" action="/edit/git/pull" {
+                                                button class="settings-save-btn" type="submit" { "Pull now" }
+                                            }
+                                        } @else {
+                                            span class="settings-hint" {
+                                                "None. Set " code { "--git-remote" } " to pull changes made elsewhere."
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                            section class="settings-section" {
+                                h3 class="settings-heading" { "Commits" }
+                                div class="settings-group" {
+                                    @if status.commits.is_empty() {
+                                        p class="settings-empty" { "No commits yet. Saving a file commits it." }
+                                    }
+                                    @for commit in &status.commits {
+                                        div class="settings-row" {
+                                            code class="git-commit-id" { (commit.id) }
+                                            span class="git-commit-summary" { (commit.summary) }
+                                            span class="link-target" {
+                                                (commit.author) " · " (commit.time.format("%Y-%m-%d %H:%M UTC"))
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        } @else {
+                            section class="settings-section" {
+                                div class="settings-group" {
+                                    p class="settings-empty" {
+                                        "Git mode is off. Start the server with " code { "--git" }
+                                        " to commit every edit to a git repository."
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
 // ── Sidebar ────────────────────────────────────────────────────────────────────
 
-fn sidebar(tree: &[FileNode], active: Option<&str>, analytics_active: bool, settings_active: bool) -> Markup {
-    let content_active = !analytics_active && !settings_active;
+/// Editor section highlighted in the sidebar nav.
+#[derive(PartialEq, Eq)]
+enum Section {
+    Content,
+    Analytics,
+    Links,
+    Redirects,
+    Media,
+    History,
+    Git,
+    Settings,
+}
+
+fn sidebar(nav: Nav, active: Option<&str>, section: Section) -> Markup {
+    let nav_class = |s: Section| if section == s { "snav-link active" } else { "snav-link" };
     html! {
         aside class="sidebar" {
             div class="sidebar-header" {
@@ -650,21 +1484,43 @@ fn sidebar(tree: &[FileNode], active: Option<&str>, analytics_active: bool, sett
                 }
             }
             div class="sidebar-nav" {
-                a href="/edit" class=(if content_active { "snav-link active" } else { "snav-link" }) {
+                a href="/edit" class=(nav_class(Section::Content)) {
                     (PreEscaped(r#"<svg width="13" height="13" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><path d="M13 2H6a2 2 0 0 0-2 2v16a2 2 0 0 0 2 2h12a2 2 0 0 0 2-2V9z"/><polyline points="13 2 13 9 20 9"/></svg>"#))
                     " Content"
                 }
-                a href="/edit/analytics" class=(if analytics_active { "snav-link active" } else { "snav-link" }) {
+                a href="/edit/analytics" class=(nav_class(Section::Analytics)) {
                     (PreEscaped(r#"<svg width="13" height="13" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><line x1="18" y1="20" x2="18" y2="10"/><line x1="12" y1="20" x2="12" y2="4"/><line x1="6" y1="20" x2="6" y2="14"/></svg>"#))
                     " Analytics"
                 }
-                a href="/edit/settings" class=(if settings_active { "snav-link active" } else { "snav-link" }) {
+                a href="/edit/links" class=(nav_class(Section::Links)) {
+                    (PreEscaped(r#"<svg width="13" height="13" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><path d="M10 13a5 5 0 0 0 7.54.54l3-3a5 5 0 0 0-7.07-7.07l-1.72 1.71"/><path d="M14 11a5 5 0 0 0-7.54-.54l-3 3a5 5 0 0 0 7.07 7.07l1.71-1.71"/></svg>"#))
+                    " Links"
+                }
+                a href="/edit/redirects" class=(nav_class(Section::Redirects)) {
+                    (PreEscaped(r#"<svg width="13" height="13" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><polyline points="15 10 20 15 15 20"/><path d="M4 4v7a4 4 0 0 0 4 4h12"/></svg>"#))
+                    " Redirects"
+                }
+                a href="/edit/media" class=(nav_class(Section::Media)) {
+                    (PreEscaped(r#"<svg width="13" height="13" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><rect x="3" y="3" width="18" height="18" rx="2" ry="2"/><circle cx="8.5" cy="8.5" r="1.5"/><polyline points="21 15 16 10 5 21"/></svg>"#))
+                    " Media"
+                }
+                a href="/edit/history" class=(nav_class(Section::History)) {
+                    (PreEscaped(r#"<svg width="13" height="13" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><circle cx="12" cy="12" r="10"/><polyline points="12 6 12 12 16 14"/></svg>"#))
+                    " History"
+                }
+                @if nav.git {
+                    a href="/edit/git" class=(nav_class(Section::Git)) {
+                        (PreEscaped(r#"<svg width="13" height="13" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><circle cx="6" cy="6" r="3"/><circle cx="6" cy="18" r="3"/><circle cx="18" cy="9" r="3"/><path d="M6 9v6"/><path d="M18 12a6 6 0 0 1-6 6H9"/></svg>"#))
+                        " Git"
+                    }
+                }
+                a href="/edit/settings" class=(nav_class(Section::Settings)) {
                     (PreEscaped(r#"<svg width="13" height="13" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><circle cx="12" cy="12" r="3"/><path d="M19.4 15a1.65 1.65 0 0 0 .33 1.82l.06.06a2 2 0 0 1-2.83 2.83l-.06-.06a1.65 1.65 0 0 0-1.82-.33 1.65 1.65 0 0 0-1 1.51V21a2 2 0 0 1-4 0v-.09A1.65 1.65 0 0 0 9 19.4a1.65 1.65 0 0 0-1.82.33l-.06.06a2 2 0 0 1-2.83-2.83l.06-.06A1.65 1.65 0 0 0 4.68 15a1.65 1.65 0 0 0-1.51-1H3a2 2 0 0 1 0-4h.09A1.65 1.65 0 0 0 4.6 9a1.65 1.65 0 0 0-.33-1.82l-.06-.06a2 2 0 0 1 2.83-2.83l.06.06A1.65 1.65 0 0 0 9 4.68a1.65 1.65 0 0 0 1-1.51V3a2 2 0 0 1 4 0v.09a1.65 1.65 0 0 0 1 1.51 1.65 1.65 0 0 0 1.82-.33l.06-.06a2 2 0 0 1 2.83 2.83l-.06.06A1.65 1.65 0 0 0 19.4 9a1.65 1.65 0 0 0 1.51 1H21a2 2 0 0 1 0 4h-.09a1.65 1.65 0 0 0-1.51 1z"/></svg>"#))
                     " Settings"
                 }
             }
             nav class="file-tree" {
-                (render_tree(tree, active))
+                (render_tree(nav.tree, active))
             }
             div class="sidebar-footer" {
                 a href="/" target="_blank" { "View site ↗" }
@@ -865,6 +1721,95 @@ fn codemirror_init() -> Markup {
 
   window.getEditorContent = function () { return cm.getValue(); };
 
+  // ── Media: insert, drop / paste to upload, picker ─────────────────────────
+  window.insertMedia = function (markdown) {
+    cm.replaceSelection(markdown);
+    cm.focus();
+    cm.save();
+    htmx.trigger(ta, 'save-shortcut');
+  };
+
+  var status = document.getElementById('save-status');
+  function uploadAll(files) {
+    files.forEach(function (file) {
+      // A placeholder at the cursor, replaced by the link once uploaded.
+      var placeholder = '![Uploading ' + file.name + '…]()';
+      cm.replaceSelection(placeholder);
+      window.uploadMedia(file).then(function (markdown) {
+        replacePlaceholder(placeholder, markdown);
+        cm.save();
+        htmx.trigger(ta, 'save-shortcut');
+      }).catch(function (err) {
+        replacePlaceholder(placeholder, '');
+        if (status) status.textContent = file.name + ': ' + err.message;
+      });
+    });
+  }
+  function replacePlaceholder(placeholder, text) {
+    var at = cm.getValue().indexOf(placeholder);
+    if (at < 0) return;
+    cm.replaceRange(text, cm.posFromIndex(at), cm.posFromIndex(at + placeholder.length));
+  }
+
+  cm.on('drop', function (cm, e) {
+    var files = Array.prototype.slice.call((e.dataTransfer && e.dataTransfer.files) || []);
+    if (!files.length) return;
+    e.preventDefault();
+    cm.setCursor(cm.coordsChar({ left: e.clientX, top: e.clientY }));
+    uploadAll(files);
+  });
+  cm.on('paste', function (cm, e) {
+    var files = Array.prototype.slice.call((e.clipboardData && e.clipboardData.files) || []);
+    if (!files.length) return;
+    e.preventDefault();
+    uploadAll(files);
+  });
+
+  var picker = document.getElementById('media-picker');
+  var pickerUpload = document.getElementById('media-picker-upload');
+  document.getElementById('toolbar-media').addEventListener('click', function () {
+    picker.classList.add('active');
+    htmx.ajax('GET', '/edit/media?pick=1', '#media-picker-grid');
+  });
+  document.getElementById('media-picker-close').addEventListener('click', function () {
+    picker.classList.remove('active');
+  });
+  picker.addEventListener('click', function (e) {
+    if (e.target === picker) picker.classList.remove('active');
+  });
+  pickerUpload.addEventListener('change', function () {
+    picker.classList.remove('active');
+    uploadAll(Array.prototype.slice.call(pickerUpload.files));
+    pickerUpload.value = '';
+  });
+
+  // A 409 from /edit/save carries the conflict panel; show it.
+  document.body.addEventListener('htmx:beforeSwap', function (e) {
+    if (e.detail.xhr.status === 409) {
+      e.detail.shouldSwap = true;
+      e.detail.isError = false;
+    }
+  });
+
+  document.addEventListener('click', function (e) {
+    var btn = e.target.closest('[data-conflict-action]');
+    if (!btn) return;
+    var panel = document.getElementById('save-conflict');
+    // Save over the file as it is now.
+    document.getElementById('editor-version').value = panel.dataset.version;
+    if (btn.dataset.conflictAction === 'merge') {
+      cm.setValue(document.getElementById('conflict-merged').value);
+      cm.save();
+      if (btn.dataset.conflicts !== '0') {
+        panel.innerHTML = '<p class="save-conflict-message">Merged. Resolve the sections between '
+          + '<code>&lt;&lt;&lt;&lt;&lt;&lt;&lt; yours</code> and <code>&gt;&gt;&gt;&gt;&gt;&gt;&gt; theirs</code>, then save.</p>';
+        return;
+      }
+    }
+    panel.innerHTML = '';
+    htmx.trigger(ta, 'save-shortcut');
+  });
+
   fetchPreview();
 })();
 "#)) }
@@ -1332,8 +2277,53 @@ body.sidebar-open .sidebar-backdrop { display: block; }
   text-overflow: ellipsis;
   min-width: 0;
 }
+.backlinks-bar {
+  padding: 0.4rem 1rem;
+  border-bottom: 1px solid var(--border);
+  background: var(--surface);
+  display: flex;
+  flex-wrap: wrap;
+  align-items: baseline;
+  gap: 0.3rem 0.75rem;
+  flex-shrink: 0;
+}
+.backlinks-label {
+  font-family: 'Syne', sans-serif;
+  font-size: 0.7rem;
+  font-weight: 700;
+  text-transform: uppercase;
+  letter-spacing: 0.1em;
+  color: var(--muted);
+}
+.backlinks-file, .backlinks-none {
+  font-family: 'JetBrains Mono', monospace;
+  font-size: 0.75rem;
+  color: var(--muted);
+}
+.backlinks-file:hover { color: var(--accent); }
+.preview-link-bar { align-items: center; }
+.preview-link-days {
+  background: var(--bg);
+  border: 1px solid var(--border);
+  border-radius: 6px;
+  padding: 0.2rem 0.4rem;
+  font-size: 0.75rem;
+  color: var(--text);
+}
 .save-status { font-family: 'Syne', sans-serif; font-size: 0.75rem; white-space: nowrap; }
 .save-ok { color: var(--success); font-family: 'Syne', sans-serif; font-size: 0.75rem; }
+.save-conflict-status { color: var(--danger); }
+.save-conflict:empty { display: none; }
+.save-conflict {
+  padding: 0.75rem 1rem;
+  border-bottom: 1px solid var(--border);
+  background: rgba(224,85,85,.06);
+  font-size: 0.82rem;
+}
+.save-conflict-message { color: var(--text); margin-bottom: 0.625rem; }
+.save-conflict-actions { display: flex; align-items: center; flex-wrap: wrap; gap: 0.75rem; }
+.save-conflict-legend { margin: 0.75rem 0 0.375rem; color: var(--muted); font-size: 0.75rem; }
+.save-conflict-diff { max-height: 40vh; overflow: auto; border: 1px solid var(--border); border-radius: 6px; }
 
 .toolbar-btn {
   display: flex;
@@ -1620,11 +2610,54 @@ body.sidebar-open .sidebar-backdrop { display: block; }
   transition: border-color 0.15s;
 }
 .settings-input:focus { border-color: var(--accent); }
+.settings-hint {
+  flex: 1;
+  font-size: 0.8rem;
+  color: var(--muted);
+}
+.settings-block-form {
+  display: flex;
+  flex-direction: column;
+  gap: 0.625rem;
+  padding: 0.875rem 1.25rem;
+}
+.settings-textarea {
+  background: var(--surface-2);
+  border: 1px solid var(--border);
+  border-radius: 6px;
+  padding: 0.6rem 0.75rem;
+  font-family: 'JetBrains Mono', monospace;
+  font-size: 0.8rem;
+  line-height: 1.5;
+  color: var(--text);
+  outline: none;
+  resize: vertical;
+  transition: border-color 0.15s;
+}
+.settings-textarea:focus { border-color: var(--accent); }
+.settings-error {
+  font-size: 0.8rem;
+  color: var(--danger);
+}
 .settings-empty {
   padding: 0.875rem 1.25rem;
   font-size: 0.82rem;
   color: var(--muted);
 }
+.link-source {
+  font-family: 'JetBrains Mono', monospace;
+  font-size: 0.8rem;
+  color: var(--text);
+  min-width: 220px;
+  flex-shrink: 0;
+}
+.link-source:hover { color: var(--accent); }
+.link-target {
+  font-family: 'JetBrains Mono', monospace;
+  font-size: 0.8rem;
+  color: var(--muted);
+  word-break: break-all;
+}
 .settings-save-btn {
   background: transparent;
   border: 1px solid var(--border);
@@ -1677,6 +2710,103 @@ body.sidebar-open .sidebar-backdrop { display: block; }
   font-size: 0.72rem;
   color: var(--muted);
 }
+.history-diff-head {
+  display: flex;
+  align-items: center;
+  justify-content: space-between;
+  gap: 1rem;
+}
+.revision-row.active { background: var(--surface); }
+.media-upload-input { font-size: 0.8rem; color: var(--muted); }
+.media-grid {
+  display: grid;
+  grid-template-columns: repeat(auto-fill, minmax(170px, 1fr));
+  gap: 1rem;
+}
+.media-item {
+  display: flex;
+  flex-direction: column;
+  gap: 0.5rem;
+  padding: 0.625rem;
+  background: var(--surface);
+  border: 1px solid var(--border);
+  border-radius: 8px;
+  min-width: 0;
+}
+.media-thumb {
+  display: flex;
+  align-items: center;
+  justify-content: center;
+  aspect-ratio: 4 / 3;
+  background: var(--bg);
+  border-radius: 4px;
+  overflow: hidden;
+}
+.media-thumb img { width: 100%; height: 100%; object-fit: cover; }
+.media-ext { font-family: 'JetBrains Mono', monospace; font-size: 0.8rem; color: var(--muted); }
+.media-info { display: flex; flex-direction: column; gap: 0.125rem; min-width: 0; }
+.media-name {
+  font-family: 'JetBrains Mono', monospace;
+  font-size: 0.75rem;
+  color: var(--text);
+  overflow: hidden;
+  text-overflow: ellipsis;
+  white-space: nowrap;
+}
+.media-meta { font-size: 0.72rem; color: var(--muted); }
+.media-action { justify-content: center; }
+.media-picker { width: 760px; }
+.media-picker-head { display: flex; align-items: center; gap: 0.75rem; margin-bottom: 1rem; }
+.media-picker-head .modal-title { flex: 1; margin: 0; }
+.media-picker-grid { max-height: 60vh; overflow: auto; }
+.git-notice { margin-bottom: 1.5rem; font-size: 0.85rem; color: var(--success); }
+.git-notice.error { color: var(--danger); }
+.git-commit-id {
+  font-family: 'JetBrains Mono', monospace;
+  font-size: 0.8rem;
+  color: var(--accent);
+}
+.git-commit-summary {
+  flex: 1;
+  font-size: 0.85rem;
+  color: var(--text);
+}
+.revision-row.active .link-source { color: var(--accent); }
+.diff {
+  width: 100%;
+  border-collapse: collapse;
+  table-layout: fixed;
+  font-family: 'JetBrains Mono', monospace;
+  font-size: 0.75rem;
+}
+.diff td {
+  padding: 0.1rem 0.5rem;
+  vertical-align: top;
+  white-space: pre-wrap;
+  word-break: break-word;
+}
+.diff .diff-ln {
+  width: 3.5rem;
+  text-align: right;
+  color: var(--muted);
+  user-select: none;
+}
+.diff-removed .diff-old, .diff-changed .diff-old { background: #3a1515; }
+.diff-added .diff-new, .diff-changed .diff-new { background: #13301a; }
+.diff-empty { background: var(--surface); }
+.diff-skipped td {
+  padding: 0.3rem 0.75rem;
+  color: var(--muted);
+  background: var(--surface);
+  font-family: 'Syne', sans-serif;
+}
+.preview-link-url {
+  font-family: 'JetBrains Mono', monospace;
+  font-size: 0.72rem;
+  color: var(--text);
+  word-break: break-all;
+  user-select: all;
+}
 .token-revoke-form { flex-shrink: 0; }
 .token-revoke-btn {
   background: transparent;
@@ -1828,6 +2958,12 @@ const UI_JS: &str = r#"
     form.submit();
   }
 
+  // Offer to keep the old address working once something has moved.
+  function askRedirect(from, to) {
+    if (from === to) return '';
+    return confirm('Redirect the old URL of "' + from + '" to its new one?') ? 'true' : '';
+  }
+
   // ── Dashboard actions (exposed as globals) ─────────────────────────────────
   window.showNewFileModal = function () {
     showModal('New file', 'untitled.md', function (val) {
@@ -1897,7 +3033,9 @@ const UI_JS: &str = r#"
         });
       } else if (action === 'rename') {
         showModal('Rename / move', path, function (val) {
-          submitForm('hidden-rename-form', { old_path: path, new_path: val });
+          submitForm('hidden-rename-form', {
+            old_path: path, new_path: val, redirect: askRedirect(path, val),
+          });
         });
       } else if (action === 'delete') {
         if (confirm('Delete "' + path + '"? This cannot be undone.')) {
@@ -1956,7 +3094,9 @@ const UI_JS: &str = r#"
     renameBtn.addEventListener('click', function () {
       var path = renameBtn.dataset.path;
       showModal('Rename / move', path, function (val) {
-        submitForm('hidden-rename-form', { old_path: path, new_path: val });
+        submitForm('hidden-rename-form', {
+          old_path: path, new_path: val, redirect: askRedirect(path, val),
+        });
       });
     });
   }
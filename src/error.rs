@@ -8,6 +8,8 @@ use maud::{html, DOCTYPE};
 pub enum AppError {
     #[error("Not found")]
     NotFound,
+    #[error("Gone")]
+    Gone,
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("{0}")]
@@ -22,6 +24,11 @@ impl IntoResponse for AppError {
                 "404 Not Found",
                 "The page you requested could not be found.".to_string(),
             ),
+            AppError::Gone => (
+                StatusCode::GONE,
+                "410 Gone",
+                "The page you requested has been removed.".to_string(),
+            ),
             AppError::Io(e) => {
                 tracing::error!("IO error: {}", e);
                 (
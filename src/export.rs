@@ -0,0 +1,361 @@
+//! `md-server export --out <dir>`: pre-render the whole site into plain files
+//! for static hosting.
+//!
+//! Every URL is rendered by calling `handler::handle` exactly as a request
+//! would, so the output matches what the server sends. Clean URLs become
+//! `dir/index.html` files, listing pages `?page=N` become `page/N/`, and
+//! drafts (which the handler 404s) are skipped, as is everything in a
+//! section restricted by `_access.yml`, static files included.
+
+use anyhow::{Context, Result, bail};
+use axum::{
+    body,
+    extract::State,
+    http::{HeaderMap, StatusCode, Uri},
+    response::IntoResponse,
+};
+use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    access,
+    error::AppError,
+    handler, redirects,
+    rss::FeedFormat,
+    state::AppState,
+    tags,
+};
+
+/// Characters escaped when turning a filesystem path into a request URI.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+#[derive(Default)]
+pub struct ExportStats {
+    pub pages: usize,
+    pub files: usize,
+}
+
+/// Render the site served from `state` into `out`, creating it if needed.
+/// Existing files in `out` are overwritten but never deleted.
+pub async fn run(state: &AppState, out: &Path) -> Result<ExportStats> {
+    tokio::fs::create_dir_all(out)
+        .await
+        .with_context(|| format!("Cannot create {}", out.display()))?;
+    let out = tokio::fs::canonicalize(out).await?;
+    if out.starts_with(&state.canonical_root) {
+        bail!("Output directory must be outside the www root");
+    }
+
+    let mut exporter = Exporter {
+        state,
+        out,
+        stats: ExportStats::default(),
+    };
+
+    let mut stack = vec![state.canonical_root.clone()];
+    while let Some(dir) = stack.pop() {
+        let rel = handler::rel_path(&state.canonical_root, &dir);
+        let dir_url = if rel.is_empty() {
+            String::from("/")
+        } else {
+            format!("/{}/", rel)
+        };
+        // A subdirectory can open its section up again, so keep descending.
+        let public = access::is_public(state, &dir_url).await;
+        if public {
+            exporter.export_listing(&dir_url).await?;
+        }
+
+        let mut read_dir = tokio::fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("Cannot read {}", dir.display()))?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                stack.push(entry.path());
+            } else if file_type.is_file() && public {
+                exporter.export_file(&dir, &dir_url, &name).await?;
+            }
+        }
+    }
+
+    // Generated tag pages, unless the tree has its own `tags/`.
+    if !tokio::fs::try_exists(state.canonical_root.join("tags"))
+        .await
+        .unwrap_or(false)
+    {
+        exporter.export_listing(tags::TAGS_PREFIX).await?;
+        for url in tags::tag_urls(state).await {
+            exporter.export_listing(&decode(&url)).await?;
+        }
+    }
+
+    // Sitemap (with its parts, for large sites) and robots.txt. Without
+    // `--base-url` there is no sitemap, and robots.txt doesn't mention one.
+    for url in ["/sitemap.xml", "/robots.txt"] {
+        exporter.export_file_url(url).await?;
+    }
+    for part in 1.. {
+        if !exporter.export_file_url(&format!("/sitemap-{}.xml", part)).await? {
+            break;
+        }
+    }
+
+    // Most static hosts serve this for missing paths.
+    let not_found = AppError::NotFound.into_response();
+    let bytes = body::to_bytes(not_found.into_body(), usize::MAX).await?;
+    exporter.write("404.html", &bytes).await?;
+
+    // Hosts such as Netlify and Cloudflare Pages read this themselves.
+    let redirects = redirects::with_aliases(state).await;
+    if !redirects.is_empty() {
+        exporter
+            .write(redirects::REDIRECTS_FILE, redirects.as_bytes())
+            .await?;
+        exporter.stats.files += 1;
+    }
+
+    Ok(exporter.stats)
+}
+
+struct Exporter<'a> {
+    state: &'a AppState,
+    out: PathBuf,
+    stats: ExportStats,
+}
+
+impl Exporter<'_> {
+    /// A directory (or generated) listing URL ending in `/`, not
+    /// percent-encoded: its page(s) and feeds. Directories with an `index.md`
+    /// render that page and have no feeds, which the handler reports as 404s.
+    async fn export_listing(&mut self, dir_url: &str) -> Result<()> {
+        let Some(html) = self.fetch(dir_url).await? else {
+            return Ok(());
+        };
+        let dir_path = dir_url.trim_start_matches('/');
+        let mut html = String::from_utf8_lossy(&html).into_owned();
+        let mut page = 1;
+        loop {
+            let path = match page {
+                1 => format!("{}index.html", dir_path),
+                n => format!("{}page/{}/index.html", dir_path, n),
+            };
+            self.write(&path, rewrite_page_links(&html, dir_url).as_bytes())
+                .await?;
+            self.stats.pages += 1;
+
+            // Follow the pager rather than probing, since not every listing
+            // is paginated.
+            if !html.contains("rel=\"next\"") {
+                break;
+            }
+            page += 1;
+            match self.fetch(&format!("{}?page={}", dir_url, page)).await? {
+                Some(next) => html = String::from_utf8_lossy(&next).into_owned(),
+                None => break,
+            }
+        }
+
+        for format in FeedFormat::ALL {
+            let url = format!("{}{}", dir_url, format.file_name());
+            if let Some(feed) = self.fetch(&url).await? {
+                self.write(&format!("{}{}", dir_path, format.file_name()), &feed)
+                    .await?;
+                self.stats.files += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// A file directly inside `dir`: markdown is rendered to
+    /// `<stem>/index.html`, static assets are copied as-is, anything else the
+    /// server wouldn't serve is skipped.
+    async fn export_file(&mut self, dir: &Path, dir_url: &str, name: &str) -> Result<()> {
+        let dir_path = dir_url.trim_start_matches('/');
+
+        if let Some(stem) = name.strip_suffix(".md") {
+            // index.md is the directory's own page; a same-named directory
+            // shadows the file, as it does when serving.
+            if stem == "index" || dir.join(stem).is_dir() {
+                return Ok(());
+            }
+            let url = format!("{}{}", dir_url, stem);
+            if let Some(html) = self.fetch(&url).await? {
+                // The page moves one level down (`/a/b` → `/a/b/`), so
+                // relative links must be pinned to where they pointed before.
+                let html = absolutize_links(&String::from_utf8_lossy(&html), dir_url);
+                self.write(&format!("{}{}/index.html", dir_path, stem), html.as_bytes())
+                    .await?;
+                self.stats.pages += 1;
+            }
+            return Ok(());
+        }
+
+        let is_static = Path::new(name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| handler::STATIC_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+        if is_static {
+            let dest = self.out.join(dir_path).join(name);
+            if let Some(parent) = dest.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::copy(dir.join(name), &dest)
+                .await
+                .with_context(|| format!("Cannot copy {}", dest.display()))?;
+            self.stats.files += 1;
+        }
+        Ok(())
+    }
+
+    /// A generated file served at `url`, written to the same path. Returns
+    /// whether it exists.
+    async fn export_file_url(&mut self, url: &str) -> Result<bool> {
+        let Some(bytes) = self.fetch(url).await? else {
+            return Ok(false);
+        };
+        self.write(url.trim_start_matches('/'), &bytes).await?;
+        self.stats.files += 1;
+        Ok(true)
+    }
+
+    /// Render `url` (path not percent-encoded, optionally with a query)
+    /// through the request handler. Returns `None` for anything but a 200
+    /// (drafts, redirects, missing feeds).
+    async fn fetch(&self, url: &str) -> Result<Option<body::Bytes>> {
+        let (path, query) = match url.split_once('?') {
+            Some((path, query)) => (path, format!("?{}", query)),
+            None => (url, String::new()),
+        };
+        let uri: Uri = format!("{}{}", utf8_percent_encode(path, PATH_SEGMENT), query)
+            .parse()
+            .with_context(|| format!("Invalid URL {}", url))?;
+        let response =
+            match handler::handle(State(self.state.clone()), HeaderMap::new(), uri).await {
+                Ok(r) => r,
+                Err(AppError::NotFound) => return Ok(None),
+                Err(e) => bail!("Cannot render {}: {}", url, e),
+            };
+        if response.status() != StatusCode::OK {
+            return Ok(None);
+        }
+        Ok(Some(body::to_bytes(response.into_body(), usize::MAX).await?))
+    }
+
+    async fn write(&self, rel: &str, bytes: &[u8]) -> Result<()> {
+        let dest = self.out.join(rel);
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&dest, bytes)
+            .await
+            .with_context(|| format!("Cannot write {}", dest.display()))
+    }
+}
+
+fn decode(s: &str) -> String {
+    percent_encoding::percent_decode_str(s)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Point `?page=N` links of the listing at `dir_url` to the exported
+/// `page/N/` directories.
+fn rewrite_page_links(html: &str, dir_url: &str) -> String {
+    let needle = format!("href=\"{}?page=", dir_url);
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(pos) = rest.find(&needle) {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos + needle.len()..];
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        out.push_str(&format!("href=\"{}page/{}/", dir_url, &rest[..digits]));
+        rest = &rest[digits..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Resolve relative `href` / `src` attribute values against `base_dir` (the
+/// URL directory the page was served from) so they survive the page moving
+/// into its own directory.
+fn absolutize_links(html: &str, base_dir: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    loop {
+        let next = ["href=\"", "src=\""]
+            .iter()
+            .filter_map(|attr| rest.find(attr).map(|pos| pos + attr.len()))
+            .min();
+        let Some(start) = next else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find('"').unwrap_or(rest.len());
+        let value = &rest[..end];
+        if is_relative(value) {
+            out.push_str(&resolve(base_dir, value));
+        } else {
+            out.push_str(value);
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A path-relative reference: not absolute, not a fragment or query, and
+/// without a scheme.
+fn is_relative(url: &str) -> bool {
+    if url.is_empty() || url.starts_with(['/', '#', '?']) {
+        return false;
+    }
+    let scheme_end = url.find([':', '/', '?', '#']);
+    !matches!(scheme_end, Some(i) if url.as_bytes()[i] == b':')
+}
+
+/// Join a relative reference onto `base_dir` (ending in `/`), collapsing
+/// `.` and `..` segments.
+fn resolve(base_dir: &str, rel: &str) -> String {
+    let (path, suffix) = match rel.find(['?', '#']) {
+        Some(i) => rel.split_at(i),
+        None => (rel, ""),
+    };
+    let mut segments: Vec<&str> = base_dir.split('/').filter(|s| !s.is_empty()).collect();
+    for part in path.split('/') {
+        match part {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            "" => {}
+            part => segments.push(part),
+        }
+    }
+    let trailing = if path.ends_with('/') || path.ends_with("/.") || path.ends_with("/..") {
+        "/"
+    } else {
+        ""
+    };
+    let mut url = format!("/{}", segments.join("/"));
+    if !segments.is_empty() {
+        url.push_str(trailing);
+    }
+    url.push_str(suffix);
+    url
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
@@ -12,10 +12,44 @@ pub struct FrontMatter {
     pub author: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub date: Option<String>,
+    /// When the page goes live, if later than `date`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub draft: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tags: Option<Vec<String>>,
+    /// Show a table of contents in the page sidebar.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toc: Option<bool>,
+    /// List the pages linking here; overrides the site setting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backlinks: Option<bool>,
+    /// Former URLs of the page, redirected to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<String>>,
+    /// `date` was filled in by `fill_inferred` rather than written in the
+    /// file. An inferred date is a local calendar day, so it never schedules
+    /// the page.
+    #[serde(skip)]
+    pub date_inferred: bool,
+}
+
+impl FrontMatter {
+    /// When the page is scheduled to go live: `publish_at`, or else a `date`
+    /// written in the front matter.
+    pub fn publish_time(&self) -> Option<DateTime<FixedOffset>> {
+        self.publish_at
+            .as_deref()
+            .or(self.date.as_deref().filter(|_| !self.date_inferred))
+            .and_then(parse_date)
+    }
+
+    /// Drafts, and pages whose publish time is still in the future, are not
+    /// served, listed, indexed or syndicated.
+    pub fn is_hidden(&self) -> bool {
+        self.draft == Some(true) || self.publish_time().is_some_and(|t| t > Utc::now())
+    }
 }
 
 /// Serialize a FrontMatter struct + markdown content back into a complete .md file string.
@@ -107,6 +141,7 @@ pub async fn fill_inferred(fm: &mut FrontMatter, content: &str, path: &Path) {
     }
     if fm.date.is_none() {
         fm.date = infer_date(path).await;
+        fm.date_inferred = fm.date.is_some();
     }
     if fm.draft.is_none() {
         fm.draft = Some(false);
@@ -205,3 +240,21 @@ pub async fn infer_date(path: &Path) -> Option<String> {
     let dt: DateTime<Local> = sys_time.into();
     Some(dt.format("%Y-%m-%d").to_string())
 }
+
+/// Parse a front matter date: RFC 3339, `YYYY-MM-DD HH:MM[:SS]` (taken as
+/// UTC), or a bare `YYYY-MM-DD` (midnight UTC).
+pub fn parse_date(s: &str) -> Option<DateTime<FixedOffset>> {
+    let s = s.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt);
+    }
+    for fmt in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Some(dt.and_utc().fixed_offset());
+        }
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc().fixed_offset())
+}
@@ -0,0 +1,317 @@
+//! Git-backed content: commit every write made through the editor or
+//! Micropub, and optionally pull from a remote on a timer.
+//!
+//! With `--git`, the repository containing the www root (created there if
+//! there is none) gets one commit per editor save, new file, rename, delete
+//! or restore and per Micropub create, update or delete, authored by the
+//! editor user or Micropub token that made it. Only the paths the write
+//! touched are staged, so unrelated changes in the working tree are left
+//! alone. With `--git-remote`, the current branch is fetched from that remote
+//! (a configured remote name or a path, such as a local bare repository)
+//! every `--git-pull-interval` seconds and fast-forwarded or merged; a merge
+//! with conflicts is abandoned and logged. The file watcher picks up whatever
+//! a pull changes.
+//!
+//! All repository work runs on the blocking pool, one operation at a time.
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Utc};
+use git2::{
+    FetchOptions, IndexAddOption, MergeAnalysis, Oid, Repository, Signature, build::CheckoutBuilder,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::task::JoinSet;
+
+use crate::state::AppState;
+
+/// Settings for git mode, set from the command line.
+#[derive(Debug, Clone)]
+pub struct GitConfig {
+    /// Remote name or path to pull from.
+    pub remote: Option<String>,
+    /// Time between pulls; zero pulls only when asked to from the editor.
+    pub pull_interval: Duration,
+}
+
+/// The repository git mode commits to, kept in `AppState::git`.
+pub struct GitRepo {
+    /// Working directory of the repository.
+    workdir: PathBuf,
+    config: GitConfig,
+    /// Serialises access to the repository and its index.
+    lock: Mutex<()>,
+}
+
+/// Email for signatures that have none: editor users and Micropub tokens
+/// only have names.
+const NO_EMAIL: &str = "md-server@localhost";
+
+/// Open the repository containing `www_root` for git mode, creating one
+/// there if it isn't in a working tree yet.
+pub fn open(www_root: &Path, config: GitConfig) -> Result<GitRepo> {
+    let root = www_root
+        .canonicalize()
+        .with_context(|| format!("Cannot resolve {}", www_root.display()))?;
+    let repo = match Repository::discover(&root) {
+        Ok(repo) => repo,
+        Err(_) => {
+            tracing::info!("Creating git repository in {}", root.display());
+            Repository::init(&root).context("Cannot create git repository")?
+        }
+    };
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("{} is inside a bare git repository", root.display()))?
+        .canonicalize()?;
+    tracing::info!(
+        "Committing content changes to git repository {}",
+        workdir.display()
+    );
+    Ok(GitRepo {
+        workdir,
+        config,
+        lock: Mutex::new(()),
+    })
+}
+
+// ── Committing ────────────────────────────────────────────────────────────────
+
+/// Commit the current state of `paths` (files or directories, which may no
+/// longer exist) as `author`. Does nothing unless git mode is on; failures
+/// are logged, never returned, so a write never fails because of git.
+pub async fn commit(state: &AppState, paths: &[&Path], author: &str, message: String) {
+    let Some(repo) = state.git.clone() else {
+        return;
+    };
+    let paths: Vec<PathBuf> = paths.iter().map(|p| p.to_path_buf()).collect();
+    let author = author.to_string();
+    let result = tokio::task::spawn_blocking(move || {
+        let _guard = repo.lock.lock().unwrap_or_else(|e| e.into_inner());
+        commit_blocking(&repo, &paths, &author, &message)
+    })
+    .await;
+    match result {
+        Ok(Ok(Some(id))) => tracing::debug!("Committed {}", id),
+        Ok(Ok(None)) => {}
+        Ok(Err(e)) => tracing::warn!("Git commit failed: {e:#}"),
+        Err(e) => tracing::error!("Git commit task failed: {e}"),
+    }
+}
+
+/// Stage `paths` and commit them onto HEAD. `None` when nothing changed.
+fn commit_blocking(
+    git: &GitRepo,
+    paths: &[PathBuf],
+    author: &str,
+    message: &str,
+) -> Result<Option<Oid>> {
+    let repo = Repository::open(&git.workdir)?;
+    let mut index = repo.index()?;
+    for path in paths {
+        let rel = path
+            .strip_prefix(&git.workdir)
+            .with_context(|| format!("{} is outside the repository", path.display()))?;
+        let spec = rel.to_string_lossy().into_owned();
+        // `add_all` stages new and changed files, `update_all` removals.
+        index.add_all([&spec], IndexAddOption::DEFAULT, None)?;
+        index.update_all([&spec], None)?;
+    }
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+        Err(e) => return Err(e.into()),
+    };
+    if parent.as_ref().is_some_and(|p| p.tree_id() == tree.id()) {
+        return Ok(None);
+    }
+
+    let author = Signature::now(author, NO_EMAIL)?;
+    let committer = committer(&repo)?;
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    let id = repo.commit(Some("HEAD"), &author, &committer, message, &tree, &parents)?;
+    Ok(Some(id))
+}
+
+/// The repository's configured identity, or a stand-in.
+fn committer(repo: &Repository) -> Result<Signature<'static>> {
+    repo.signature()
+        .or_else(|_| Signature::now("md-server", NO_EMAIL))
+        .map_err(Into::into)
+}
+
+// ── Pulling ───────────────────────────────────────────────────────────────────
+
+/// Pull from the configured remote every `--git-pull-interval`.
+pub fn spawn_puller(state: &AppState, tasks: &mut JoinSet<()>) {
+    let Some(repo) = &state.git else {
+        return;
+    };
+    if repo.config.remote.is_none() || repo.config.pull_interval.is_zero() {
+        return;
+    }
+    let period = repo.config.pull_interval;
+    let state = state.clone();
+    tasks.spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = pull(&state).await {
+                tracing::warn!("Git pull failed: {e:#}");
+            }
+        }
+    });
+}
+
+/// Fetch the current branch from the remote and merge it. Returns a short
+/// description of what happened.
+pub async fn pull(state: &AppState) -> Result<String> {
+    let repo = state.git.clone().context("Git mode is off")?;
+    tokio::task::spawn_blocking(move || {
+        let _guard = repo.lock.lock().unwrap_or_else(|e| e.into_inner());
+        pull_blocking(&repo)
+    })
+    .await?
+}
+
+fn pull_blocking(git: &GitRepo) -> Result<String> {
+    let name = git
+        .config
+        .remote
+        .as_deref()
+        .context("No --git-remote set")?;
+    let repo = Repository::open(&git.workdir)?;
+    let branch_ref = repo
+        .find_reference("HEAD")?
+        .symbolic_target()
+        .context("HEAD is detached")?
+        .to_string();
+    let branch = branch_ref
+        .strip_prefix("refs/heads/")
+        .unwrap_or(&branch_ref);
+
+    let mut remote = repo
+        .find_remote(name)
+        .or_else(|_| repo.remote_anonymous(name))?;
+    remote.fetch(&[branch_ref.as_str()], Some(&mut FetchOptions::new()), None)?;
+    let fetched = repo.find_reference("FETCH_HEAD")?;
+    let theirs = repo.reference_to_annotated_commit(&fetched)?;
+
+    let (analysis, _) = repo.merge_analysis(&[&theirs])?;
+    let summary = if analysis.contains(MergeAnalysis::ANALYSIS_UP_TO_DATE) {
+        return Ok(format!("Already up to date with {} {}", name, branch));
+    } else if analysis.contains(MergeAnalysis::ANALYSIS_UNBORN) {
+        repo.reference(&branch_ref, theirs.id(), true, "md-server: pull")?;
+        repo.checkout_head(Some(CheckoutBuilder::new().safe()))?;
+        format!("Checked out {} {}", name, branch)
+    } else if analysis.contains(MergeAnalysis::ANALYSIS_FASTFORWARD) {
+        let target = repo.find_object(theirs.id(), None)?;
+        repo.checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+        repo.find_reference(&branch_ref)?
+            .set_target(theirs.id(), "md-server: fast-forward")?;
+        format!(
+            "Fast-forwarded to {} {} ({})",
+            name,
+            branch,
+            short(theirs.id())
+        )
+    } else {
+        let ours = repo.head()?.peel_to_commit()?;
+        let their_commit = repo.find_commit(theirs.id())?;
+        let mut index = repo.merge_commits(&ours, &their_commit, None)?;
+        if index.has_conflicts() {
+            bail!("{} {} conflicts with local changes; merge it by hand", name, branch);
+        }
+        let tree = repo.find_tree(index.write_tree_to(&repo)?)?;
+        repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))?;
+        let signature = committer(&repo)?;
+        let message = format!("Merge {} {}", name, branch);
+        let id = repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message,
+            &tree,
+            &[&ours, &their_commit],
+        )?;
+        // Bring the index in line with the merged tree.
+        let mut repo_index = repo.index()?;
+        repo_index.read_tree(&tree)?;
+        repo_index.write()?;
+        format!("Merged {} {} ({})", name, branch, short(id))
+    };
+    tracing::info!("Git: {}", summary);
+    Ok(summary)
+}
+
+// ── Log ───────────────────────────────────────────────────────────────────────
+
+/// A commit, for the editor's log page.
+pub struct CommitInfo {
+    pub id: String,
+    pub summary: String,
+    pub author: String,
+    pub time: DateTime<Utc>,
+}
+
+/// What the editor's git page shows.
+pub struct Status {
+    pub workdir: PathBuf,
+    pub branch: String,
+    pub remote: Option<String>,
+    pub pull_interval: Duration,
+    pub commits: Vec<CommitInfo>,
+}
+
+/// Repository details and the newest `limit` commits on HEAD; `None` unless
+/// git mode is on.
+pub async fn status(state: &AppState, limit: usize) -> Option<Result<Status>> {
+    let repo = state.git.clone()?;
+    let result = tokio::task::spawn_blocking(move || {
+        let _guard = repo.lock.lock().unwrap_or_else(|e| e.into_inner());
+        status_blocking(&repo, limit)
+    })
+    .await;
+    Some(result.map_err(Into::into).and_then(|r| r))
+}
+
+fn status_blocking(git: &GitRepo, limit: usize) -> Result<Status> {
+    let repo = Repository::open(&git.workdir)?;
+    let head = repo.find_reference("HEAD")?;
+    let branch = head
+        .symbolic_target()
+        .map(|t| t.strip_prefix("refs/heads/").unwrap_or(t).to_string())
+        .unwrap_or_else(|| String::from("(detached)"));
+
+    let mut commits = Vec::new();
+    if repo.head().is_ok() {
+        let mut walk = repo.revwalk()?;
+        walk.push_head()?;
+        for id in walk.take(limit) {
+            let commit = repo.find_commit(id?)?;
+            commits.push(CommitInfo {
+                id: short(commit.id()),
+                summary: commit.summary().unwrap_or("").to_string(),
+                author: commit.author().name().unwrap_or("").to_string(),
+                time: DateTime::from_timestamp(commit.time().seconds(), 0).unwrap_or_default(),
+            });
+        }
+    }
+    Ok(Status {
+        workdir: git.workdir.clone(),
+        branch,
+        remote: git.config.remote.clone(),
+        pull_interval: git.config.pull_interval,
+        commits,
+    })
+}
+
+fn short(id: Oid) -> String {
+    id.to_string()[..7].to_string()
+}
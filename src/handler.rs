@@ -1,28 +1,61 @@
 use axum::{
     body::Body,
     extract::State,
-    http::{StatusCode, Uri, header},
+    http::{HeaderMap, StatusCode, Uri, header},
     response::{Html, IntoResponse, Redirect, Response},
 };
-use std::{io, path::Path};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
 use tokio_util::io::ReaderStream;
 
 use crate::{
+    access::{self, Access},
+    backlinks,
+    cache::{CachedPage, DirAssets},
+    compress, conditional,
     css::{find_css, find_meta_image},
     error::AppError,
     front_matter::{self, ParsedDoc},
-    rss,
-    state::AppState,
+    highlight, images,
+    layout, preview,
+    range::{self, RangeRequest},
+    redirects, rss,
+    sitemap,
+    state::{AppState, RenderConfig},
+    tags,
     template::{self, DirEntry},
+    toc,
+    wiki,
 };
 
 /// File extensions served as static pass-throughs (not converted to HTML).
-const STATIC_EXTENSIONS: &[&str] = &[
+pub(crate) const STATIC_EXTENSIONS: &[&str] = &[
     "css", "js", "mjs", "png", "jpg", "jpeg", "gif", "svg", "webp", "ico", "avif", "woff", "woff2",
     "ttf", "otf", "eot", "txt", "pdf", "mp4", "webm", "mp3", "ogg", "wav",
 ];
 
-pub async fn handle(State(state): State<AppState>, uri: Uri) -> Result<Response, AppError> {
+pub async fn handle(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Result<Response, AppError> {
+    let response = match route(&state, &headers, &uri).await {
+        // Moved and removed pages: `_redirects` and front matter aliases.
+        Err(AppError::NotFound) => {
+            return redirects::find(&state, uri.path(), uri.query())
+                .await
+                .ok_or(AppError::NotFound);
+        }
+        result => result?,
+    };
+    Ok(conditional::apply(&headers, response))
+}
+
+async fn route(state: &AppState, headers: &HeaderMap, uri: &Uri) -> Result<Response, AppError> {
     let raw_path = uri.path();
 
     // Decode percent-encoded characters; reject if the path is not valid UTF-8.
@@ -41,20 +74,87 @@ pub async fn handle(State(state): State<AppState>, uri: Uri) -> Result<Response,
         return Err(AppError::NotFound);
     }
 
+    // Rule files may hold section passwords, and `_redirects` is read by the
+    // server; never serve them.
+    if fs_path
+        .file_name()
+        .is_some_and(|n| n == access::ACCESS_FILE || n == redirects::REDIRECTS_FILE)
+    {
+        return Err(AppError::NotFound);
+    }
+
+    // Private sections (`_access.yml`) apply to everything below them.
+    let private = match access::check(state, headers, &fs_path, &decoded).await {
+        Access::Public => false,
+        Access::Granted => true,
+        Access::Denied(response) => return Ok(response),
+    };
+    let mut response = dispatch(state, headers, uri, &decoded, &fs_path).await?;
+    if private {
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            header::HeaderValue::from_static("private, no-cache"),
+        );
+    }
+    Ok(response)
+}
+
+/// Serve an already-validated request for `decoded` (mapped to `fs_path`).
+async fn dispatch(
+    state: &AppState,
+    headers: &HeaderMap,
+    uri: &Uri,
+    decoded: &str,
+    fs_path: &Path,
+) -> Result<Response, AppError> {
+    let raw_path = uri.path();
+    let rel = decoded.trim_start_matches('/');
+
+    // Generated sitemap and robots.txt, unless the content tree has its own.
+    let is_sitemap = decoded == "/sitemap.xml" || sitemap::part_number(decoded).is_some();
+    if is_sitemap || decoded == "/robots.txt" {
+        if tokio::fs::try_exists(fs_path).await.unwrap_or(false) {
+            return serve_static(state, fs_path, headers, None).await;
+        }
+        return match sitemap::part_number(decoded) {
+            Some(part) => sitemap::serve_part(state, part).await,
+            None if is_sitemap => sitemap::serve_sitemap(state).await,
+            None => sitemap::serve_robots(state),
+        };
+    }
+
+    // Generated tag pages, unless the content tree has its own `tags/`.
+    let tags_root = tags::TAGS_PREFIX.trim_end_matches('/');
+    if (decoded == tags_root || decoded.starts_with(tags::TAGS_PREFIX))
+        && !tokio::fs::try_exists(state.www_root.join(tags_root.trim_start_matches('/')))
+            .await
+            .unwrap_or(false)
+    {
+        return match decoded.strip_prefix(tags::TAGS_PREFIX) {
+            Some(rest) => tags::serve(state, rest).await,
+            None => Ok(Redirect::permanent(tags::TAGS_PREFIX).into_response()),
+        };
+    }
+
+    let preview_token = query_preview(uri);
+    let preview_token = preview_token.as_deref();
+
     // Root or trailing slash → directory listing.
     if raw_path.ends_with('/') || rel.is_empty() {
-        return serve_directory(&state, &fs_path, &decoded).await;
+        let page = query_page(uri)?;
+        return serve_directory(state, headers, fs_path, decoded, page, preview_token).await;
     }
 
     // /any/path/index.html → treat as its parent directory.
     if raw_path.ends_with("/index.html") {
         let dir_url = decoded.strip_suffix("index.html").unwrap_or("/");
         let dir_fs = state.www_root.join(dir_url.trim_start_matches('/'));
-        return serve_directory(&state, &dir_fs, dir_url).await;
+        let page = query_page(uri)?;
+        return serve_directory(state, headers, &dir_fs, dir_url, page, preview_token).await;
     }
 
     // Real directory on disk without trailing slash → redirect to canonical URL.
-    if tokio::fs::metadata(&fs_path)
+    if tokio::fs::metadata(fs_path)
         .await
         .map(|m| m.is_dir())
         .unwrap_or(false)
@@ -62,22 +162,22 @@ pub async fn handle(State(state): State<AppState>, uri: Uri) -> Result<Response,
         return Ok(Redirect::permanent(&format!("{}/", raw_path)).into_response());
     }
 
-    let ext = file_extension(&fs_path);
+    let ext = file_extension(fs_path);
 
     match ext.as_deref() {
-        Some("md") => serve_markdown(&state, &fs_path, &decoded).await,
-        Some(e) if STATIC_EXTENSIONS.contains(&e) => serve_static(&state, &fs_path).await,
-        Some("xml") => {
-            let stem = fs_path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .map(|s| s.to_lowercase());
-            if matches!(stem.as_deref(), Some("feed") | Some("rss")) {
-                let dir_path = fs_path.parent().unwrap_or(&fs_path);
-                serve_rss(&state, dir_path, &decoded).await
-            } else {
-                Err(AppError::NotFound)
-            }
+        Some("md") => serve_markdown(state, fs_path, decoded, preview_token).await,
+        Some(e) if STATIC_EXTENSIONS.contains(&e) => {
+            let transform = images::Transform::from_query(uri.query());
+            serve_static(state, fs_path, headers, transform).await
+        }
+        Some("xml") | Some("json") => {
+            let format = fs_path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(rss::FeedFormat::from_file_name)
+                .ok_or(AppError::NotFound)?;
+            let dir_path = fs_path.parent().unwrap_or(fs_path);
+            serve_feed(state, dir_path, decoded, format).await
         }
         _ => {
             // No or unrecognized extension — try appending .md for clean URLs.
@@ -86,7 +186,7 @@ pub async fn handle(State(state): State<AppState>, uri: Uri) -> Result<Response,
                 .await
                 .map_err(AppError::Io)?
             {
-                serve_markdown(&state, &md_path, &decoded).await
+                serve_markdown(state, &md_path, decoded, preview_token).await
             } else {
                 Err(AppError::NotFound)
             }
@@ -98,46 +198,81 @@ async fn serve_markdown(
     state: &AppState,
     fs_path: &Path,
     url_path: &str,
+    preview_token: Option<&str>,
 ) -> Result<Response, AppError> {
     let real_path = validate_path(state, fs_path).await?;
-    let raw = tokio::fs::read_to_string(&real_path)
-        .await
-        .map_err(io_err)?;
-
-    let ParsedDoc {
-        mut front_matter,
-        content,
-    } = front_matter::parse(&raw);
-    front_matter::fill_inferred(&mut front_matter, &content, &real_path).await;
-
-    if front_matter.draft.unwrap_or(false) {
-        return Err(AppError::NotFound);
-    }
+    let page = load_page(state, &real_path).await?;
+    let front_matter = &page.front_matter;
+
+    // Hidden pages are only shown through a valid preview link.
+    let preview_expires = if front_matter.is_hidden() {
+        let rel = rel_path(&state.canonical_root, &real_path);
+        let token = preview_token.ok_or(AppError::NotFound)?;
+        let expires_at = preview::verify(state, &rel, token).await;
+        Some(expires_at.ok_or(AppError::NotFound)?)
+    } else {
+        None
+    };
 
-    let html_body = render_markdown(&content);
-    let css = find_css(&state.canonical_root, &real_path).await;
-    let meta_image = find_meta_image(&state.canonical_root, &real_path).await;
+    let assets = dir_assets(state, real_path.parent().unwrap_or(&real_path)).await;
     let mut breadcrumbs = template::build_breadcrumbs(url_path);
     if let (Some(last), Some(title)) = (breadcrumbs.last_mut(), front_matter.title.as_deref()) {
         last.label = title.to_string();
     }
 
+    // Advertise the enclosing listing's feeds, if it has any.
+    let feed_dir = listing_feed_dir(&real_path, url_path).await;
+
     // Build the Micropub endpoint URL for discovery (only when base_url is set).
     let micropub_url = state
         .base_url
         .as_deref()
         .map(|base| format!("{}/micropub", base.trim_end_matches('/')));
 
-    let markup = template::page(
-        &front_matter,
-        &html_body,
-        css.as_deref(),
-        meta_image.as_deref(),
-        &breadcrumbs,
-        micropub_url.as_deref(),
-    );
+    let toc = front_matter
+        .toc
+        .unwrap_or(false)
+        .then(|| toc::render(&page.headings).into_string());
+
+    let backlinks = if backlinks::enabled(state, front_matter).await {
+        backlinks::linked_from(state, url_path).await
+    } else {
+        Vec::new()
+    };
+
+    let vars = template::PageVars {
+        front_matter,
+        content_html: &page.html,
+        url: url_path,
+        css_path: assets.css.as_deref(),
+        meta_image: assets.meta_image.as_deref(),
+        breadcrumbs: &breadcrumbs,
+        feed_dir: feed_dir.as_deref(),
+        micropub_url: micropub_url.as_deref(),
+        toc: toc.as_deref(),
+        backlinks: &backlinks,
+        heading_anchors: state.render.heading_anchors,
+    };
+    let user_layout = match &assets.layout {
+        Some(path) => layout::render_page(&state.canonical_root, path, &vars).await,
+        None => None,
+    };
+    let body = user_layout.unwrap_or_else(|| template::page(&vars).into_string());
+
+    if let Some(expires_at) = preview_expires {
+        let mut response = Html(preview::with_banner(&body, expires_at)).into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CACHE_CONTROL,
+            header::HeaderValue::from_static("private, no-store"),
+        );
+        headers.insert("X-Robots-Tag", header::HeaderValue::from_static("noindex"));
+        return Ok(response);
+    }
 
-    let mut response = Html(markup.into_string()).into_response();
+    let etag = conditional::content_etag(body.as_bytes());
+    let mut response = Html(body).into_response();
+    conditional::set_validators(response.headers_mut(), &etag, page.modified);
 
     // Emit Link: </micropub>; rel="micropub" header per Micropub spec §5.3.
     if let Some(ref mp) = micropub_url {
@@ -149,54 +284,142 @@ async fn serve_markdown(
     Ok(response)
 }
 
+/// Serve a directory as its `index.md` or, failing that, page `page` (1-based)
+/// of its auto-listing.
 async fn serve_directory(
     state: &AppState,
+    headers: &HeaderMap,
     fs_path: &Path,
     url_path: &str,
+    page: usize,
+    preview_token: Option<&str>,
 ) -> Result<Response, AppError> {
     let real_path = validate_path(state, fs_path).await?;
 
     // Prefer index.md if present.
     let index_md = real_path.join("index.md");
     if tokio::fs::try_exists(&index_md).await.unwrap_or(false) {
-        return serve_markdown(state, &index_md, url_path).await;
+        return serve_markdown(state, &index_md, url_path, preview_token).await;
     }
 
     let url_prefix = url_path.trim_end_matches('/');
-    let mut entries = collect_dir_entries(&real_path, url_prefix).await?;
-    sort_entries(&mut entries);
+    let entries = load_listing(state, &real_path, url_prefix).await?;
+    // Private subdirectories are only listed for visitors who may open them.
+    let entries = access::visible_entries(state, headers, &entries).await;
+
+    let (shown, pagination) = paginate(&entries, page, state.listing.page_size)?;
 
     let display_path = if url_path.is_empty() { "/" } else { url_path };
-    let css = find_css(&state.canonical_root, &real_path).await;
-    let markup = template::directory_index(display_path, &entries, css.as_deref());
+    let assets = dir_assets(state, &real_path).await;
+    let css = assets.css.as_deref();
+    let user_listing = match &assets.listing_template {
+        Some(path) => {
+            layout::render_listing(
+                &state.canonical_root,
+                path,
+                display_path,
+                shown,
+                &pagination,
+                css,
+            )
+            .await
+        }
+        None => None,
+    };
+    let body = match user_listing {
+        Some(html) => html,
+        None => template::directory_index(display_path, shown, &pagination, css).into_string(),
+    };
 
-    Ok(Html(markup.into_string()).into_response())
+    let etag = conditional::content_etag(body.as_bytes());
+    let mut response = Html(body).into_response();
+    let modified = listing_modified(&real_path, &entries).await;
+    conditional::set_validators(response.headers_mut(), &etag, modified);
+    Ok(response)
 }
 
-async fn serve_static(state: &AppState, fs_path: &Path) -> Result<Response, AppError> {
+async fn serve_static(
+    state: &AppState,
+    fs_path: &Path,
+    headers: &HeaderMap,
+    transform: Option<images::Transform>,
+) -> Result<Response, AppError> {
     let real_path = validate_path(state, fs_path).await?;
+    // A resized or re-encoded image comes from the variant cache.
+    let real_path = match transform {
+        Some(transform) => images::variant(&state.images, &real_path, transform)
+            .await
+            .unwrap_or(real_path),
+        None => real_path,
+    };
+    // A precompressed `.br` / `.gz` sibling, when the client accepts one.
+    let (has_siblings, precompressed) = compress::precompressed(headers, &real_path).await;
+    let file_path = precompressed.as_ref().map_or(&real_path, |p| &p.path);
 
-    let file = tokio::fs::File::open(&real_path).await.map_err(io_err)?;
-    let content_length = file.metadata().await.map_err(AppError::Io)?.len();
+    let file = tokio::fs::File::open(file_path).await.map_err(io_err)?;
+    let meta = file.metadata().await.map_err(AppError::Io)?;
+    let content_length = meta.len();
+    let modified = meta.modified().ok();
+    let etag = conditional::file_etag(modified, content_length);
 
     let mime: &'static str = mime_guess::from_path(&real_path)
         .first_raw()
         .unwrap_or("application/octet-stream");
 
-    let body = Body::from_stream(ReaderStream::new(file));
+    let mut builder = Response::builder().header(header::ACCEPT_RANGES, "bytes");
+    if let Some(precompressed) = &precompressed {
+        builder = builder.header(header::CONTENT_ENCODING, precompressed.content_encoding());
+    }
+    if has_siblings {
+        builder = builder.header(header::VARY, "accept-encoding");
+    }
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, mime)
-        .header(header::CONTENT_LENGTH, content_length)
-        .body(body)
-        .map_err(|e| AppError::Internal(e.to_string()))
+    let mut response = match range::evaluate(headers, content_length, &etag, modified) {
+        RangeRequest::Full => builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, mime)
+            .header(header::CONTENT_LENGTH, content_length)
+            .body(Body::from_stream(ReaderStream::new(file))),
+        RangeRequest::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", content_length))
+            .body(Body::empty()),
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let r = ranges[0];
+            let body = range::single_body(file_path, r).await.map_err(io_err)?;
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, mime)
+                .header(header::CONTENT_LENGTH, r.len())
+                .header(header::CONTENT_RANGE, r.content_range(content_length))
+                .body(body)
+        }
+        RangeRequest::Partial(ranges) => {
+            let (body, length, boundary) =
+                range::multipart_body(file_path, &ranges, mime, content_length)
+                    .await
+                    .map_err(io_err)?;
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/byteranges; boundary={}", boundary),
+                )
+                .header(header::CONTENT_LENGTH, length)
+                .body(body)
+        }
+    }
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    conditional::set_validators(response.headers_mut(), &etag, modified);
+    Ok(response)
 }
 
-async fn serve_rss(
+async fn serve_feed(
     state: &AppState,
     dir_path: &Path,
     feed_url: &str,
+    format: rss::FeedFormat,
 ) -> Result<Response, AppError> {
     let real_path = validate_path(state, dir_path).await?;
 
@@ -217,8 +440,7 @@ async fn serve_rss(
     };
     let url_prefix = dir_url.trim_end_matches('/'); // e.g. "/blog"
 
-    let mut entries = collect_dir_entries(&real_path, url_prefix).await?;
-    sort_entries(&mut entries);
+    let entries = load_listing(state, &real_path, url_prefix).await?;
 
     let base_url = state.base_url.as_deref().unwrap_or("");
     let channel_title = dir_url
@@ -227,19 +449,205 @@ async fn serve_rss(
         .next()
         .filter(|s| !s.is_empty())
         .unwrap_or("Feed");
-    let channel_link = format!("{}{}", base_url, dir_url);
+    let base = base_url.trim_end_matches('/');
+    let channel_link = format!("{}{}", base, dir_url);
+    let self_link = format!("{}{}", base, feed_url);
+    let channel = rss::Channel {
+        title: channel_title,
+        link: &channel_link,
+        feed_url: &self_link,
+        description: "",
+    };
 
-    let xml = rss::build_feed(channel_title, &channel_link, "", &entries, base_url);
+    let pages = load_site_pages(state).await;
+    let xml = rss::build(
+        format,
+        &channel,
+        &entries,
+        &pages,
+        base_url,
+        state.listing.feed,
+        &state.render,
+    );
 
-    Response::builder()
+    let etag = conditional::content_etag(xml.as_bytes());
+    let mut response = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")
+        .header(header::CONTENT_TYPE, format.content_type())
         .body(Body::from(xml))
-        .map_err(|e| AppError::Internal(e.to_string()))
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let modified = listing_modified(&real_path, &entries).await;
+    conditional::set_validators(response.headers_mut(), &etag, modified);
+    Ok(response)
 }
 
 // ── Helpers ──────────────────────────────────────────────────────────────────
 
+/// Parse, infer and render the markdown file at `real_path`, going through
+/// the render cache.
+async fn load_page(state: &AppState, real_path: &Path) -> Result<Arc<CachedPage>, AppError> {
+    if let Some(page) = state.cache.page(real_path) {
+        return Ok(page);
+    }
+
+    let generation = state.cache.generation();
+    let modified = tokio::fs::metadata(real_path)
+        .await
+        .and_then(|m| m.modified())
+        .ok();
+    let raw = tokio::fs::read_to_string(real_path)
+        .await
+        .map_err(io_err)?;
+
+    let ParsedDoc {
+        mut front_matter,
+        content,
+    } = front_matter::parse(&raw);
+    front_matter::fill_inferred(&mut front_matter, &content, real_path).await;
+
+    let wiki_links = wiki::has_links(&content);
+    let pages = if wiki_links {
+        load_site_pages(state).await
+    } else {
+        Arc::default()
+    };
+    let (_, url) = page_url(&rel_path(&state.canonical_root, real_path));
+    let links = wiki::Resolver::new(&pages, &url);
+    let (html, headings) = render_document(&content, &links, &state.render);
+    let (html, local_images) =
+        images::responsive(&html, &state.canonical_root, &url, &state.images).await;
+    Ok(state.cache.insert_page(
+        real_path.to_path_buf(),
+        CachedPage {
+            front_matter,
+            html,
+            headings,
+            wiki_links,
+            local_images,
+            modified,
+        },
+        generation,
+    ))
+}
+
+/// Sorted listing entries for `real_path`, going through the render cache.
+async fn load_listing(
+    state: &AppState,
+    real_path: &Path,
+    url_prefix: &str,
+) -> Result<Arc<Vec<DirEntry>>, AppError> {
+    if let Some(entries) = state.cache.listing(real_path, url_prefix) {
+        return Ok(entries);
+    }
+
+    let generation = state.cache.generation();
+    let mut entries = collect_dir_entries(real_path, url_prefix).await?;
+    sort_entries(&mut entries);
+    Ok(state.cache.insert_listing(
+        real_path.to_path_buf(),
+        url_prefix.to_string(),
+        entries,
+        generation,
+    ))
+}
+
+/// Most recent modification time among a listing directory and its entries,
+/// used as the `Last-Modified` of listings and feeds.
+async fn listing_modified(real_path: &Path, entries: &[DirEntry]) -> Option<SystemTime> {
+    let dir_modified = tokio::fs::metadata(real_path)
+        .await
+        .and_then(|m| m.modified())
+        .ok();
+    entries
+        .iter()
+        .filter_map(|e| e.modified)
+        .chain(dir_modified)
+        .max()
+}
+
+/// `style.css`, `meta.*` and template lookups for `dir`, going through the
+/// render cache.
+pub(crate) async fn dir_assets(state: &AppState, dir: &Path) -> Arc<DirAssets> {
+    if let Some(assets) = state.cache.assets(dir) {
+        return assets;
+    }
+
+    let generation = state.cache.generation();
+    let root = &state.canonical_root;
+    let assets = DirAssets {
+        css: find_css(root, dir).await,
+        meta_image: find_meta_image(root, dir).await,
+        layout: layout::find_template(root, dir, layout::PAGE_TEMPLATE).await,
+        listing_template: layout::find_template(root, dir, layout::LISTING_TEMPLATE).await,
+        access: access::find_rule(root, dir).await,
+    };
+    state.cache.insert_assets(dir.to_path_buf(), assets, generation)
+}
+
+/// The site-wide stylesheet (`style.css` at the www root), if any.
+pub(crate) async fn root_css(state: &AppState) -> Option<String> {
+    dir_assets(state, &state.canonical_root).await.css.clone()
+}
+
+/// URL of the listing directory whose feeds cover the page at `real_path`
+/// (served at `url_path`): its parent, unless the page is a directory's
+/// `index.md` or the parent has one, in which case there is no feed.
+pub(crate) async fn listing_feed_dir(real_path: &Path, url_path: &str) -> Option<String> {
+    if real_path.file_name().is_some_and(|n| n == "index.md") {
+        return None;
+    }
+    let parent = real_path.parent()?;
+    if tokio::fs::try_exists(parent.join("index.md")).await.unwrap_or(true) {
+        return None;
+    }
+    url_path.rfind('/').map(|pos| url_path[..=pos].to_string())
+}
+
+/// The `?preview=` token of a draft preview link, if any.
+fn query_preview(uri: &Uri) -> Option<String> {
+    let query = uri.query()?;
+    form_urlencoded::parse(query.as_bytes())
+        .find(|(k, _)| k == preview::QUERY_PARAM)
+        .map(|(_, v)| v.into_owned())
+}
+
+/// The `page` query parameter, defaulting to 1. Anything that isn't a
+/// positive integer is a 404 rather than a silent alias of page 1.
+fn query_page(uri: &Uri) -> Result<usize, AppError> {
+    let query = uri.query().unwrap_or("");
+    match form_urlencoded::parse(query.as_bytes()).find(|(k, _)| k == "page") {
+        Some((_, v)) => v
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or(AppError::NotFound),
+        None => Ok(1),
+    }
+}
+
+/// Slice sorted listing entries to 1-based `page`. A `page_size` of 0 shows
+/// everything on one page; pages past the end are a 404.
+fn paginate(
+    entries: &[DirEntry],
+    page: usize,
+    page_size: usize,
+) -> Result<(&[DirEntry], template::Pagination), AppError> {
+    if page_size == 0 {
+        return match page {
+            1 => Ok((entries, template::Pagination { page: 1, total_pages: 1 })),
+            _ => Err(AppError::NotFound),
+        };
+    }
+
+    let total_pages = entries.len().div_ceil(page_size).max(1);
+    if page > total_pages {
+        return Err(AppError::NotFound);
+    }
+    let start = (page - 1) * page_size;
+    let end = (start + page_size).min(entries.len());
+    Ok((&entries[start..end], template::Pagination { page, total_pages }))
+}
+
 /// Collect directory entries (subdirectories and `.md` files) for `real_path`,
 /// building item URLs relative to `url_prefix` (e.g. `"/blog"`).
 async fn collect_dir_entries(
@@ -264,6 +672,8 @@ async fn collect_dir_entries(
             }
         };
 
+        let modified = entry.metadata().await.and_then(|m| m.modified()).ok();
+
         if file_type.is_dir() {
             let url = format!("{}/{}/", url_prefix, name);
             let date = front_matter::infer_date(&entry_path).await;
@@ -277,6 +687,9 @@ async fn collect_dir_entries(
                 summary,
                 author,
                 content: None,
+                modified,
+                tags: Vec::new(),
+                aliases: Vec::new(),
             });
         } else if file_type.is_file() {
             let Some(stem) = md_stem(&name) else {
@@ -287,41 +700,140 @@ async fn collect_dir_entries(
             }
 
             let url = format!("{}/{}", url_prefix, stem);
-            let raw = tokio::fs::read_to_string(&entry_path)
-                .await
-                .unwrap_or_else(|e| {
-                    tracing::warn!("Cannot read {}: {}", entry_path.display(), e);
-                    String::new()
-                });
-
-            let ParsedDoc {
-                mut front_matter,
-                content,
-            } = front_matter::parse(&raw);
-            front_matter::fill_inferred(&mut front_matter, &content, &entry_path).await;
-
-            if let Some(true) = front_matter.draft {
+            if let Some(e) = read_page_entry(&entry_path, stem, url, modified).await {
+                entries.push(e);
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Build a listing entry for the markdown file at `path`. Returns `None` for
+/// drafts and pages scheduled for later.
+async fn read_page_entry(
+    path: &Path,
+    display_name: &str,
+    url: String,
+    modified: Option<SystemTime>,
+) -> Option<DirEntry> {
+    let raw = tokio::fs::read_to_string(path).await.unwrap_or_else(|e| {
+        tracing::warn!("Cannot read {}: {}", path.display(), e);
+        String::new()
+    });
+
+    let ParsedDoc {
+        mut front_matter,
+        content,
+    } = front_matter::parse(&raw);
+    front_matter::fill_inferred(&mut front_matter, &content, path).await;
+
+    if front_matter.is_hidden() {
+        return None;
+    }
+
+    Some(DirEntry {
+        display_name: display_name.to_string(),
+        url,
+        is_dir: false,
+        title: front_matter.title,
+        date: front_matter.date,
+        summary: front_matter.summary,
+        author: front_matter.author,
+        content: Some(content),
+        modified,
+        tags: front_matter.tags.unwrap_or_default(),
+        aliases: front_matter.aliases.unwrap_or_default(),
+    })
+}
+
+/// Every published markdown page under the www root, sorted like a listing,
+/// going through the render cache.
+pub(crate) async fn load_site_pages(state: &AppState) -> Arc<Vec<DirEntry>> {
+    if let Some(pages) = state.cache.site_pages() {
+        return pages;
+    }
+
+    let generation = state.cache.generation();
+    let root = &state.canonical_root;
+    let mut pages: Vec<DirEntry> = Vec::new();
+    for (path, modified) in walk_markdown(root).await {
+        let (display_name, url) = page_url(&rel_path(root, &path));
+        if let Some(e) = read_page_entry(&path, &display_name, url, modified).await {
+            pages.push(e);
+        }
+    }
+    sort_entries(&mut pages);
+    state.cache.insert_site_pages(pages, generation)
+}
+
+/// Walk `dir` recursively and return every `.md` file with its modification
+/// time. Hidden entries and symlinks are skipped, as in `collect_dir_entries`.
+pub(crate) async fn walk_markdown(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut files = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let mut read_dir = match tokio::fs::read_dir(&dir).await {
+            Ok(rd) => rd,
+            Err(e) => {
+                tracing::warn!("Cannot read directory {}: {}", dir.display(), e);
                 continue;
             }
+        };
 
-            entries.push(DirEntry {
-                display_name: stem.to_string(),
-                url,
-                is_dir: false,
-                title: front_matter.title,
-                date: front_matter.date,
-                summary: front_matter.summary,
-                author: front_matter.author,
-                content: Some(content),
-            });
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type().await else {
+                continue;
+            };
+            if file_type.is_dir() {
+                stack.push(entry.path());
+            } else if file_type.is_file() && md_stem(&name).is_some() {
+                let modified = entry.metadata().await.and_then(|m| m.modified()).ok();
+                files.push((entry.path(), modified));
+            }
         }
     }
 
-    Ok(entries)
+    files
+}
+
+/// Path of `path` relative to `root`, with forward slashes.
+pub(crate) fn rel_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Map a www-root-relative `.md` path to its (display name, clean URL):
+/// `blog/post.md` → `/blog/post`, `blog/index.md` → `/blog/`.
+pub(crate) fn page_url(rel: &str) -> (String, String) {
+    let stem = rel.strip_suffix(".md").unwrap_or(rel);
+    let (dir, name) = match stem.rsplit_once('/') {
+        Some((dir, name)) => (dir, name),
+        None => ("", stem),
+    };
+    let dir_url = if dir.is_empty() {
+        String::from("/")
+    } else {
+        format!("/{}/", dir)
+    };
+
+    if name == "index" {
+        let dir_name = dir.rsplit('/').next().unwrap_or("");
+        (dir_name.to_string(), dir_url)
+    } else {
+        (name.to_string(), format!("{}{}", dir_url, name))
+    }
 }
 
 /// Sort entries by date descending; undated entries last, alphabetically within ties.
-fn sort_entries(entries: &mut [DirEntry]) {
+pub(crate) fn sort_entries(entries: &mut [DirEntry]) {
     entries.sort_unstable_by(|a, b| match (&b.date, &a.date) {
         (Some(bd), Some(ad)) => bd.cmp(ad),
         (Some(_), None) => std::cmp::Ordering::Less,
@@ -387,11 +899,27 @@ async fn read_index_metadata(dir: &Path) -> (Option<String>, Option<String>, Opt
     )
 }
 
-pub fn render_markdown(content: &str) -> String {
+pub fn render_markdown(content: &str, links: &wiki::Resolver, render: &RenderConfig) -> String {
+    render_document(content, links, render).0
+}
+
+/// Render markdown to HTML with wiki links resolved, heading IDs and a
+/// `[[toc]]` marker expanded, also returning the headings for a sidebar
+/// table of contents.
+pub fn render_document(
+    content: &str,
+    links: &wiki::Resolver,
+    render: &RenderConfig,
+) -> (String, Vec<toc::Heading>) {
     let mut opts = markdown::Options::gfm();
     opts.parse.constructs.frontmatter = false;
     opts.compile.allow_dangerous_html = true;
-    markdown::to_html_with_options(content, &opts).unwrap_or_else(|_| markdown::to_html(content))
+    let html = markdown::to_html_with_options(content, &opts)
+        .unwrap_or_else(|_| markdown::to_html(content));
+    let html = highlight::code_blocks(content, &opts.parse, html, &render.highlight);
+    let html = wiki::link(&html, links);
+    let (html, headings) = toc::add_heading_ids(&html, render.heading_anchors);
+    (toc::expand_marker(html, &headings), headings)
 }
 
 fn file_extension(path: &Path) -> Option<String> {
@@ -0,0 +1,204 @@
+//! Response headers configured per path.
+//!
+//! The **Response headers** box on the editor's settings page holds rules in
+//! the `_headers` format used by static hosts: a URL pattern on its own line,
+//! followed by indented `Name: value` lines for the headers to send with
+//! every response whose path matches.
+//!
+//! ```text
+//! /_media/**
+//!   Cache-Control: public, max-age=31536000, immutable
+//! /**/feed.xml
+//!   Cache-Control: public, max-age=600
+//! ```
+//!
+//! In patterns, `*` matches within one path segment, `**` across segments
+//! (`/**/` also matches a single `/`) and `?` one character. Every matching
+//! block applies, in order, so a later block overrides an earlier one's
+//! value for the same header; rule headers also replace those the server set
+//! itself. A `Cache-Control` rule only applies to successful responses not
+//! already marked `private` or `no-store`, so errors, login redirects and
+//! private sections are never cached by its terms. The editor's own pages
+//! are left alone.
+//!
+//! Rules are kept in the site settings and cached in `AppState::headers`;
+//! until they are first saved, `DEFAULT_RULES` applies.
+
+use anyhow::{Result, bail};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::{Arc, RwLock};
+
+use crate::{db, state::AppState};
+
+/// Site setting holding the rules text.
+const SETTING: &str = "header_rules";
+
+/// Rules used until the site has its own.
+pub const DEFAULT_RULES: &str = "\
+/**
+  X-Content-Type-Options: nosniff
+  Referrer-Policy: strict-origin-when-cross-origin
+";
+
+/// A URL pattern and the headers sent with the responses it matches.
+#[derive(Debug)]
+struct Rule {
+    pattern: String,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// Parsed rules along with the text they came from.
+#[derive(Debug)]
+pub struct HeaderRules {
+    pub source: String,
+    rules: Vec<Rule>,
+}
+
+/// The parsed rules once loaded, shared by all requests through `AppState`.
+#[derive(Default)]
+pub struct RulesCache {
+    rules: RwLock<Option<Arc<HeaderRules>>>,
+}
+
+impl RulesCache {
+    fn get(&self) -> Option<Arc<HeaderRules>> {
+        self.rules.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set(&self, rules: Arc<HeaderRules>) {
+        *self.rules.write().unwrap_or_else(|e| e.into_inner()) = Some(rules);
+    }
+}
+
+/// Parse rules text. Errors name the offending line.
+pub fn parse(source: &str) -> Result<HeaderRules> {
+    let mut rules: Vec<Rule> = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        let n = n + 1;
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            if !text.starts_with('/') {
+                bail!("Line {}: a path pattern must start with /", n);
+            }
+            rules.push(Rule {
+                pattern: text.to_string(),
+                headers: Vec::new(),
+            });
+            continue;
+        }
+        let Some(rule) = rules.last_mut() else {
+            bail!("Line {}: header before any path pattern", n);
+        };
+        let Some((name, value)) = text.split_once(':') else {
+            bail!("Line {}: expected Name: value", n);
+        };
+        let Ok(name) = HeaderName::from_bytes(name.trim().as_bytes()) else {
+            bail!("Line {}: invalid header name {:?}", n, name.trim());
+        };
+        let Ok(value) = HeaderValue::from_str(value.trim()) else {
+            bail!("Line {}: invalid value for {}", n, name);
+        };
+        rule.headers.push((name, value));
+    }
+    Ok(HeaderRules {
+        source: source.to_string(),
+        rules,
+    })
+}
+
+/// The site's rules, loaded from the settings on first use.
+pub async fn current(state: &AppState) -> Arc<HeaderRules> {
+    if let Some(rules) = state.headers.get() {
+        return rules;
+    }
+    let source = match db::get_site_setting(&state.db, SETTING).await {
+        Ok(Some(source)) => source,
+        Ok(None) => DEFAULT_RULES.to_string(),
+        Err(e) => {
+            tracing::warn!("Cannot read header rules: {e:#}");
+            DEFAULT_RULES.to_string()
+        }
+    };
+    let rules = parse(&source).unwrap_or_else(|e| {
+        tracing::warn!("Invalid header rules, ignoring them: {e:#}");
+        HeaderRules {
+            source,
+            rules: Vec::new(),
+        }
+    });
+    let rules = Arc::new(rules);
+    state.headers.set(Arc::clone(&rules));
+    rules
+}
+
+/// Validate, store and start applying new rules.
+pub async fn save(state: &AppState, source: &str) -> Result<()> {
+    // Browsers submit textareas with CRLF line endings.
+    let source = source.replace("\r\n", "\n");
+    let rules = parse(&source)?;
+    db::set_site_setting(&state.db, SETTING, &source).await?;
+    state.headers.set(Arc::new(rules));
+    Ok(())
+}
+
+/// Add the configured headers to every response outside the editor.
+pub async fn apply(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+    let mut response = next.run(req).await;
+    if path == "/edit" || path.starts_with("/edit/") {
+        return response;
+    }
+
+    let rules = current(&state).await;
+    let decoded = percent_encoding::percent_decode_str(&path).decode_utf8_lossy();
+    // Errors, redirects to the login page and private pages keep their own
+    // caching.
+    let status = response.status();
+    let keep_caching = !(status.is_success() || status == StatusCode::NOT_MODIFIED)
+        || response
+            .headers()
+            .get(header::CACHE_CONTROL)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("private") || v.contains("no-store"));
+    let headers = response.headers_mut();
+    for rule in rules.rules.iter().filter(|r| glob_match(&r.pattern, &decoded)) {
+        for (name, value) in &rule.headers {
+            if keep_caching && name == header::CACHE_CONTROL {
+                continue;
+            }
+            headers.insert(name.clone(), value.clone());
+        }
+    }
+    response
+}
+
+/// Whether `path` matches the glob `pattern`.
+fn glob_match(pattern: &str, path: &str) -> bool {
+    matches(pattern.as_bytes(), path.as_bytes())
+}
+
+fn matches(p: &[u8], s: &[u8]) -> bool {
+    match p {
+        [] => s.is_empty(),
+        [b'/', b'*', b'*', b'/', rest @ ..] => {
+            // `/**/` is one `/` or `/…/` around any number of segments.
+            s.first() == Some(&b'/')
+                && (0..s.len()).any(|i| s[i] == b'/' && matches(rest, &s[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=s.len()).any(|i| matches(rest, &s[i..])),
+        [b'*', rest @ ..] => {
+            let segment = s.iter().position(|&c| c == b'/').unwrap_or(s.len());
+            (0..=segment).any(|i| matches(rest, &s[i..]))
+        }
+        [b'?', rest @ ..] => s.first().is_some_and(|&c| c != b'/') && matches(rest, &s[1..]),
+        [c, rest @ ..] => s.first() == Some(c) && matches(rest, &s[1..]),
+    }
+}
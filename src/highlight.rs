@@ -0,0 +1,254 @@
+//! Server-side syntax highlighting for fenced code blocks.
+//!
+//! The `markdown` crate renders code as plain `<pre><code class="language-x">`
+//! and drops everything after the language in the info string, so rendered
+//! blocks are matched back up with the code nodes of the parsed document to
+//! recover it. Braces after the language select line options:
+//! ` ```rust {3-5} ` highlights lines 3 to 5, and ` ```rust {1,4 linenos} `
+//! also numbers the lines. Colours are inline styles from the configured
+//! `syntect` theme, so no extra stylesheet is needed.
+
+use anyhow::bail;
+use markdown::{ParseOptions, mdast::Node};
+use std::sync::LazyLock;
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, Theme, ThemeSet},
+    html::{IncludeBackground, styled_line_to_highlighted_html},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+/// Highlighting settings, set from the command line.
+#[derive(Debug, Clone)]
+pub struct HighlightConfig {
+    /// `syntect` theme name; `None` leaves code blocks untouched.
+    pub theme: Option<String>,
+    /// Number the lines of every block, not just those marked `linenos`.
+    pub line_numbers: bool,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        Self {
+            theme: Some(DEFAULT_THEME.to_string()),
+            line_numbers: false,
+        }
+    }
+}
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Check the highlighting settings. Fails on an unknown theme name.
+pub fn check(config: &HighlightConfig) -> anyhow::Result<()> {
+    if let Some(theme) = &config.theme
+        && !THEMES.themes.contains_key(theme)
+    {
+        let names: Vec<&str> = THEMES.themes.keys().map(String::as_str).collect();
+        bail!("Unknown code theme {:?}; available: {}", theme, names.join(", "));
+    }
+    Ok(())
+}
+
+/// Replace the plain code blocks in `html` (rendered from `source` with
+/// `options`) with ones highlighted according to `config`.
+pub fn code_blocks(
+    source: &str,
+    options: &ParseOptions,
+    html: String,
+    config: &HighlightConfig,
+) -> String {
+    const OPEN: &str = "<pre><code";
+    const CLOSE: &str = "</code></pre>";
+    if !html.contains(OPEN) {
+        return html;
+    }
+    let Some(theme) = config.theme.as_deref().and_then(|t| THEMES.themes.get(t)) else {
+        return html;
+    };
+    let Ok(tree) = markdown::to_mdast(source, options) else {
+        return html;
+    };
+    let mut blocks = Vec::new();
+    collect_code(&tree, &mut blocks);
+    let mut blocks = blocks.into_iter().peekable();
+
+    let mut out = String::with_capacity(html.len() * 2);
+    let mut rest = html.as_str();
+    while let Some(start) = rest.find(OPEN) {
+        let Some(open_end) = rest[start + OPEN.len()..]
+            .find('>')
+            .map(|i| start + OPEN.len() + i + 1)
+        else {
+            break;
+        };
+        let Some(close) = rest[open_end..].find(CLOSE).map(|i| open_end + i) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+
+        // Raw `<pre><code>` HTML in the source has no code node; only
+        // consume a node when its rendered text matches.
+        let rendered = &rest[open_end..close];
+        let matched = blocks.next_if(|b| rendered == encoded(&b.value));
+        match matched.and_then(|b| highlight_block(b, theme, config.line_numbers)) {
+            Some(highlighted) => out.push_str(&highlighted),
+            None => out.push_str(&rest[start..close + CLOSE.len()]),
+        }
+        rest = &rest[close + CLOSE.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+struct CodeBlock {
+    value: String,
+    lang: Option<String>,
+    meta: Option<String>,
+}
+
+fn collect_code(node: &Node, blocks: &mut Vec<CodeBlock>) {
+    if let Node::Code(code) = node {
+        blocks.push(CodeBlock {
+            value: code.value.clone(),
+            lang: code.lang.clone(),
+            meta: code.meta.clone(),
+        });
+    }
+    for child in node.children().into_iter().flatten() {
+        collect_code(child, blocks);
+    }
+}
+
+/// Code text as the `markdown` crate writes it inside `<code>`.
+fn encoded(value: &str) -> String {
+    let mut s = escape(value);
+    if !s.is_empty() {
+        s.push('\n');
+    }
+    s
+}
+
+/// Line options from the `{…}` part of the info string.
+#[derive(Default)]
+struct LineOptions {
+    numbers: bool,
+    highlighted: Vec<(usize, usize)>,
+}
+
+impl LineOptions {
+    fn parse(meta: Option<&str>) -> Self {
+        let mut options = LineOptions::default();
+        let Some(inner) = meta
+            .and_then(|m| m.split_once('{'))
+            .and_then(|(_, rest)| rest.split_once('}'))
+            .map(|(inner, _)| inner)
+        else {
+            return options;
+        };
+        for token in inner.split([',', ' ']).filter(|t| !t.is_empty()) {
+            if token == "linenos" {
+                options.numbers = true;
+                continue;
+            }
+            let (from, to) = token.split_once('-').unwrap_or((token, token));
+            if let (Ok(from), Ok(to)) = (from.trim().parse(), to.trim().parse()) {
+                options.highlighted.push((from, to));
+            }
+        }
+        options
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.highlighted
+            .iter()
+            .any(|&(from, to)| (from..=to).contains(&line))
+    }
+}
+
+/// Render one block. `None` (leave it as it was) for blocks with neither a
+/// language nor line options.
+fn highlight_block(block: CodeBlock, theme: &Theme, line_numbers: bool) -> Option<String> {
+    let mut options = LineOptions::parse(block.meta.as_deref());
+    options.numbers |= line_numbers;
+    if block.lang.is_none() && block.meta.is_none() && !options.numbers {
+        return None;
+    }
+
+    let syntax = block
+        .lang
+        .as_deref()
+        .and_then(|lang| SYNTAXES.find_syntax_by_token(lang))
+        .unwrap_or_else(|| SYNTAXES.find_syntax_plain_text());
+    let mut highlighter = HighlightLines::new(syntax, theme);
+
+    let settings = &theme.settings;
+    let mut html = String::from("<pre class=\"highlight\" style=\"");
+    if let Some(bg) = settings.background {
+        html.push_str(&format!("background-color:{};", css_color(bg)));
+    }
+    if let Some(fg) = settings.foreground {
+        html.push_str(&format!("color:{};", css_color(fg)));
+    }
+    html.push_str("\"><code");
+    if let Some(lang) = &block.lang {
+        html.push_str(&format!(" class=\"language-{}\"", escape(lang)));
+    }
+    html.push('>');
+
+    let line_bg = settings
+        .line_highlight
+        .map(css_color)
+        .unwrap_or_else(|| "rgba(128,128,128,0.2)".to_string());
+    let gutter = settings
+        .gutter_foreground
+        .map(|c| format!("color:{};", css_color(c)))
+        .unwrap_or_else(|| "opacity:0.6;".to_string());
+    let width = block.value.lines().count().max(1).to_string().len();
+
+    for (i, line) in LinesWithEndings::from(&block.value).enumerate() {
+        let number = i + 1;
+        let regions = highlighter.highlight_line(line, &SYNTAXES).ok()?;
+        let code = styled_line_to_highlighted_html(&regions, IncludeBackground::No).ok()?;
+
+        if options.is_highlighted(number) {
+            html.push_str(&format!(
+                "<span class=\"line hl\" style=\"display:block;background-color:{}\">",
+                line_bg
+            ));
+        } else {
+            html.push_str("<span class=\"line\">");
+        }
+        if options.numbers {
+            html.push_str(&format!(
+                "<span class=\"ln\" style=\"user-select:none;margin-right:1em;{}\">{:>width$}</span>",
+                gutter, number
+            ));
+        }
+        html.push_str(&code);
+        if !line.ends_with('\n') {
+            html.push('\n');
+        }
+        html.push_str("</span>");
+    }
+    html.push_str("</code></pre>");
+    Some(html)
+}
+
+fn css_color(c: Color) -> String {
+    if c.a == 0xff {
+        format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)
+    } else {
+        format!("rgba({},{},{},{:.2})", c.r, c.g, c.b, c.a as f32 / 255.0)
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
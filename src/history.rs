@@ -0,0 +1,360 @@
+//! Revision history for files written through the editor and Micropub.
+//!
+//! Before a save, restore, delete or Micropub update replaces a file, its
+//! current content is stored in SQLite along with who made the write, when,
+//! and through which interface. The editor lists these snapshots per file,
+//! diffs any of them against the file as it is now, and can restore one.
+//! Renames carry a file's history along. Only the newest `MAX_REVISIONS`
+//! snapshots of each file are kept.
+//!
+//! The snapshots also let the editor settle concurrent edits: a save made
+//! from a stale copy can be three-way merged with the current file, using
+//! the snapshot of the copy it started from as the common base.
+
+use std::path::Path;
+
+use crate::{
+    conditional,
+    db::{self, Revision},
+    handler,
+    state::AppState,
+};
+
+/// Snapshots kept per file.
+pub const MAX_REVISIONS: i64 = 100;
+
+/// Unchanged lines shown around each change in a diff.
+const CONTEXT_LINES: usize = 3;
+
+/// Largest line-count product diffed line by line; bigger changes are shown
+/// as one replaced block.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Where a write came from.
+#[derive(Debug, Clone, Copy)]
+pub enum Source {
+    Editor,
+    Micropub,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Source::Editor => "editor",
+            Source::Micropub => "micropub",
+        }
+    }
+}
+
+// ── Recording ─────────────────────────────────────────────────────────────────
+
+/// Snapshot the file at `path` before `author` replaces it with `new`, or
+/// deletes it when `new` is `None`. Missing and binary files, and writes
+/// that change nothing, are not recorded.
+pub async fn record(
+    state: &AppState,
+    path: &Path,
+    new: Option<&str>,
+    author: &str,
+    source: Source,
+) {
+    let Ok(old) = tokio::fs::read_to_string(path).await else {
+        return;
+    };
+    if new == Some(old.as_str()) {
+        return;
+    }
+    let rel = handler::rel_path(&state.canonical_root, path);
+    if let Err(e) = db::insert_revision(
+        &state.db,
+        &rel,
+        &old,
+        author,
+        source.as_str(),
+        MAX_REVISIONS,
+    )
+    .await
+    {
+        tracing::warn!("Cannot record revision of {}: {e:#}", rel);
+    }
+}
+
+/// Snapshot every markdown file under `dir` before it is deleted.
+pub async fn record_tree(state: &AppState, dir: &Path, author: &str, source: Source) {
+    for (path, _) in handler::walk_markdown(dir).await {
+        record(state, &path, None, author, source).await;
+    }
+}
+
+/// Carry the history of `from` (a file or directory) over to `to` after a
+/// rename.
+pub async fn moved(state: &AppState, from: &Path, to: &Path) {
+    let root = &state.canonical_root;
+    let (from, to) = (handler::rel_path(root, from), handler::rel_path(root, to));
+    if let Err(e) = db::move_revisions(&state.db, &from, &to).await {
+        tracing::warn!("Cannot move history of {} to {}: {e:#}", from, to);
+    }
+}
+
+/// The stored revision of `rel` whose content has the ETag `version`.
+///
+/// When someone else's write replaced the copy an editor started from, that
+/// copy was recorded, so this finds the base for a three-way merge and who
+/// made the conflicting change.
+pub async fn find_version(state: &AppState, rel: &str, version: &str) -> Option<Revision> {
+    let revisions = match db::revision_contents(&state.db, rel).await {
+        Ok(revisions) => revisions,
+        Err(e) => {
+            tracing::warn!("Cannot read history of {}: {e:#}", rel);
+            return None;
+        }
+    };
+    revisions
+        .into_iter()
+        .find(|rev| conditional::content_etag(rev.content.as_bytes()) == version)
+}
+
+// ── Diffing ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowKind {
+    Same,
+    Removed,
+    Added,
+    /// Removed on the left and added on the right.
+    Changed,
+    /// A run of unchanged lines left out; neither side is set.
+    Skipped(usize),
+}
+
+/// One row of a side-by-side diff: 1-based line numbers and text.
+#[derive(Debug, Clone)]
+pub struct DiffRow<'a> {
+    pub kind: RowKind,
+    pub old: Option<(usize, &'a str)>,
+    pub new: Option<(usize, &'a str)>,
+}
+
+enum Op {
+    Same(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Line diff of `old` against `new` for side-by-side display, with long
+/// unchanged stretches collapsed. Empty when the texts are equal.
+pub fn side_by_side<'a>(old: &'a str, new: &'a str) -> Vec<DiffRow<'a>> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = line_ops(&a, &b);
+
+    // Pair each run of deletions with the insertions that follow it.
+    let mut rows = Vec::with_capacity(ops.len());
+    let mut i = 0;
+    while i < ops.len() {
+        match ops[i] {
+            Op::Same(x, y) => {
+                rows.push(DiffRow {
+                    kind: RowKind::Same,
+                    old: Some((x + 1, a[x])),
+                    new: Some((y + 1, b[y])),
+                });
+                i += 1;
+            }
+            _ => {
+                let mut deleted = Vec::new();
+                while let Some(Op::Delete(x)) = ops.get(i) {
+                    deleted.push(*x);
+                    i += 1;
+                }
+                let mut inserted = Vec::new();
+                while let Some(Op::Insert(y)) = ops.get(i) {
+                    inserted.push(*y);
+                    i += 1;
+                }
+                for k in 0..deleted.len().max(inserted.len()) {
+                    let old = deleted.get(k).map(|&x| (x + 1, a[x]));
+                    let new = inserted.get(k).map(|&y| (y + 1, b[y]));
+                    let kind = match (old, new) {
+                        (Some(_), Some(_)) => RowKind::Changed,
+                        (Some(_), None) => RowKind::Removed,
+                        _ => RowKind::Added,
+                    };
+                    rows.push(DiffRow { kind, old, new });
+                }
+            }
+        }
+    }
+    collapse(rows)
+}
+
+/// Edit script turning `a` into `b`: common prefix and suffix, then a
+/// longest-common-subsequence diff of the middle.
+fn line_ops(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (mid_a, mid_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut ops: Vec<Op> = (0..prefix).map(|i| Op::Same(i, i)).collect();
+    let (n, m) = (mid_a.len(), mid_b.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        ops.extend((0..n).map(|i| Op::Delete(prefix + i)));
+        ops.extend((0..m).map(|j| Op::Insert(prefix + j)));
+    } else {
+        // lcs[i][j]: length of the LCS of mid_a[i..] and mid_b[j..].
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if mid_a[i] == mid_b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && mid_a[i] == mid_b[j] {
+                ops.push(Op::Same(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if j < m && (i == n || lcs[i * width + j + 1] >= lcs[(i + 1) * width + j]) {
+                ops.push(Op::Insert(prefix + j));
+                j += 1;
+            } else {
+                ops.push(Op::Delete(prefix + i));
+                i += 1;
+            }
+        }
+    }
+    let (tail_a, tail_b) = (a.len() - suffix, b.len() - suffix);
+    ops.extend((0..suffix).map(|k| Op::Same(tail_a + k, tail_b + k)));
+
+    // Deletions before insertions within each changed run, so they pair up.
+    let mut start = 0;
+    while start < ops.len() {
+        let end = ops[start..]
+            .iter()
+            .position(|op| matches!(op, Op::Same(..)))
+            .map_or(ops.len(), |p| start + p);
+        ops[start..end].sort_by_key(|op| matches!(op, Op::Insert(_)));
+        start = end + 1;
+    }
+    ops
+}
+
+/// Replace unchanged lines more than `CONTEXT_LINES` away from any change
+/// with a `Skipped` row, unless that would hide just one line.
+fn collapse(rows: Vec<DiffRow<'_>>) -> Vec<DiffRow<'_>> {
+    if rows.iter().all(|r| r.kind == RowKind::Same) {
+        return Vec::new();
+    }
+    let changed: Vec<bool> = rows.iter().map(|r| r.kind != RowKind::Same).collect();
+    let near_change = |i: usize| {
+        let from = i.saturating_sub(CONTEXT_LINES);
+        let to = (i + CONTEXT_LINES + 1).min(changed.len());
+        changed[from..to].iter().any(|&c| c)
+    };
+
+    let mut out = Vec::with_capacity(rows.len());
+    let mut hidden = Vec::new();
+    for (i, row) in rows.into_iter().enumerate() {
+        if near_change(i) {
+            flush_hidden(&mut out, &mut hidden);
+            out.push(row);
+        } else {
+            hidden.push(row);
+        }
+    }
+    flush_hidden(&mut out, &mut hidden);
+    out
+}
+
+fn flush_hidden<'a>(out: &mut Vec<DiffRow<'a>>, hidden: &mut Vec<DiffRow<'a>>) {
+    match hidden.len() {
+        0 => {}
+        1 => out.append(hidden),
+        lines => {
+            hidden.clear();
+            out.push(skipped_row(lines));
+        }
+    }
+}
+
+fn skipped_row<'a>(lines: usize) -> DiffRow<'a> {
+    DiffRow {
+        kind: RowKind::Skipped(lines),
+        old: None,
+        new: None,
+    }
+}
+
+// ── Merging ───────────────────────────────────────────────────────────────────
+
+/// The result of a three-way merge.
+pub struct Merge {
+    pub text: String,
+    /// Number of regions left between conflict markers.
+    pub conflicts: usize,
+}
+
+/// Line-based three-way merge of two edits of `base`. Where both sides
+/// changed the same lines differently, both versions are kept between
+/// `<<<<<<<`, `=======` and `>>>>>>>` markers.
+pub fn merge(base: &str, mine: &str, theirs: &str) -> Merge {
+    let o: Vec<&str> = base.lines().collect();
+    let a: Vec<&str> = mine.lines().collect();
+    let b: Vec<&str> = theirs.lines().collect();
+    let (in_a, in_b) = (matched_lines(&o, &a), matched_lines(&o, &b));
+
+    let mut out = Vec::with_capacity(a.len().max(b.len()));
+    let mut conflicts = 0;
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        // The next base line both sides kept; everything before it is a
+        // region at least one side changed.
+        let next = (i..o.len()).find_map(|x| Some((x, in_a[x]?, in_b[x]?)));
+        let (end, end_a, end_b) = next.unwrap_or((o.len(), a.len(), b.len()));
+        let (orig, ours, other) = (&o[i..end], &a[j..end_a], &b[k..end_b]);
+        if ours == orig {
+            out.extend_from_slice(other);
+        } else if other == orig || ours == other {
+            out.extend_from_slice(ours);
+        } else {
+            conflicts += 1;
+            out.push("<<<<<<< yours");
+            out.extend_from_slice(ours);
+            out.push("=======");
+            out.extend_from_slice(other);
+            out.push(">>>>>>> theirs");
+        }
+        let Some((x, y, z)) = next else {
+            break;
+        };
+        out.push(o[x]);
+        (i, j, k) = (x + 1, y + 1, z + 1);
+    }
+
+    let mut text = out.join("\n");
+    if !text.is_empty() && (mine.ends_with('\n') || theirs.ends_with('\n')) {
+        text.push('\n');
+    }
+    Merge { text, conflicts }
+}
+
+/// For each line of `a`, the line of `b` it corresponds to, if it was kept.
+fn matched_lines(a: &[&str], b: &[&str]) -> Vec<Option<usize>> {
+    let mut matched = vec![None; a.len()];
+    for op in line_ops(a, b) {
+        if let Op::Same(x, y) = op {
+            matched[x] = Some(y);
+        }
+    }
+    matched
+}
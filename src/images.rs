@@ -0,0 +1,616 @@
+//! Responsive images.
+//!
+//! Local JPEG, PNG and WebP files can be requested resized or re-encoded
+//! with `?w=<width>` and `?format=jpeg|png`. Widths snap up to one of
+//! `WIDTHS` and never exceed the original, so only a handful of variants
+//! exist per image; they are generated on first request and kept in a disk
+//! cache that drops the least recently used files once it outgrows
+//! `--image-cache-size`.
+//!
+//! Rendered pages give every local `<img>` its `width` and `height`, so the
+//! layout doesn't shift while images load, and — unless the cache is
+//! disabled — a `srcset` of the smaller variants with a `sizes` hint.
+//!
+//! Uploads have their EXIF GPS tags blanked before they are stored.
+
+use anyhow::{Context, Result, bail};
+use image::{
+    DynamicImage, ImageDecoder, ImageReader,
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, FileTimes},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Semaphore;
+
+/// Widths variants are generated at.
+pub const WIDTHS: [u32; 5] = [320, 640, 960, 1280, 1920];
+
+/// `sizes` for rendered images: full width on small screens, otherwise the
+/// width of the default stylesheet's content column.
+const SIZES: &str = "(max-width: 48rem) 100vw, 48rem";
+
+/// Quality of re-encoded JPEGs.
+const JPEG_QUALITY: u8 = 82;
+
+/// Images resized at once; decoding a large photo takes a lot of memory.
+const WORKERS: usize = 2;
+
+/// Settings for the variant cache, set from the command line.
+#[derive(Debug)]
+pub struct ImageConfig {
+    pub cache_dir: PathBuf,
+    /// Size the cache is trimmed back to; zero turns resizing off.
+    pub max_cache_bytes: u64,
+    /// Limits resizes to `WORKERS` at a time.
+    workers: Semaphore,
+}
+
+impl ImageConfig {
+    pub fn new(cache_dir: PathBuf, max_cache_bytes: u64) -> Self {
+        Self {
+            cache_dir,
+            max_cache_bytes,
+            workers: Semaphore::new(WORKERS),
+        }
+    }
+
+    /// Whether images are resized on request.
+    fn resizes(&self) -> bool {
+        self.max_cache_bytes > 0
+    }
+}
+
+/// Image formats that can be resized. WebP is only read: the available
+/// encoder is lossless, which makes photos larger than their source, so WebP
+/// images are resized to JPEG, or PNG when they have transparency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    WebP,
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "png" => Some(Format::Png),
+            "webp" => Some(Format::WebP),
+            _ => None,
+        }
+    }
+
+    fn of(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+
+    /// A format variants can be written in.
+    fn output(name: &str) -> Option<Self> {
+        Self::from_name(name).filter(|f| *f != Format::WebP)
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Jpeg => "jpg",
+            Format::Png => "png",
+            Format::WebP => "webp",
+        }
+    }
+}
+
+/// Whether `path` is an image that can be resized.
+pub fn is_resizable(path: &Path) -> bool {
+    Format::of(path).is_some()
+}
+
+/// A requested resize or re-encode.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub width: Option<u32>,
+    pub format: Option<Format>,
+}
+
+impl Transform {
+    /// `w` and `format` from a query string. Values that don't parse are
+    /// ignored, so a bad link still gets the original image.
+    pub fn from_query(query: Option<&str>) -> Option<Self> {
+        let mut transform = Transform {
+            width: None,
+            format: None,
+        };
+        for (key, value) in form_urlencoded::parse(query?.as_bytes()) {
+            match key.as_ref() {
+                "w" => transform.width = value.parse().ok().filter(|&w| w > 0),
+                "format" => transform.format = Format::output(&value),
+                _ => {}
+            }
+        }
+        (transform.width.is_some() || transform.format.is_some()).then_some(transform)
+    }
+}
+
+// ── Variants ──────────────────────────────────────────────────────────────────
+
+/// The cached file for `transform` applied to the image at `path`, created
+/// if needed. `None` means the original should be served: it isn't a
+/// resizable image, the transform wouldn't change it, resizing is off, or
+/// the image couldn't be processed.
+pub async fn variant(
+    config: &Arc<ImageConfig>,
+    path: &Path,
+    transform: Transform,
+) -> Option<PathBuf> {
+    if !config.resizes() {
+        return None;
+    }
+    let source = Format::of(path)?;
+    let path = path.to_path_buf();
+
+    let paths = Arc::clone(config);
+    let (path, cached, width, format) = blocking(move || {
+        let (width, _) = dimensions(&path)?;
+        let target = transform.width.and_then(|w| snap_width(w, width));
+        if target.is_none() && transform.format.is_none_or(|f| f == source) {
+            return Ok(None);
+        }
+        let format = match (transform.format, source) {
+            (Some(format), _) => format,
+            (None, Format::WebP) if has_alpha(&path)? => Format::Png,
+            (None, Format::WebP) => Format::Jpeg,
+            (None, source) => source,
+        };
+        let cached = cache_path(&paths, &path, target, format)?;
+        Ok(Some((path, cached, target, format)))
+    })
+    .await??;
+
+    if cached.exists() {
+        // Mark it recently used. Not through its modification time, which
+        // the ETag is built from.
+        let touched = cached.clone();
+        blocking(move || {
+            let now = FileTimes::new().set_accessed(SystemTime::now());
+            fs::File::options()
+                .write(true)
+                .open(&touched)?
+                .set_times(now)?;
+            Ok(())
+        })
+        .await;
+        return Some(cached);
+    }
+
+    let _permit = config.workers.acquire().await.ok()?;
+    let config = Arc::clone(config);
+    blocking(move || {
+        // Another request may have made it while this one waited.
+        if !cached.exists() {
+            generate(&path, &cached, width, format)?;
+            tracing::debug!("Generated image variant {}", cached.display());
+            trim_cache(&config);
+        }
+        Ok(cached)
+    })
+    .await
+}
+
+/// Run image work on the blocking pool, logging failures.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Option<T> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(e)) => {
+            tracing::warn!("Cannot resize image: {e:#}");
+            None
+        }
+        Err(e) => {
+            tracing::error!("Image resize task failed: {e}");
+            None
+        }
+    }
+}
+
+/// The smallest of `WIDTHS` at least `requested` wide, or `None` when that
+/// would not be narrower than the original.
+fn snap_width(requested: u32, original: u32) -> Option<u32> {
+    let width = WIDTHS
+        .iter()
+        .copied()
+        .find(|&w| w >= requested)
+        .unwrap_or(WIDTHS[WIDTHS.len() - 1]);
+    (width < original).then_some(width)
+}
+
+/// Cache file for a variant, named by a hash of the source file's path,
+/// size and modification time and the transform, so an edited image gets
+/// new variants.
+fn cache_path(
+    config: &ImageConfig,
+    path: &Path,
+    width: Option<u32>,
+    format: Format,
+) -> Result<PathBuf> {
+    let meta = fs::metadata(path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let key = format!(
+        "{}\0{}\0{}\0{:?}\0{}",
+        path.display(),
+        meta.len(),
+        modified,
+        width,
+        format.extension()
+    );
+    let hash: String = Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(config
+        .cache_dir
+        .join(format!("{}.{}", hash, format.extension())))
+}
+
+fn generate(path: &Path, out: &Path, width: Option<u32>, format: Format) -> Result<()> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()
+        .with_context(|| format!("Cannot decode {}", path.display()))?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+    if let Some(width) = width {
+        img = img.resize(width, u32::MAX, FilterType::Lanczos3);
+    }
+
+    let dir = out.parent().context("Cache path has no parent")?;
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Cannot create image cache {}", dir.display()))?;
+    // Unique per job, so workers making the same variant don't share it.
+    let tmp = out.with_extension(format!(
+        "{}-{:016x}.tmp",
+        std::process::id(),
+        rand::random::<u64>()
+    ));
+    let mut file = BufWriter::new(fs::File::create(&tmp)?);
+    match format {
+        Format::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut file, JPEG_QUALITY))?,
+        Format::Png => img.write_with_encoder(PngEncoder::new(&mut file))?,
+        Format::WebP => bail!("WebP variants are not written"),
+    }
+    file.flush()?;
+    drop(file);
+    fs::rename(&tmp, out)?;
+    Ok(())
+}
+
+/// Delete the least recently used variants, by access time, until the cache
+/// fits its limit.
+fn trim_cache(config: &ImageConfig) {
+    let Ok(entries) = fs::read_dir(&config.cache_dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = entries
+        .filter_map(|e| {
+            let entry = e.ok()?;
+            let meta = entry.metadata().ok()?;
+            meta.is_file().then(|| {
+                (
+                    meta.accessed().unwrap_or(UNIX_EPOCH),
+                    meta.len(),
+                    entry.path(),
+                )
+            })
+        })
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort();
+    for (_, len, path) in files {
+        if total <= config.max_cache_bytes {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+/// Width and height of the image at `path` as displayed, after its EXIF
+/// orientation.
+fn dimensions(path: &Path) -> Result<(u32, u32)> {
+    let mut decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    let rotated = matches!(
+        decoder.orientation()?,
+        Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH
+    );
+    Ok(if rotated {
+        (height, width)
+    } else {
+        (width, height)
+    })
+}
+
+/// Whether the image at `path` has an alpha channel.
+fn has_alpha(path: &Path) -> Result<bool> {
+    let decoder = ImageReader::open(path)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    Ok(decoder.color_type().has_alpha())
+}
+
+// ── Rendered pages ────────────────────────────────────────────────────────────
+
+/// Add `width` and `height` to the local `<img>` tags in `html`, a page
+/// rendered at `page_url`, and `srcset` and `sizes` too when `config` resizes
+/// images. Also returns whether any local image was found, since the page
+/// then depends on those files.
+pub async fn responsive(
+    html: &str,
+    root: &Path,
+    page_url: &str,
+    config: &ImageConfig,
+) -> (String, bool) {
+    if !html.contains("<img ") {
+        return (html.to_string(), false);
+    }
+    let mut out = String::with_capacity(html.len());
+    let mut found = false;
+    let mut rest = html;
+    while let Some(start) = rest.find("<img ") {
+        let Some(end) = rest[start..].find('>').map(|i| start + i) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let tag = &rest[start..end];
+        match local_image(root, page_url, tag) {
+            Some(path) => {
+                found = true;
+                out.push_str(&with_sizes(tag, &path, config.resizes()).await);
+            }
+            None => out.push_str(tag),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    (out, found)
+}
+
+/// The file an `<img>` tag (up to its closing `>`) shows, if it is an image
+/// under `root`.
+fn local_image(root: &Path, page_url: &str, tag: &str) -> Option<PathBuf> {
+    let src = attr(tag, "src")?.replace("&amp;", "&");
+    if src.contains("://") || src.starts_with("//") || src.starts_with("data:") || src.contains('?')
+    {
+        return None;
+    }
+    let src = src.split('#').next().unwrap_or("");
+    let url = if src.starts_with('/') {
+        src.to_string()
+    } else {
+        let dir = page_url.rfind('/').map_or("/", |i| &page_url[..=i]);
+        format!("{}{}", dir, src)
+    };
+    let decoded = percent_encoding::percent_decode_str(&url)
+        .decode_utf8()
+        .ok()?;
+    let mut segments: Vec<&str> = Vec::new();
+    for part in decoded.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            part => segments.push(part),
+        }
+    }
+    let path = root.join(segments.join("/"));
+    let real = path.canonicalize().ok()?;
+    (real.starts_with(root) && Format::of(&real).is_some()).then_some(real)
+}
+
+async fn with_sizes(tag: &str, path: &Path, srcset: bool) -> String {
+    let owned = path.to_path_buf();
+    let Ok(Ok((width, height))) = tokio::task::spawn_blocking(move || dimensions(&owned)).await
+    else {
+        return tag.to_string();
+    };
+
+    let (head, slash) = match tag.strip_suffix('/') {
+        Some(head) => (head.trim_end(), " /"),
+        None => (tag, ""),
+    };
+    let mut out = head.to_string();
+    if attr(tag, "width").is_none() && attr(tag, "height").is_none() {
+        out.push_str(&format!(" width=\"{}\" height=\"{}\"", width, height));
+    }
+    if srcset && attr(tag, "srcset").is_none() {
+        let src = attr(tag, "src").unwrap_or("");
+        let mut candidates: Vec<String> = WIDTHS
+            .iter()
+            .filter(|&&w| w < width)
+            .map(|w| format!("{}?w={} {}w", src, w, w))
+            .collect();
+        if !candidates.is_empty() {
+            candidates.push(format!("{} {}w", src, width));
+            out.push_str(&format!(
+                " srcset=\"{}\" sizes=\"{}\"",
+                candidates.join(", "),
+                SIZES
+            ));
+        }
+    }
+    out.push_str(slash);
+    out
+}
+
+/// The raw value of the double-quoted attribute `name` in a tag.
+fn attr<'t>(tag: &'t str, name: &str) -> Option<&'t str> {
+    let needle = format!(" {}=\"", name);
+    let start = tag.find(&needle)? + needle.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+// ── EXIF GPS stripping ────────────────────────────────────────────────────────
+
+/// EXIF pointer tag to the GPS IFD.
+const GPS_IFD_TAG: u16 = 0x8825;
+
+/// `data` with any EXIF GPS tags blanked out, for JPEG, PNG and WebP files.
+/// Anything else, or metadata that doesn't parse, is returned unchanged.
+pub fn strip_gps(mut data: Vec<u8>) -> Vec<u8> {
+    if data.starts_with(&[0xff, 0xd8]) {
+        strip_gps_jpeg(&mut data);
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        strip_gps_png(&mut data);
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        strip_gps_webp(&mut data);
+    }
+    data
+}
+
+/// EXIF lives in an APP1 segment starting `Exif\0\0`, before the image data.
+fn strip_gps_jpeg(data: &mut [u8]) {
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xff {
+        let marker = data[pos + 1];
+        if marker == 0xda || marker == 0xd9 {
+            break;
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        // The length counts its own two bytes; anything less is corrupt.
+        if len < 2 {
+            break;
+        }
+        let end = (pos + 2 + len).min(data.len());
+        let segment = &mut data[pos + 4..end];
+        if marker == 0xe1 && segment.starts_with(b"Exif\0\0") {
+            scrub_tiff(&mut segment[6..]);
+        }
+        pos = end;
+    }
+}
+
+/// EXIF lives in an `eXIf` chunk, whose CRC must be recomputed.
+fn strip_gps_png(data: &mut [u8]) {
+    let mut pos = 8;
+    while pos + 12 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let end = pos + 8 + len;
+        if end + 4 > data.len() {
+            break;
+        }
+        if &data[pos + 4..pos + 8] == b"eXIf" {
+            scrub_tiff(&mut data[pos + 8..end]);
+            let crc = crc32fast::hash(&data[pos + 4..end]);
+            data[end..end + 4].copy_from_slice(&crc.to_be_bytes());
+        }
+        pos = end + 4;
+    }
+}
+
+/// EXIF lives in an `EXIF` RIFF chunk, sometimes with a JPEG-style prefix.
+fn strip_gps_webp(data: &mut [u8]) {
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let len = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let end = (pos + 8 + len).min(data.len());
+        if &data[pos..pos + 4] == b"EXIF" {
+            let chunk = &mut data[pos + 8..end];
+            let skip = if chunk.starts_with(b"Exif\0\0") { 6 } else { 0 };
+            scrub_tiff(&mut chunk[skip..]);
+        }
+        // Chunks are padded to an even length.
+        pos = end + (len & 1);
+    }
+}
+
+/// Blank the GPS IFD of a TIFF-structured EXIF block in place: its entries
+/// and the values they point to are zeroed and its entry count set to 0, so
+/// offsets elsewhere in the block stay valid.
+fn scrub_tiff(tiff: &mut [u8]) {
+    let little = match tiff.get(..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return,
+    };
+    let u16_at = |t: &[u8], at: usize| -> Option<u16> {
+        let bytes = t.get(at..at + 2)?.try_into().ok()?;
+        Some(if little {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let u32_at = |t: &[u8], at: usize| -> Option<u32> {
+        let bytes = t.get(at..at + 4)?.try_into().ok()?;
+        Some(if little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+
+    let Some(ifd0) = u32_at(tiff, 4).map(|o| o as usize) else {
+        return;
+    };
+    let Some(count) = u16_at(tiff, ifd0) else {
+        return;
+    };
+    let gps = (0..count as usize)
+        .map(|i| ifd0 + 2 + i * 12)
+        .find(|&entry| u16_at(tiff, entry) == Some(GPS_IFD_TAG))
+        .and_then(|entry| u32_at(tiff, entry + 8))
+        .map(|o| o as usize);
+    let Some(gps) = gps else {
+        return;
+    };
+    let Some(gps_count) = u16_at(tiff, gps).map(usize::from) else {
+        return;
+    };
+    let entries_end = gps + 2 + gps_count * 12;
+    if entries_end > tiff.len() {
+        return;
+    }
+
+    for i in 0..gps_count {
+        let entry = gps + 2 + i * 12;
+        let (Some(kind), Some(n), Some(offset)) = (
+            u16_at(tiff, entry + 2),
+            u32_at(tiff, entry + 4),
+            u32_at(tiff, entry + 8),
+        ) else {
+            continue;
+        };
+        let unit = match kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => continue,
+        };
+        let size = (n as usize).saturating_mul(unit);
+        let offset = offset as usize;
+        if size > 4 && offset.saturating_add(size) <= tiff.len() {
+            tiff[offset..offset + size].fill(0);
+        }
+    }
+    tiff[gps + 2..entries_end].fill(0);
+    tiff[gps..gps + 2].fill(0);
+}
@@ -0,0 +1,200 @@
+//! Optional user templates loaded from the content tree at runtime.
+//!
+//! A `_layout.html` replaces the built-in page template and a `_listing.html`
+//! the built-in directory listing, for the directory they sit in and
+//! everything beneath it — found by walking up the tree like `style.css`.
+//! Templates use Jinja syntax (via `minijinja`): `{{ page.title }}`,
+//! `{% for e in entries %}`, and `{% include "_nav.html" %}`, where included
+//! names are resolved by the same upward walk. When a template fails to
+//! render, the error is logged and the built-in template is used instead.
+
+use minijinja::{Environment, Value};
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{
+    css::ancestor_dirs,
+    template::{self, DirEntry, PageVars, Pagination},
+};
+
+pub const PAGE_TEMPLATE: &str = "_layout.html";
+pub const LISTING_TEMPLATE: &str = "_listing.html";
+
+/// Walk up from `dir` toward `www_root` and return the nearest template named
+/// `name`.
+pub async fn find_template(www_root: &Path, dir: &Path, name: &str) -> Option<PathBuf> {
+    for dir in ancestor_dirs(www_root, dir) {
+        let candidate = dir.join(name);
+        if tokio::fs::try_exists(&candidate).await.unwrap_or(false) {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+#[derive(Serialize)]
+struct Crumb<'a> {
+    label: &'a str,
+    url: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    title: &'a str,
+    url: &'a str,
+    display_name: &'a str,
+    is_dir: bool,
+    date: Option<&'a str>,
+    summary: Option<&'a str>,
+    author: Option<&'a str>,
+    tags: &'a [String],
+}
+
+impl<'a> From<&'a DirEntry> for Entry<'a> {
+    fn from(e: &'a DirEntry) -> Self {
+        Entry {
+            title: e.title.as_deref().unwrap_or(&e.display_name),
+            url: &e.url,
+            display_name: &e.display_name,
+            is_dir: e.is_dir,
+            date: e.date.as_deref(),
+            summary: e.summary.as_deref(),
+            author: e.author.as_deref(),
+            tags: &e.tags,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PageInfo {
+    page: usize,
+    total_pages: usize,
+    prev: Option<String>,
+    next: Option<String>,
+}
+
+/// Render a page through the user layout at `layout`.
+///
+/// Variables: `page` (front matter: `title`, `summary`, `author`, `date`,
+/// `tags`), `content` (rendered HTML), `url`, `breadcrumbs` (`label`, `url`),
+/// `css`, `meta_image`, `toc` (the table of contents, with `toc: true`),
+/// `backlinks` (pages linking here, listed like listing `entries`; empty
+/// unless enabled) and `head` — the built-in `<head>` contents, for layouts that only want
+/// to change the body.
+pub async fn render_page(www_root: &Path, layout: &Path, vars: &PageVars<'_>) -> Option<String> {
+    let head = template::page_head(vars);
+    let breadcrumbs: Vec<Crumb> = vars
+        .breadcrumbs
+        .iter()
+        .map(|c| Crumb {
+            label: &c.label,
+            url: c.url.as_deref(),
+        })
+        .collect();
+    let backlinks: Vec<Entry> = vars.backlinks.iter().map(Entry::from).collect();
+    let ctx = minijinja::context! {
+        page => Value::from_serialize(vars.front_matter),
+        content => Value::from_safe_string(vars.content_html.to_string()),
+        url => vars.url,
+        breadcrumbs => Value::from_serialize(&breadcrumbs),
+        css => vars.css_path,
+        meta_image => vars.meta_image,
+        toc => vars.toc.map(|t| Value::from_safe_string(t.to_string())),
+        backlinks => Value::from_serialize(&backlinks),
+        head => Value::from_safe_string(head.into_string()),
+    };
+    render(www_root, layout, ctx).await
+}
+
+/// Render a directory listing through the user template at `listing`.
+///
+/// Variables: `title` and `url` (the directory URL), `entries` (`title`,
+/// `url`, `display_name`, `is_dir`, `date`, `summary`, `author`, `tags`),
+/// `pagination` (`page`, `total_pages`, `prev`, `next`), `css` and `head`.
+pub async fn render_listing(
+    www_root: &Path,
+    listing: &Path,
+    dir_url: &str,
+    entries: &[DirEntry],
+    pagination: &Pagination,
+    css_path: Option<&str>,
+) -> Option<String> {
+    let head = template::listing_head(dir_url, pagination, css_path);
+    let entries: Vec<Entry> = entries.iter().map(Entry::from).collect();
+    let page_info = PageInfo {
+        page: pagination.page,
+        total_pages: pagination.total_pages,
+        prev: pagination.prev_url(dir_url),
+        next: pagination.next_url(dir_url),
+    };
+    let ctx = minijinja::context! {
+        title => dir_url,
+        url => dir_url,
+        entries => Value::from_serialize(&entries),
+        pagination => Value::from_serialize(&page_info),
+        css => css_path,
+        head => Value::from_safe_string(head.into_string()),
+    };
+    render(www_root, listing, ctx).await
+}
+
+/// Render the template file at `path`. `{% include %}` / `{% extends %}`
+/// names are looked up from the template's own directory upward.
+async fn render(www_root: &Path, path: &Path, ctx: Value) -> Option<String> {
+    let root = www_root.to_path_buf();
+    let start = path.parent()?.to_path_buf();
+    let name = path.file_name()?.to_string_lossy().into_owned();
+    let template_path = path.display().to_string();
+
+    // Template loading reads files synchronously.
+    let result = tokio::task::spawn_blocking(move || {
+        let mut env = Environment::new();
+        env.set_loader(move |name| load_nearest(&root, &start, name));
+        env.get_template(&name)?.render(ctx)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(html)) => Some(html),
+        Ok(Err(e)) => {
+            tracing::warn!("Template {} failed, using built-in: {:#}", template_path, e);
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Template {} panicked, using built-in: {}", template_path, e);
+            None
+        }
+    }
+}
+
+/// Loader for template names: plain relative paths (no `..`) resolved
+/// against each directory from `start` up to `www_root`.
+fn load_nearest(
+    www_root: &Path,
+    start: &Path,
+    name: &str,
+) -> Result<Option<String>, minijinja::Error> {
+    let rel = Path::new(name);
+    if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Ok(None);
+    }
+    for dir in ancestor_dirs(www_root, start) {
+        let candidate = dir.join(rel);
+        if !candidate.is_file() {
+            continue;
+        }
+        // Symlinks must not lead outside the www root.
+        match candidate.canonicalize() {
+            Ok(real) if real.starts_with(www_root) => {}
+            _ => return Ok(None),
+        }
+        return std::fs::read_to_string(&candidate).map(Some).map_err(|e| {
+            minijinja::Error::new(
+                minijinja::ErrorKind::InvalidOperation,
+                "cannot read template",
+            )
+            .with_source(e)
+        });
+    }
+    Ok(None)
+}
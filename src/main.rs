@@ -1,29 +1,57 @@
+mod access;
 mod analytics;
+mod backlinks;
+mod cache;
+mod compress;
+mod conditional;
 mod css;
 mod db;
 mod editor;
 mod error;
+mod export;
 mod front_matter;
+mod git;
 mod handler;
+mod headers;
+mod highlight;
+mod history;
+mod images;
+mod layout;
 mod log_capture;
 mod micropub;
+mod preview;
+mod range;
+mod redirects;
 mod rss;
+mod schedule;
+mod search;
+mod sitemap;
 mod state;
+mod tags;
 mod template;
+mod toc;
 mod tui;
+mod wiki;
 
 use anyhow::Context;
-use axum::{Router, http::StatusCode, middleware, response::Redirect, routing::get};
-use clap::Parser;
+use axum::{
+    Router,
+    http::StatusCode,
+    middleware,
+    response::Redirect,
+    routing::{get, post},
+};
+use clap::{Parser, Subcommand};
 use sqlx::SqlitePool;
-use state::AppState;
+use state::{AppState, ListingConfig, RenderConfig, Settings, SiteSecret};
 use std::{
     collections::HashMap,
     net::SocketAddr,
     path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, task::JoinSet};
 use tower_http::catch_panic::CatchPanicLayer;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
@@ -41,17 +69,86 @@ struct Args {
 
     /// Path to the www root directory.
     /// Defaults to a `www` directory adjacent to the server binary.
-    #[arg(long, env = "WWW_ROOT")]
+    #[arg(long, global = true, env = "WWW_ROOT")]
     root: Option<PathBuf>,
 
     /// Base URL prepended to item links in generated RSS feeds (e.g. "https://example.com").
     /// If unset, RSS item links will be relative paths.
-    #[arg(long, env = "BASE_URL")]
+    #[arg(long, global = true, env = "BASE_URL")]
     base_url: Option<String>,
 
+    /// Entries per page in directory listings (`?page=N`). 0 disables pagination.
+    #[arg(long, global = true, env = "PAGE_SIZE", default_value = "50")]
+    page_size: usize,
+
+    /// Maximum number of items in each RSS feed (the newest). 0 means unlimited.
+    #[arg(long, global = true, env = "FEED_ITEMS", default_value = "20")]
+    feed_items: usize,
+
+    /// Put only each post's summary in RSS feeds instead of its full content.
+    #[arg(long, global = true, env = "FEED_SUMMARY_ONLY", default_value = "false")]
+    feed_summary_only: bool,
+
+    /// Syntax highlighting theme for fenced code blocks, or "none" to leave
+    /// them unhighlighted.
+    #[arg(long, global = true, env = "CODE_THEME", default_value = highlight::DEFAULT_THEME)]
+    code_theme: String,
+
+    /// Number the lines of every code block (blocks can opt in with `{linenos}`).
+    #[arg(long, global = true, env = "CODE_LINE_NUMBERS", default_value = "false")]
+    code_line_numbers: bool,
+
+    /// Add a `#` link to each heading, shown on hover.
+    #[arg(long, global = true, env = "HEADING_ANCHORS", default_value = "false")]
+    heading_anchors: bool,
+
+    /// WebSub hub to ping with the affected feeds when a scheduled page goes
+    /// live (repeatable, or comma-separated in the env var). Needs --base-url.
+    #[arg(long = "ping-url", global = true, env = "PING_URLS", value_delimiter = ',')]
+    ping_urls: Vec<String>,
+
+    /// Commit every change made through the editor or Micropub to the git
+    /// repository containing the www root (one is created if there is none).
+    #[arg(long, env = "GIT_AUTOCOMMIT", default_value = "false")]
+    git: bool,
+
+    /// Remote to pull from in git mode: a configured remote name or a path,
+    /// such as a local bare repository.
+    #[arg(long, env = "GIT_REMOTE")]
+    git_remote: Option<String>,
+
+    /// Seconds between pulls from --git-remote. 0 pulls only when asked to
+    /// from the editor.
+    #[arg(long, env = "GIT_PULL_INTERVAL", default_value = "300")]
+    git_pull_interval: u64,
+
+    /// Directory for resized image variants [default: image-cache next to
+    /// the binary].
+    #[arg(long, env = "IMAGE_CACHE")]
+    image_cache: Option<PathBuf>,
+
+    /// Size in MB the image variant cache is kept under. 0 turns resizing
+    /// and `srcset` off.
+    #[arg(long, env = "IMAGE_CACHE_SIZE", default_value = "512")]
+    image_cache_size: u64,
+
     /// Run in headless mode (no TUI). Useful for Docker / systemd deployments.
     #[arg(long, default_value = "false")]
     headless: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render every page, listing, feed and static asset into a directory
+    /// for static hosting, then exit.
+    Export {
+        /// Output directory (created if missing; existing files are overwritten).
+        #[arg(long)]
+        out: PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -74,7 +171,7 @@ async fn main() -> anyhow::Result<()> {
 
     // In TUI mode, redirect all log output to the ring buffer so it appears in
     // the Logs panel. In headless mode, write to stdout via the fmt layer.
-    let log_buffer = if args.headless {
+    let log_buffer = if args.headless || args.command.is_some() {
         tracing_subscriber::registry()
             .with(env_filter)
             .with(tracing_subscriber::fmt::layer())
@@ -106,9 +203,59 @@ async fn main() -> anyhow::Result<()> {
         .await
         .context("Failed to initialise database")?;
 
-    if args.headless {
+    let listing = ListingConfig {
+        page_size: args.page_size,
+        feed: rss::FeedOptions {
+            max_items: args.feed_items,
+            summary_only: args.feed_summary_only,
+        },
+    };
+
+    let render = RenderConfig {
+        highlight: highlight::HighlightConfig {
+            theme: Some(args.code_theme).filter(|t| !t.eq_ignore_ascii_case("none")),
+            line_numbers: args.code_line_numbers,
+        },
+        heading_anchors: args.heading_anchors,
+    };
+    highlight::check(&render.highlight)?;
+
+    // A static export can't resize on request, so its pages keep plain
+    // image URLs.
+    let resize = args.command.is_none();
+    let image_cache = args
+        .image_cache
+        .unwrap_or_else(|| exe_dir.join("image-cache"));
+    let max_cache_bytes = if resize {
+        args.image_cache_size * 1024 * 1024
+    } else {
+        0
+    };
+    let images = images::ImageConfig::new(image_cache, max_cache_bytes);
+
+    let settings = Settings {
+        listing,
+        render,
+        ping_urls: Arc::new(args.ping_urls),
+        git: args.git.then(|| git::GitConfig {
+            remote: args.git_remote,
+            pull_interval: Duration::from_secs(args.git_pull_interval),
+        }),
+        images: Arc::new(images),
+    };
+
+    if let Some(Command::Export { out }) = args.command {
+        let state = build_state(www_root, args.base_url, settings, db).await?;
+        let stats = export::run(&state, &out).await?;
+        tracing::info!(
+            "Exported {} pages and {} files to {}",
+            stats.pages,
+            stats.files,
+            out.display()
+        );
+    } else if args.headless {
         tracing::info!("Headless mode — TUI disabled");
-        let state = build_state(www_root, args.base_url, db).await?;
+        let state = build_state(www_root, args.base_url, settings, db).await?;
         run_http_server(args.host, args.port, state).await?;
     } else {
         let buffer = log_buffer.expect("log_buffer is Some when not headless");
@@ -119,6 +266,7 @@ async fn main() -> anyhow::Result<()> {
             env_path,
             www_root,
             base_url: args.base_url,
+            settings,
             log_buffer: buffer,
         })
         .await?;
@@ -132,11 +280,22 @@ async fn main() -> anyhow::Result<()> {
 pub(crate) async fn build_state(
     www_root: PathBuf,
     base_url: Option<String>,
+    settings: Settings,
     db: SqlitePool,
 ) -> anyhow::Result<AppState> {
     let canonical_root = tokio::fs::canonicalize(&www_root)
         .await
         .unwrap_or_else(|_| www_root.clone());
+    let cache = cache::RenderCache::new(&canonical_root);
+    if base_url.is_none() {
+        tracing::warn!(
+            "No --base-url set: /sitemap.xml is disabled, since its URLs must be absolute"
+        );
+    }
+    let git = match settings.git {
+        Some(config) => Some(Arc::new(git::open(&www_root, config)?)),
+        None => None,
+    };
 
     Ok(AppState {
         www_root,
@@ -144,6 +303,19 @@ pub(crate) async fn build_state(
         base_url,
         db,
         sessions: Arc::new(RwLock::new(HashMap::new())),
+        cache,
+        compressed: Arc::default(),
+        listing: settings.listing,
+        render: settings.render,
+        ping_urls: settings.ping_urls,
+        git,
+        images: settings.images,
+        headers: Arc::default(),
+        redirects: Arc::default(),
+        backlinks: Arc::default(),
+        writes: Arc::default(),
+        preview_secret: Arc::new(SiteSecret::new(preview::SECRET_SETTING)),
+        access_secret: Arc::new(SiteSecret::new(access::SECRET_SETTING)),
     })
 }
 
@@ -152,14 +324,23 @@ pub(crate) fn build_router(state: AppState) -> Router {
         .route("/healthz", get(|| async { StatusCode::OK }))
         // Redirect /edit/ → /edit to avoid the matchit empty-catchall gap.
         .route("/edit/", get(|| async { Redirect::permanent("/edit") }))
+        .route("/search", get(search::get_search))
+        .route("/search.json", get(search::get_search_json))
+        .route("/_access", post(access::post_password))
         .merge(editor::router(state.clone()))
         .merge(micropub::router(state.clone()))
         .fallback(handler::handle)
+        // Configured response headers — skips /edit/* internally.
+        .layer(middleware::from_fn_with_state(state.clone(), headers::apply))
         // Analytics middleware — skips /healthz and /edit/* internally.
         .layer(middleware::from_fn_with_state(
             state.clone(),
             analytics::log_request,
         ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            compress::compress,
+        ))
         .with_state(state)
         .layer(TraceLayer::new_for_http())
         .layer(CatchPanicLayer::new())
@@ -170,20 +351,29 @@ pub(crate) async fn run_http_server(
     port: u16,
     state: AppState,
 ) -> anyhow::Result<()> {
+    // Background work for this server. Dropping the set aborts it, so a TUI
+    // restart, which aborts this future, doesn't leave old tasks running.
+    let mut tasks = JoinSet::new();
+
     // Periodically evict expired sessions so the map doesn't grow unboundedly
     // when browsers close without logging out.
     let sessions = state.sessions.clone();
-    tokio::spawn(async move {
+    tasks.spawn(async move {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30 * 60));
         loop {
             interval.tick().await;
             sessions
                 .write()
                 .await
-                .retain(|_, created| created.elapsed() < editor::SESSION_TTL);
+                .retain(|_, session| session.last_used.elapsed() < editor::SESSION_TTL);
         }
     });
 
+    search::spawn_indexer(&state, &mut tasks);
+    backlinks::spawn_indexer(&state, &mut tasks);
+    schedule::spawn_scheduler(&state, &mut tasks);
+    git::spawn_puller(&state, &mut tasks);
+
     let addr = format!("{host}:{port}");
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
@@ -5,13 +5,17 @@ use axum::{
     http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
 };
-use chrono::Local;
+use chrono::{Local, Utc};
 use std::collections::HashMap;
 
 use crate::{
+    backlinks, conditional,
     db::{self, TokenRecord},
     editor::handlers::{resolve_read_path, resolve_write_path},
     front_matter::{self, FrontMatter, ParsedDoc, write_front_matter},
+    git,
+    history::{self, Source},
+    search,
     state::AppState,
 };
 use super::types::{
@@ -107,8 +111,8 @@ pub async fn get_query(
 }
 
 async fn handle_source_query(state: &AppState, url: &str) -> Response {
-    let (_fs_path, ParsedDoc { front_matter, content }) = match load_post_by_url(state, url).await {
-        Ok(pair) => pair,
+    let (_fs_path, etag, ParsedDoc { front_matter, content }) = match load_post_by_url(state, url).await {
+        Ok(post) => post,
         Err(r) => return r,
     };
     let rel = url_to_rel_path(state, url).unwrap_or_default();
@@ -133,8 +137,9 @@ async fn handle_source_query(state: &AppState, url: &str) -> Response {
             content: vec![content.trim().to_string()],
             category: front_matter.tags.unwrap_or_default(),
             published: front_matter
-                .date
+                .publish_at
                 .as_deref()
+                .or(front_matter.date.as_deref())
                 .map(|d| vec![d.to_string()])
                 .unwrap_or_default(),
             url: vec![canonical_url],
@@ -146,7 +151,8 @@ async fn handle_source_query(state: &AppState, url: &str) -> Response {
         },
     };
 
-    Json(response).into_response()
+    // Clients can send the ETag back in `If-Match` to update only this version.
+    ([(header::ETAG, etag)], Json(response)).into_response()
 }
 
 // ── POST /micropub ────────────────────────────────────────────────────────────
@@ -180,19 +186,19 @@ pub async fn post_endpoint(
     match request {
         Ok(MicropubRequest::Create(entry)) => {
             if let Some(r) = check_scope(&token, "create") { return r; }
-            handle_create(&state, entry).await
+            handle_create(&state, entry, &token.name).await
         }
         Ok(MicropubRequest::Update(update)) => {
             if let Some(r) = check_scope(&token, "update") { return r; }
-            handle_update(&state, update).await
+            handle_update(&state, update, &token.name, &headers).await
         }
         Ok(MicropubRequest::Delete { url }) => {
             if let Some(r) = check_scope(&token, "delete") { return r; }
-            handle_delete(&state, &url, true).await
+            handle_delete(&state, &url, true, &token.name).await
         }
         Ok(MicropubRequest::Undelete { url }) => {
             if let Some(r) = check_scope(&token, "delete") { return r; }
-            handle_delete(&state, &url, false).await
+            handle_delete(&state, &url, false, &token.name).await
         }
         Err(e) => e,
     }
@@ -200,12 +206,20 @@ pub async fn post_endpoint(
 
 // ── Post loader helper ────────────────────────────────────────────────────────
 
-/// Resolve a Micropub post URL → parsed file. Returns the fs path and ParsedDoc,
-/// or an error Response if the URL cannot be resolved or the file cannot be read.
+/// Resolve a Micropub post URL → parsed file. Returns the fs path, the file's
+/// ETag and ParsedDoc, or an error Response if the URL cannot be resolved or
+/// the file cannot be read.
 async fn load_post_by_url(
     state: &AppState,
     url: &str,
-) -> Result<(std::path::PathBuf, ParsedDoc), Response> {
+) -> Result<(std::path::PathBuf, String, ParsedDoc), Response> {
+    let fs_path = post_path(state, url).await?;
+    let (etag, doc) = read_post(&fs_path).await?;
+    Ok((fs_path, etag, doc))
+}
+
+/// The file behind a post URL.
+async fn post_path(state: &AppState, url: &str) -> Result<std::path::PathBuf, Response> {
     let rel = match url_to_rel_path(state, url) {
         Some(r) => r,
         None => return Err((
@@ -214,15 +228,18 @@ async fn load_post_by_url(
         ).into_response()),
     };
 
-    let fs_path = match resolve_read_path(state, &rel).await {
-        Ok(p) => p,
-        Err(_) => return Err((
+    match resolve_read_path(state, &rel).await {
+        Ok(p) => Ok(p),
+        Err(_) => Err((
             StatusCode::NOT_FOUND,
             Json(MicropubError::new("invalid_request", "Post not found")),
         ).into_response()),
-    };
+    }
+}
 
-    let raw = match tokio::fs::read_to_string(&fs_path).await {
+/// A post's `ETag` and parsed contents.
+async fn read_post(fs_path: &std::path::Path) -> Result<(String, ParsedDoc), Response> {
+    let raw = match tokio::fs::read_to_string(fs_path).await {
         Ok(r) => r,
         Err(_) => return Err((
             StatusCode::NOT_FOUND,
@@ -230,12 +247,13 @@ async fn load_post_by_url(
         ).into_response()),
     };
 
-    Ok((fs_path, front_matter::parse(&raw)))
+    let etag = conditional::content_etag(raw.as_bytes());
+    Ok((etag, front_matter::parse(&raw)))
 }
 
 // ── Create ────────────────────────────────────────────────────────────────────
 
-async fn handle_create(state: &AppState, entry: CreateEntry) -> Response {
+async fn handle_create(state: &AppState, entry: CreateEntry, author: &str) -> Response {
     let post_dir = {
         let v = db::get_micropub_setting(&state.db, "post_dir").await.unwrap_or_default();
         if v.is_empty() { "posts".to_string() } else { v }
@@ -280,6 +298,7 @@ async fn handle_create(state: &AppState, entry: CreateEntry) -> Response {
     let fm = FrontMatter {
         title: entry.name.clone(),
         date: Some(published_date),
+        publish_at: entry.published.as_deref().and_then(scheduled_publish_at),
         draft: Some(is_draft),
         tags: if entry.tags.is_empty() {
             None
@@ -342,6 +361,11 @@ async fn handle_create(state: &AppState, entry: CreateEntry) -> Response {
         None => url_path,
     };
 
+    search::reindex(state, &fs_path).await;
+    backlinks::reindex(state, &fs_path).await;
+    let message = format!("Micropub: create {}", rel_path);
+    git::commit(state, &[&fs_path], author, message).await;
+
     tracing::info!("Micropub: created {}", rel_path);
 
     (StatusCode::CREATED, [(header::LOCATION, location)]).into_response()
@@ -349,13 +373,35 @@ async fn handle_create(state: &AppState, entry: CreateEntry) -> Response {
 
 // ── Update ────────────────────────────────────────────────────────────────────
 
-async fn handle_update(state: &AppState, update: UpdateRequest) -> Response {
+async fn handle_update(
+    state: &AppState,
+    update: UpdateRequest,
+    author: &str,
+    headers: &HeaderMap,
+) -> Response {
     let rel = url_to_rel_path(state, &update.url).unwrap_or_default();
-    let (fs_path, ParsedDoc { mut front_matter, mut content }) =
-        match load_post_by_url(state, &update.url).await {
-            Ok(pair) => pair,
-            Err(r) => return r,
-        };
+    let fs_path = match post_path(state, &update.url).await {
+        Ok(p) => p,
+        Err(r) => return r,
+    };
+    // Held until the write, so the If-Match check can't go stale.
+    let _write = state.writes.lock(&fs_path).await;
+    let (etag, ParsedDoc { mut front_matter, mut content }) = match read_post(&fs_path).await {
+        Ok(post) => post,
+        Err(r) => return r,
+    };
+
+    if !conditional::if_match(headers, Some(&etag)) {
+        return (
+            StatusCode::PRECONDITION_FAILED,
+            [(header::ETAG, etag)],
+            Json(MicropubError::new(
+                "precondition_failed",
+                "The post has changed since the version in If-Match",
+            )),
+        )
+            .into_response();
+    }
 
     // Apply replace operations
     for (prop, values) in &update.replace {
@@ -373,7 +419,10 @@ async fn handle_update(state: &AppState, update: UpdateRequest) -> Response {
             "name" => front_matter.title = None,
             "summary" => front_matter.summary = None,
             "category" => front_matter.tags = None,
-            "published" => front_matter.date = None,
+            "published" => {
+                front_matter.date = None;
+                front_matter.publish_at = None;
+            }
             "post-status" => front_matter.draft = None,
             _ => {} // unknown properties silently ignored
         }
@@ -386,6 +435,7 @@ async fn handle_update(state: &AppState, update: UpdateRequest) -> Response {
             Json(MicropubError::new("server_error", &format!("Failed to serialize front matter: {}", e))),
         ).into_response(),
     };
+    history::record(state, &fs_path, Some(&new_file), author, Source::Micropub).await;
     if let Err(e) = tokio::fs::write(&fs_path, new_file.as_bytes()).await {
         return (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -394,8 +444,14 @@ async fn handle_update(state: &AppState, update: UpdateRequest) -> Response {
             .into_response();
     }
 
+    search::reindex(state, &fs_path).await;
+    backlinks::reindex(state, &fs_path).await;
+    let message = format!("Micropub: update {}", rel);
+    git::commit(state, &[&fs_path], author, message).await;
+
     tracing::info!("Micropub: updated {}", rel);
-    StatusCode::OK.into_response()
+    let etag = conditional::content_etag(new_file.as_bytes());
+    (StatusCode::OK, [(header::ETAG, etag)]).into_response()
 }
 
 enum UpdateOp {
@@ -444,6 +500,7 @@ fn apply_property(
         "published" => {
             if let Some(v) = values.first().and_then(|v| v.as_str()) {
                 fm.date = Some(normalize_date(v));
+                fm.publish_at = scheduled_publish_at(v);
             }
         }
         "post-status" => {
@@ -481,11 +538,11 @@ fn extract_content_value(v: &serde_json::Value) -> String {
 /// Soft-delete: set `draft: true` (or `draft: false` for undelete).
 /// This makes the post invisible to public readers (existing serve_markdown
 /// already returns 404 for drafts) without permanently deleting the file.
-async fn handle_delete(state: &AppState, url: &str, make_draft: bool) -> Response {
+async fn handle_delete(state: &AppState, url: &str, make_draft: bool, author: &str) -> Response {
     let rel = url_to_rel_path(state, url).unwrap_or_default();
-    let (fs_path, ParsedDoc { mut front_matter, content }) =
+    let (fs_path, _etag, ParsedDoc { mut front_matter, content }) =
         match load_post_by_url(state, url).await {
-            Ok(pair) => pair,
+            Ok(post) => post,
             Err(r) => return r,
         };
     front_matter.draft = Some(make_draft);
@@ -497,6 +554,7 @@ async fn handle_delete(state: &AppState, url: &str, make_draft: bool) -> Respons
             Json(MicropubError::new("server_error", &format!("Failed to serialize front matter: {}", e))),
         ).into_response(),
     };
+    history::record(state, &fs_path, Some(&new_file), author, Source::Micropub).await;
     if let Err(e) = tokio::fs::write(&fs_path, new_file.as_bytes()).await {
         return (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -505,7 +563,11 @@ async fn handle_delete(state: &AppState, url: &str, make_draft: bool) -> Respons
             .into_response();
     }
 
+    search::reindex(state, &fs_path).await;
+    backlinks::reindex(state, &fs_path).await;
+
     let action = if make_draft { "deleted (set draft)" } else { "undeleted" };
+    git::commit(state, &[&fs_path], author, format!("Micropub: {} {}", action, rel)).await;
     tracing::info!("Micropub: {} {}", action, rel);
     StatusCode::OK.into_response()
 }
@@ -817,6 +879,14 @@ pub fn slugify(title: &str) -> String {
     }
 }
 
+/// `publish_at` for a `published` value in the future, which queues the post
+/// until then. Past values are just the post's `date`.
+fn scheduled_publish_at(published: &str) -> Option<String> {
+    front_matter::parse_date(published)
+        .filter(|t| *t > Utc::now())
+        .map(|_| published.trim().to_string())
+}
+
 /// Normalise an ISO 8601 date string to `YYYY-MM-DD`.
 fn normalize_date(s: &str) -> String {
     if s.len() >= 10 {
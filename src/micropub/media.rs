@@ -1,12 +1,14 @@
 use axum::{
     Json,
+    body::Bytes,
     extract::{Multipart, State},
     http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
 use chrono::Local;
+use std::path::PathBuf;
 
-use crate::{db, db::TokenRecord, state::AppState};
+use crate::{db, db::TokenRecord, git, images, state::AppState};
 use super::handlers::check_scope;
 use super::types::MicropubError;
 
@@ -20,11 +22,6 @@ pub async fn post_media(
 ) -> Response {
     if let Some(r) = check_scope(&token, "media") { return r; }
 
-    let media_dir = {
-        let v = db::get_micropub_setting(&state.db, "media_dir").await.unwrap_or_default();
-        if v.is_empty() { "_media".to_string() } else { v }
-    };
-
     while let Ok(Some(field)) = multipart.next_field().await {
         if field.name() != Some("file") {
             continue;
@@ -62,24 +59,16 @@ pub async fn post_media(
             }
         };
 
-        // Build storage path: {canonical_root}/{media_dir}/{YYYY}/{MM}/
-        let now = Local::now();
-        let storage_dir = state.canonical_root
-            .join(media_dir.trim_matches('/'))
-            .join(now.format("%Y/%m").to_string());
-
-        if let Err(e) = tokio::fs::create_dir_all(&storage_dir).await {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(MicropubError::new("server_error", &e.to_string())),
-            )
-                .into_response();
-        }
-
-        // Verify the created directory is still within canonical_root
-        let canonical_storage = match tokio::fs::canonicalize(&storage_dir).await {
-            Ok(p) if p.starts_with(&state.canonical_root) => p,
-            _ => {
+        let StoredMedia { path: dest, url_path } = match store_media(&state, &original_name, data).await {
+            Ok(stored) => stored,
+            Err(StoreError::Io(e)) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(MicropubError::new("server_error", &e.to_string())),
+                )
+                    .into_response()
+            }
+            Err(StoreError::OutsideRoot) => {
                 return (
                     StatusCode::FORBIDDEN,
                     Json(MicropubError::new("forbidden", "Storage path escapes www root")),
@@ -88,19 +77,8 @@ pub async fn post_media(
             }
         };
 
-        let safe_name = sanitize_media_filename(&original_name);
-        let final_name = find_available_filename(&canonical_storage, &safe_name).await;
-        let dest = canonical_storage.join(&final_name);
-
-        if let Err(e) = tokio::fs::write(&dest, data).await {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(MicropubError::new("server_error", &e.to_string())),
-            )
-                .into_response();
-        }
-
-        let url_path = format!("/{}/{}/{}", media_dir.trim_matches('/'), now.format("%Y/%m"), final_name);
+        let message = format!("Micropub: upload {}", url_path);
+        git::commit(&state, &[&dest], &token.name, message).await;
         let location = match &state.base_url {
             Some(base) => format!("{}{}", base.trim_end_matches('/'), url_path),
             None => url_path,
@@ -120,6 +98,61 @@ pub async fn post_media(
         .into_response()
 }
 
+/// The media directory setting, relative to the www root.
+pub(crate) async fn media_dir(state: &AppState) -> String {
+    let v = db::get_micropub_setting(&state.db, "media_dir").await.unwrap_or_default();
+    if v.is_empty() { "_media".to_string() } else { v }
+}
+
+/// An upload saved into the media directory.
+pub(crate) struct StoredMedia {
+    pub path: PathBuf,
+    /// URL path of the file, e.g. `/_media/2025/01/photo.jpg`.
+    pub url_path: String,
+}
+
+pub(crate) enum StoreError {
+    Io(std::io::Error),
+    /// The media directory resolves outside the www root.
+    OutsideRoot,
+}
+
+/// Save an upload into `{www_root}/{media_dir}/{YYYY}/{MM}/`, under a
+/// sanitized version of `original_name` that doesn't clash with an existing
+/// file. Shared by Micropub and the editor.
+pub(crate) async fn store_media(
+    state: &AppState,
+    original_name: &str,
+    data: Bytes,
+) -> Result<StoredMedia, StoreError> {
+    let media_dir = media_dir(state).await;
+
+    // Build storage path: {canonical_root}/{media_dir}/{YYYY}/{MM}/
+    let now = Local::now();
+    let storage_dir = state.canonical_root
+        .join(media_dir.trim_matches('/'))
+        .join(now.format("%Y/%m").to_string());
+
+    tokio::fs::create_dir_all(&storage_dir).await.map_err(StoreError::Io)?;
+
+    // Verify the created directory is still within canonical_root
+    let canonical_storage = match tokio::fs::canonicalize(&storage_dir).await {
+        Ok(p) if p.starts_with(&state.canonical_root) => p,
+        _ => return Err(StoreError::OutsideRoot),
+    };
+
+    let safe_name = sanitize_media_filename(original_name);
+    let final_name = find_available_filename(&canonical_storage, &safe_name).await;
+    let dest = canonical_storage.join(&final_name);
+
+    // Don't publish where a photo was taken.
+    let data = images::strip_gps(data.to_vec());
+    tokio::fs::write(&dest, data).await.map_err(StoreError::Io)?;
+
+    let url_path = format!("/{}/{}/{}", media_dir.trim_matches('/'), now.format("%Y/%m"), final_name);
+    Ok(StoredMedia { path: dest, url_path })
+}
+
 /// Sanitize a client-supplied filename to ASCII alphanumeric + `-._` only.
 /// Strips path separators and other dangerous characters.
 fn sanitize_media_filename(name: &str) -> String {
@@ -186,7 +219,7 @@ async fn find_available_filename(dir: &std::path::Path, name: &str) -> String {
 }
 
 /// Check that the MIME type is in the allowed set for media uploads.
-fn is_allowed_media_type(content_type: &str) -> bool {
+pub(crate) fn is_allowed_media_type(content_type: &str) -> bool {
     let base = content_type.split(';').next().unwrap_or("").trim();
     base.starts_with("image/")
         || base.starts_with("video/")
@@ -0,0 +1,159 @@
+//! Shareable preview links for drafts.
+//!
+//! The editor can hand out a URL for a draft (or a page scheduled for later)
+//! that works without an editor account: the page's clean URL with
+//! `?preview=<id>.<expiry>.<signature>`. The signature is an HMAC over the
+//! link id, the file path and the expiry, keyed with a secret kept in the
+//! site settings, so a link can't be altered to reach another file or live
+//! longer. Links are also recorded in SQLite so they can be listed and
+//! revoked from the editor's settings page.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use maud::html;
+use sha2::Sha256;
+use std::time::Duration;
+
+use crate::{db, handler, state::AppState};
+
+/// Query parameter carrying the preview token.
+pub const QUERY_PARAM: &str = "preview";
+
+/// Site setting holding the signing key, generated on first use.
+pub const SECRET_SETTING: &str = "preview_secret";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// An active preview link, for the editor's settings page.
+pub struct PreviewLink {
+    pub id: i64,
+    /// Markdown file relative to the www root.
+    pub path: String,
+    /// The shareable URL, absolute when `--base-url` is set.
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Create a preview link for the markdown file at `rel` that stays valid for
+/// `ttl`, and return its URL.
+pub async fn create(state: &AppState, rel: &str, ttl: Duration) -> Result<String> {
+    let now = Utc::now().timestamp();
+    db::delete_expired_preview_links(&state.db, now).await?;
+    let expires_at = now + ttl.as_secs() as i64;
+    let id = db::create_preview_link(&state.db, rel, expires_at).await?;
+    Ok(link_url(state, id, rel, expires_at).await)
+}
+
+/// Links that have not yet expired, soonest to expire first.
+pub async fn active(state: &AppState) -> Vec<PreviewLink> {
+    let rows = match db::list_preview_links(&state.db, Utc::now().timestamp()).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            tracing::warn!("Cannot list preview links: {e:#}");
+            return Vec::new();
+        }
+    };
+    let mut links = Vec::with_capacity(rows.len());
+    for row in rows {
+        links.push(PreviewLink {
+            id: row.id,
+            url: link_url(state, row.id, &row.path, row.expires_at).await,
+            expires_at: DateTime::from_timestamp(row.expires_at, 0).unwrap_or_default(),
+            path: row.path,
+        });
+    }
+    links
+}
+
+pub async fn revoke(state: &AppState, id: i64) -> Result<()> {
+    db::delete_preview_link(&state.db, id).await
+}
+
+/// The expiry of `token` when it is a valid, unrevoked preview token for the
+/// markdown file at `rel`.
+pub async fn verify(state: &AppState, rel: &str, token: &str) -> Option<DateTime<Utc>> {
+    let mut parts = token.splitn(3, '.');
+    let id: i64 = parts.next()?.parse().ok()?;
+    let expires_at: i64 = parts.next()?.parse().ok()?;
+    let signature = decode_hex(parts.next()?)?;
+
+    mac(state, id, rel, expires_at)
+        .await
+        .verify_slice(&signature)
+        .ok()?;
+    if expires_at <= Utc::now().timestamp() {
+        return None;
+    }
+    match db::preview_link_exists(&state.db, id, rel, expires_at).await {
+        Ok(true) => DateTime::from_timestamp(expires_at, 0),
+        Ok(false) => None,
+        Err(e) => {
+            tracing::warn!("Cannot check preview link: {e:#}");
+            None
+        }
+    }
+}
+
+/// Insert the "Draft preview" banner at the top of a rendered page's body.
+/// The banner carries its own styles so it shows under any stylesheet or
+/// layout.
+pub fn with_banner(page: &str, expires_at: DateTime<Utc>) -> String {
+    let banner = html! {
+        div class="draft-preview-banner" role="status" style="position:sticky;top:0;z-index:1000;padding:0.5em 1em;background:#fff3cd;color:#664d03;border-bottom:1px solid #e0c36c;font:600 0.9rem/1.4 system-ui,sans-serif;text-align:center" {
+            "Draft preview — this page is not published. The link expires "
+            (expires_at.format("%Y-%m-%d %H:%M UTC"))
+            "."
+        }
+    }
+    .into_string();
+
+    let at = page
+        .find("<body")
+        .and_then(|start| page[start..].find('>').map(|end| start + end + 1))
+        .unwrap_or(0);
+    let mut out = String::with_capacity(page.len() + banner.len());
+    out.push_str(&page[..at]);
+    out.push_str(&banner);
+    out.push_str(&page[at..]);
+    out
+}
+
+async fn link_url(state: &AppState, id: i64, rel: &str, expires_at: i64) -> String {
+    let signature: String = mac(state, id, rel, expires_at)
+        .await
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let base = state
+        .base_url
+        .as_deref()
+        .unwrap_or("")
+        .trim_end_matches('/');
+    let (_, url) = handler::page_url(rel);
+    format!(
+        "{}{}?{}={}.{}.{}",
+        base, url, QUERY_PARAM, id, expires_at, signature
+    )
+}
+
+/// HMAC over everything a token vouches for.
+async fn mac(state: &AppState, id: i64, rel: &str, expires_at: i64) -> HmacSha256 {
+    let secret = state.preview_secret.get(&state.db).await;
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}\0{}\0{}", id, rel, expires_at).as_bytes());
+    mac
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
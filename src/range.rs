@@ -0,0 +1,200 @@
+//! HTTP `Range` request parsing and partial-content bodies for static files.
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, header},
+};
+use std::{io, path::Path, time::SystemTime};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::conditional::http_date;
+
+/// Upper bound on distinct ranges served in one multipart response. Requests
+/// asking for more (after merging overlaps) get the full file instead, which
+/// RFC 9110 §14.2 explicitly permits.
+const MAX_RANGES: usize = 32;
+
+/// An inclusive byte range within a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// `Content-Range` value for this range of a `total`-byte file.
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// Outcome of evaluating a `Range` header against a file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable `Range` header — serve the whole file with 200.
+    Full,
+    /// One or more satisfiable ranges, sorted and non-overlapping.
+    Partial(Vec<ByteRange>),
+    /// Syntactically valid but none of the ranges overlap the file — 416.
+    Unsatisfiable,
+}
+
+/// Evaluate the request's `Range` and `If-Range` headers for a file of `len`
+/// bytes whose current validators are `etag` / `modified`.
+pub fn evaluate(
+    headers: &HeaderMap,
+    len: u64,
+    etag: &str,
+    modified: Option<SystemTime>,
+) -> RangeRequest {
+    let Some(range) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()) else {
+        return RangeRequest::Full;
+    };
+
+    // If-Range: only honour the Range when the client's copy is current.
+    if let Some(if_range) = headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) {
+        let if_range = if_range.trim();
+        let current = if if_range.starts_with('"') {
+            // Strong comparison; weak tags never match.
+            if_range == etag
+        } else {
+            modified.map(http_date).as_deref() == Some(if_range)
+        };
+        if !current {
+            return RangeRequest::Full;
+        }
+    }
+
+    parse(range, len)
+}
+
+/// Parse a `Range` header value against a file of `len` bytes.
+///
+/// Malformed headers and non-byte units are ignored (`Full`), as RFC 9110
+/// requires.
+pub fn parse(header: &str, len: u64) -> RangeRequest {
+    let Some((unit, specs)) = header.split_once('=') else {
+        return RangeRequest::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges: Vec<ByteRange> = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        if first.is_empty() {
+            // Suffix range: the final `n` bytes.
+            let Ok(n) = last.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            if n > 0 && len > 0 {
+                ranges.push(ByteRange {
+                    start: len.saturating_sub(n),
+                    end: len - 1,
+                });
+            }
+            continue;
+        }
+
+        let Ok(start) = first.parse::<u64>() else {
+            return RangeRequest::Full;
+        };
+        let end = if last.is_empty() {
+            u64::MAX
+        } else {
+            match last.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return RangeRequest::Full,
+            }
+        };
+        if start < len {
+            ranges.push(ByteRange {
+                start,
+                end: end.min(len - 1),
+            });
+        }
+    }
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    // Merge overlapping or adjacent ranges so clients can't make us send the
+    // same bytes repeatedly.
+    ranges.sort_unstable_by_key(|r| r.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+        match merged.last_mut() {
+            Some(prev) if r.start <= prev.end.saturating_add(1) => {
+                prev.end = prev.end.max(r.end);
+            }
+            _ => merged.push(r),
+        }
+    }
+
+    if merged.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+    RangeRequest::Partial(merged)
+}
+
+/// Stream a single range of the file at `path`.
+pub async fn single_body(path: &Path, range: ByteRange) -> io::Result<Body> {
+    let reader = open_range(path, range).await?;
+    Ok(Body::from_stream(ReaderStream::new(reader)))
+}
+
+/// Build a `multipart/byteranges` body for `ranges` of the file at `path`.
+/// Returns the body, its exact length, and the boundary for the
+/// `Content-Type` header.
+pub async fn multipart_body(
+    path: &Path,
+    ranges: &[ByteRange],
+    mime: &str,
+    total: u64,
+) -> io::Result<(Body, u64, String)> {
+    let boundary = new_boundary();
+    let mut length = 0u64;
+    let mut reader: Box<dyn AsyncRead + Send + Unpin> = Box::new(tokio::io::empty());
+
+    for range in ranges {
+        let part_header = format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            boundary,
+            mime,
+            range.content_range(total)
+        );
+        length += part_header.len() as u64 + range.len();
+        let part = open_range(path, *range).await?;
+        reader = Box::new(reader.chain(io::Cursor::new(part_header)).chain(part));
+    }
+
+    let trailer = format!("\r\n--{}--\r\n", boundary);
+    length += trailer.len() as u64;
+    reader = Box::new(reader.chain(io::Cursor::new(trailer)));
+
+    Ok((Body::from_stream(ReaderStream::new(reader)), length, boundary))
+}
+
+async fn open_range(path: &Path, range: ByteRange) -> io::Result<tokio::io::Take<tokio::fs::File>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(io::SeekFrom::Start(range.start)).await?;
+    Ok(file.take(range.len()))
+}
+
+fn new_boundary() -> String {
+    use rand::RngCore;
+    let mut bytes = [0u8; 12];
+    rand::rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,381 @@
+//! Redirects for moved and removed pages.
+//!
+//! Before `handler::handle` answers 404, the request path is looked up in a
+//! site-wide `_redirects` file at the www root and then among the `aliases`
+//! in each published page's front matter. `_redirects` holds one rule per
+//! line, in the format static hosts use:
+//!
+//! ```text
+//! /old-post          /blog/new-post
+//! /blog/2019/*       /archive/2019/:splat   302
+//! /users/:name       /people/:name
+//! /withdrawn         410
+//! ```
+//!
+//! A rule is a path, a target and an optional status: 301 (the default),
+//! 302, 303, 307, 308, or 410 Gone, which takes no target. `:name` in a path
+//! matches one segment and a trailing `*` the rest of the path; the target
+//! can use both, the latter as `:splat`. Trailing slashes are ignored, and
+//! the request's query string is kept unless the target has its own. The
+//! first matching rule wins, and lines starting with `#` are comments.
+//! Aliases redirect permanently to their page.
+//!
+//! The parsed rules are kept in `AppState::redirects` and parsed again
+//! whenever the file changes, so edits apply at once; lines that don't parse
+//! are logged and skipped. The editor's
+//! `/edit/redirects` page edits it, and a rename in the editor can record a
+//! rule for the old path.
+
+use anyhow::{Result, bail};
+use axum::{
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, utf8_percent_encode};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::{editor, error::AppError, handler, state::AppState};
+
+pub const REDIRECTS_FILE: &str = "_redirects";
+
+/// Characters escaped in targets. Browsers read `\` as `/`, so it is escaped
+/// too.
+const TARGET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'<')
+    .add(b'>')
+    .add(b'`')
+    .add(b'\\');
+
+/// Characters escaped in values captured from the request path, and in the
+/// paths of rules recorded on rename.
+const CAPTURE: &AsciiSet = &TARGET.add(b'%').add(b'?').add(b'#');
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Splat,
+}
+
+/// The last rules parsed, with the file's modification time then, shared by
+/// all requests through `AppState`.
+#[derive(Default)]
+pub struct RedirectCache {
+    parsed: Mutex<Option<(SystemTime, Arc<Vec<Rule>>)>>,
+}
+
+impl RedirectCache {
+    /// The cached rules, if the file hasn't changed since they were parsed.
+    fn get(&self, modified: SystemTime) -> Option<Arc<Vec<Rule>>> {
+        match &*self.parsed.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some((time, rules)) if *time == modified => Some(Arc::clone(rules)),
+            _ => None,
+        }
+    }
+
+    fn set(&self, modified: SystemTime, rules: Arc<Vec<Rule>>) {
+        *self.parsed.lock().unwrap_or_else(|e| e.into_inner()) = Some((modified, rules));
+    }
+}
+
+/// One line of `_redirects`.
+#[derive(Debug)]
+pub struct Rule {
+    from: Vec<Segment>,
+    /// `None` for 410 Gone.
+    to: Option<String>,
+    status: StatusCode,
+}
+
+/// Parse `_redirects` text. Errors name the offending line.
+pub fn parse(source: &str) -> Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    for (n, line) in source.lines().enumerate() {
+        match parse_line(line) {
+            Ok(Some(rule)) => rules.push(rule),
+            Ok(None) => {}
+            Err(e) => bail!("Line {}: {}", n + 1, e),
+        }
+    }
+    Ok(rules)
+}
+
+fn parse_line(line: &str) -> Result<Option<Rule>, String> {
+    if line.trim_start().starts_with('#') {
+        return Ok(None);
+    }
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (from, to, status) = match fields[..] {
+        [] => return Ok(None),
+        [from, status] if status.parse::<u16>().is_ok() => (from, None, status),
+        [from, to] => (from, Some(to), "301"),
+        [from, to, status] => (from, Some(to), status),
+        _ => return Err("expected a path, a target and an optional status".to_string()),
+    };
+    if !from.starts_with('/') {
+        return Err(format!("{:?} must start with /", from));
+    }
+    let status = match status {
+        "301" | "302" | "303" | "307" | "308" | "410" => {
+            StatusCode::from_bytes(status.as_bytes()).map_err(|e| e.to_string())?
+        }
+        _ => return Err(format!("unsupported status {}", status)),
+    };
+    let to = match (to, status) {
+        (_, StatusCode::GONE) => None,
+        (None, _) => return Err(format!("status {} needs a target", status.as_u16())),
+        (Some(to), _) if !to.starts_with('/') && !to.contains("://") => {
+            return Err(format!("{:?} must be a path or a full URL", to));
+        }
+        (Some(to), _) => Some(utf8_percent_encode(to, TARGET).to_string()),
+    };
+
+    let decoded = percent_decode_str(from).decode_utf8_lossy();
+    let parts = segments(&decoded);
+    let mut from = Vec::with_capacity(parts.len());
+    for (i, part) in parts.iter().enumerate() {
+        from.push(match *part {
+            "*" if i + 1 == parts.len() => Segment::Splat,
+            "*" => return Err("* may only end a path".to_string()),
+            _ => match part.strip_prefix(':') {
+                Some(name) if !name.is_empty() => Segment::Param(name.to_string()),
+                _ => Segment::Literal(part.to_string()),
+            },
+        });
+    }
+    Ok(Some(Rule { from, to, status }))
+}
+
+/// The segments of a path, ignoring leading and trailing slashes.
+fn segments(path: &str) -> Vec<&str> {
+    path.trim_matches('/')
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+impl Rule {
+    /// The values captured from `path`'s segments, if the rule matches it.
+    fn captures(&self, path: &[&str]) -> Option<Vec<(&str, String)>> {
+        let mut captures = Vec::new();
+        for (i, segment) in self.from.iter().enumerate() {
+            match segment {
+                Segment::Splat => {
+                    captures.push(("splat", path.get(i..).unwrap_or_default().join("/")));
+                    return Some(captures);
+                }
+                Segment::Param(name) => captures.push((name, path.get(i)?.to_string())),
+                Segment::Literal(text) => {
+                    if path.get(i) != Some(&text.as_str()) {
+                        return None;
+                    }
+                }
+            }
+        }
+        (path.len() == self.from.len()).then_some(captures)
+    }
+}
+
+/// `to` with each `:name` it uses replaced by the captured value.
+fn substitute(to: &str, captures: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(to.len());
+    let mut rest = to;
+    while let Some(colon) = rest.find(':') {
+        out.push_str(&rest[..colon]);
+        let after = &rest[colon + 1..];
+        let len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        match captures.iter().find(|(name, _)| *name == &after[..len]) {
+            Some((_, value)) if len > 0 => {
+                out.extend(utf8_percent_encode(value, CAPTURE));
+                rest = &after[len..];
+            }
+            _ => {
+                out.push(':');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// ── Lookup ────────────────────────────────────────────────────────────────────
+
+/// The redirect or 410 for a request that found nothing, if a rule or alias
+/// covers its (percent-encoded) `path`.
+pub async fn find(state: &AppState, path: &str, query: Option<&str>) -> Option<Response> {
+    let decoded = percent_decode_str(path).decode_utf8_lossy();
+    let request = segments(&decoded);
+
+    for rule in load(state).await.iter() {
+        let Some(captures) = rule.captures(&request) else {
+            continue;
+        };
+        let Some(to) = &rule.to else {
+            return Some(AppError::Gone.into_response());
+        };
+        let target = substitute(to, &captures);
+        // A path must stay on this site: `//host` and `/\host` leave it.
+        if target.starts_with('/') && editor::safe_next(&target).is_none() {
+            continue;
+        }
+        // A rule pointing back at the request would loop.
+        if segments(target.split(['?', '#']).next().unwrap_or("")) == segments(path) {
+            continue;
+        }
+        return Some(redirect(rule.status, &target, query));
+    }
+
+    let pages = handler::load_site_pages(state).await;
+    pages.iter().find_map(|page| {
+        page.aliases
+            .iter()
+            .any(|alias| segments(alias) == request && segments(&page.url) != request)
+            .then(|| redirect(StatusCode::MOVED_PERMANENTLY, &page.url, query))
+    })
+}
+
+fn redirect(status: StatusCode, target: &str, query: Option<&str>) -> Response {
+    let target = match query {
+        Some(query) if !query.is_empty() && !target.contains('?') => {
+            format!("{}?{}", target, query)
+        }
+        _ => target.to_string(),
+    };
+    let encoded = utf8_percent_encode(&target, TARGET).to_string();
+    match HeaderValue::from_str(&encoded) {
+        Ok(location) => (status, [(header::LOCATION, location)]).into_response(),
+        Err(_) => AppError::NotFound.into_response(),
+    }
+}
+
+/// The site's rules, skipping lines that don't parse. The file is parsed
+/// again only when its modification time changes.
+async fn load(state: &AppState) -> Arc<Vec<Rule>> {
+    let path = file_path(state);
+    let modified = match tokio::fs::metadata(&path).await {
+        Ok(meta) => meta.modified().ok(),
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Cannot read {}: {}", REDIRECTS_FILE, e);
+            }
+            return Arc::default();
+        }
+    };
+    if let Some(rules) = modified.and_then(|m| state.redirects.get(m)) {
+        return rules;
+    }
+
+    let source = tokio::fs::read_to_string(&path).await.unwrap_or_else(|e| {
+        tracing::warn!("Cannot read {}: {}", REDIRECTS_FILE, e);
+        String::new()
+    });
+    let rules: Arc<Vec<Rule>> = Arc::new(
+        source
+            .lines()
+            .enumerate()
+            .filter_map(|(n, line)| {
+                parse_line(line)
+                    .inspect_err(|e| {
+                        tracing::warn!("{} line {}: {}, skipping it", REDIRECTS_FILE, n + 1, e)
+                    })
+                    .ok()
+                    .flatten()
+            })
+            .collect(),
+    );
+    if let Some(time) = modified {
+        state.redirects.set(time, Arc::clone(&rules));
+    }
+    rules
+}
+
+// ── Editing ───────────────────────────────────────────────────────────────────
+
+pub fn file_path(state: &AppState) -> PathBuf {
+    state.canonical_root.join(REDIRECTS_FILE)
+}
+
+/// The current `_redirects` text; empty when there is none.
+pub async fn source(state: &AppState) -> String {
+    tokio::fs::read_to_string(file_path(state))
+        .await
+        .unwrap_or_default()
+}
+
+/// Validate and write new `_redirects` text.
+pub async fn save(state: &AppState, source: &str) -> Result<()> {
+    // Browsers submit textareas with CRLF line endings.
+    let mut source = source.replace("\r\n", "\n");
+    parse(&source)?;
+    if !source.is_empty() && !source.ends_with('\n') {
+        source.push('\n');
+    }
+    tokio::fs::write(file_path(state), source).await?;
+    Ok(())
+}
+
+/// Append a permanent redirect from `from` to `to`, both decoded URL paths,
+/// unless `_redirects` already has that line.
+pub async fn add(state: &AppState, from: &str, to: &str) -> Result<()> {
+    let line = permanent(from, to);
+    let mut source = source(state).await;
+    if source.lines().any(|l| l.trim() == line) {
+        return Ok(());
+    }
+    if !source.is_empty() && !source.ends_with('\n') {
+        source.push('\n');
+    }
+    source.push_str(&line);
+    source.push('\n');
+    tokio::fs::write(file_path(state), source).await?;
+    Ok(())
+}
+
+/// A `_redirects` line sending the decoded path `from` to `to` for good.
+fn permanent(from: &str, to: &str) -> String {
+    format!(
+        "{} {} 301",
+        utf8_percent_encode(from, CAPTURE),
+        utf8_percent_encode(to, CAPTURE)
+    )
+}
+
+/// `_redirects` with a rule for each alias appended, for static hosts that
+/// read the file themselves.
+pub async fn with_aliases(state: &AppState) -> String {
+    let mut source = source(state).await;
+    for (alias, url) in aliases(state).await {
+        if !source.is_empty() && !source.ends_with('\n') {
+            source.push('\n');
+        }
+        source.push_str(&permanent(
+            &format!("/{}", alias.trim_start_matches('/')),
+            &url,
+        ));
+        source.push('\n');
+    }
+    source
+}
+
+/// Front matter aliases of every published page, with the URL each
+/// redirects to.
+pub async fn aliases(state: &AppState) -> Vec<(String, String)> {
+    let pages = handler::load_site_pages(state).await;
+    pages
+        .iter()
+        .flat_map(|page| {
+            page.aliases
+                .iter()
+                .map(|alias| (alias.clone(), page.url.clone()))
+        })
+        .collect()
+}
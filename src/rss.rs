@@ -1,23 +1,127 @@
-use chrono::NaiveDate;
+//! Syndication feeds for listings: RSS 2.0 (`feed.xml` / `rss.xml`), Atom 1.0
+//! (`atom.xml`) and JSON Feed 1.1 (`feed.json`), all built from the same
+//! `DirEntry` list.
 
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use serde_json::{Value, json};
+use std::time::SystemTime;
+
+use crate::front_matter;
 use crate::handler::render_markdown;
+use crate::state::RenderConfig;
 use crate::template::DirEntry;
+use crate::wiki;
+
+/// Limits applied to every generated feed.
+#[derive(Debug, Clone, Copy)]
+pub struct FeedOptions {
+    /// Maximum number of items (the newest, as `items` are sorted by date);
+    /// 0 means unlimited.
+    pub max_items: usize,
+    /// Emit only each item's summary instead of its full rendered HTML.
+    pub summary_only: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    /// Every format, in the order auto-discovery links are emitted.
+    pub const ALL: [FeedFormat; 3] = [FeedFormat::Rss, FeedFormat::Atom, FeedFormat::Json];
+
+    /// Recognise a feed by file name (case-insensitive).
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "feed.xml" | "rss.xml" => Some(FeedFormat::Rss),
+            "atom.xml" => Some(FeedFormat::Atom),
+            "feed.json" => Some(FeedFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Canonical file name, relative to the listing URL.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "feed.xml",
+            FeedFormat::Atom => "atom.xml",
+            FeedFormat::Json => "feed.json",
+        }
+    }
+
+    /// Media type for the response and for `<link rel="alternate">`.
+    pub fn mime(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml",
+            FeedFormat::Atom => "application/atom+xml",
+            FeedFormat::Json => "application/feed+json",
+        }
+    }
+
+    pub fn content_type(self) -> String {
+        format!("{}; charset=utf-8", self.mime())
+    }
+
+    /// Human-readable name for auto-discovery link titles.
+    pub fn label(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "RSS",
+            FeedFormat::Atom => "Atom",
+            FeedFormat::Json => "JSON Feed",
+        }
+    }
+}
+
+/// Feed-level metadata. `link` and `feed_url` should already include the
+/// base URL when one is configured.
+pub struct Channel<'a> {
+    pub title: &'a str,
+    /// The HTML page the feed describes.
+    pub link: &'a str,
+    /// The feed's own URL.
+    pub feed_url: &'a str,
+    pub description: &'a str,
+}
+
+/// Build a feed of `items` in `format`. `site_pages` resolve wiki links in
+/// item content, rendered with `render`; see `build_feed` for `base_url`.
+pub fn build(
+    format: FeedFormat,
+    channel: &Channel,
+    items: &[DirEntry],
+    site_pages: &[DirEntry],
+    base_url: &str,
+    options: FeedOptions,
+    render: &RenderConfig,
+) -> String {
+    let items = match options.max_items {
+        0 => items,
+        n => &items[..items.len().min(n)],
+    };
+    match format {
+        FeedFormat::Rss => build_feed(channel, items, site_pages, base_url, options, render),
+        FeedFormat::Atom => build_atom(channel, items, site_pages, base_url, options, render),
+        FeedFormat::Json => build_json_feed(channel, items, site_pages, base_url, options, render),
+    }
+}
 
 /// Build a complete RSS 2.0 feed as an XML string.
 ///
 /// `base_url` should be an absolute origin like `"https://example.com"` (no
 /// trailing slash). When empty, item links are relative paths and
 /// `<guid isPermaLink>` is set to `"false"`.
-pub fn build_feed(
-    channel_title: &str,
-    channel_link: &str,
-    channel_description: &str,
+fn build_feed(
+    channel: &Channel,
     items: &[DirEntry],
+    site_pages: &[DirEntry],
     base_url: &str,
+    options: FeedOptions,
+    render: &RenderConfig,
 ) -> String {
-    // Determine whether we can emit valid absolute URLs.
-    let has_absolute_base = base_url.starts_with("http://") || base_url.starts_with("https://");
-    let permalink_attr = if has_absolute_base { "true" } else { "false" };
+    let permalink_attr = if has_absolute_base(base_url) { "true" } else { "false" };
 
     let mut xml = String::from(
         "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
@@ -25,16 +129,16 @@ pub fn build_feed(
          <channel>\n",
     );
 
-    xml.push_str(&format!("  <title>{}</title>\n", esc(channel_title)));
-    xml.push_str(&format!("  <link>{}</link>\n", esc(channel_link)));
+    xml.push_str(&format!("  <title>{}</title>\n", esc(channel.title)));
+    xml.push_str(&format!("  <link>{}</link>\n", esc(channel.link)));
     xml.push_str(&format!(
         "  <description>{}</description>\n",
-        esc(channel_description)
+        esc(channel.description)
     ));
 
     for item in items {
         let title = item.title.as_deref().unwrap_or(&item.display_name);
-        let link = format!("{}{}", base_url.trim_end_matches('/'), item.url);
+        let link = item_link(base_url, item);
 
         xml.push_str("  <item>\n");
         xml.push_str(&format!("    <title>{}</title>\n", esc(title)));
@@ -44,10 +148,9 @@ pub fn build_feed(
             permalink_attr,
             esc(&link)
         ));
-        if let Some(md) = &item.content {
-            let html = render_markdown(md);
+        if let Some(html) = content_html(item, site_pages, options, render) {
             xml.push_str("    <description><![CDATA[");
-            xml.push_str(&html);
+            xml.push_str(&html.replace("]]>", "]]]]><![CDATA[>"));
             xml.push_str("]]></description>\n");
         } else if let Some(summary) = &item.summary {
             xml.push_str(&format!(
@@ -56,13 +159,19 @@ pub fn build_feed(
             ));
         }
         if let Some(date) = &item.date {
-            xml.push_str(&format!("    <pubDate>{}</pubDate>\n", to_rfc822(date)));
+            let pub_date = front_matter::parse_date(date)
+                .map(|d| d.to_rfc2822())
+                .unwrap_or_else(|| date.clone());
+            xml.push_str(&format!("    <pubDate>{}</pubDate>\n", pub_date));
         }
         if let Some(author) = &item.author {
             // <dc:creator> accepts a plain name; the core RSS <author> element
             // requires an email address, which we don't have.
             xml.push_str(&format!("    <dc:creator>{}</dc:creator>\n", esc(author)));
         }
+        for tag in &item.tags {
+            xml.push_str(&format!("    <category>{}</category>\n", esc(tag)));
+        }
         xml.push_str("  </item>\n");
     }
 
@@ -70,19 +179,190 @@ pub fn build_feed(
     xml
 }
 
+/// Build an Atom 1.0 feed. Entries carry `published` from the front matter
+/// date and `updated` from the file's modification time.
+fn build_atom(
+    channel: &Channel,
+    items: &[DirEntry],
+    site_pages: &[DirEntry],
+    base_url: &str,
+    options: FeedOptions,
+    render: &RenderConfig,
+) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+    );
+
+    let feed_updated = items
+        .iter()
+        .filter_map(updated)
+        .max()
+        .unwrap_or(DateTime::<Utc>::UNIX_EPOCH.fixed_offset());
+
+    xml.push_str(&format!("  <id>{}</id>\n", esc(&atom_id(base_url, channel.link))));
+    xml.push_str(&format!("  <title>{}</title>\n", esc(channel.title)));
+    if !channel.description.is_empty() {
+        xml.push_str(&format!("  <subtitle>{}</subtitle>\n", esc(channel.description)));
+    }
+    xml.push_str(&format!("  <updated>{}</updated>\n", rfc3339(feed_updated)));
+    xml.push_str(&format!(
+        "  <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n",
+        esc(channel.link)
+    ));
+    xml.push_str(&format!(
+        "  <link rel=\"self\" type=\"{}\" href=\"{}\"/>\n",
+        FeedFormat::Atom.mime(),
+        esc(channel.feed_url)
+    ));
+
+    for item in items {
+        let title = item.title.as_deref().unwrap_or(&item.display_name);
+        let link = item_link(base_url, item);
+        let published = item.date.as_deref().and_then(front_matter::parse_date);
+        let updated = updated(item).unwrap_or(feed_updated);
+
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>{}</id>\n", esc(&atom_id(base_url, &link))));
+        xml.push_str(&format!("    <title>{}</title>\n", esc(title)));
+        xml.push_str(&format!(
+            "    <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n",
+            esc(&link)
+        ));
+        if let Some(published) = published {
+            xml.push_str(&format!("    <published>{}</published>\n", rfc3339(published)));
+        }
+        xml.push_str(&format!("    <updated>{}</updated>\n", rfc3339(updated)));
+        if let Some(author) = &item.author {
+            xml.push_str(&format!(
+                "    <author><name>{}</name></author>\n",
+                esc(author)
+            ));
+        }
+        for tag in &item.tags {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", esc(tag)));
+        }
+        if let Some(summary) = &item.summary {
+            xml.push_str(&format!("    <summary>{}</summary>\n", esc(summary)));
+        }
+        if let Some(html) = content_html(item, site_pages, options, render) {
+            xml.push_str(&format!(
+                "    <content type=\"html\">{}</content>\n",
+                esc(&html)
+            ));
+        }
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>");
+    xml
+}
+
+/// Build a JSON Feed 1.1 document.
+fn build_json_feed(
+    channel: &Channel,
+    items: &[DirEntry],
+    site_pages: &[DirEntry],
+    base_url: &str,
+    options: FeedOptions,
+    render: &RenderConfig,
+) -> String {
+    let items: Vec<Value> = items
+        .iter()
+        .map(|item| {
+            let link = item_link(base_url, item);
+            let mut obj = json!({
+                "id": link,
+                "url": link,
+                "title": item.title.as_deref().unwrap_or(&item.display_name),
+            });
+            // Every item needs `content_html` or `content_text`.
+            match content_html(item, site_pages, options, render) {
+                Some(html) => obj["content_html"] = json!(html),
+                None => obj["content_text"] = json!(item.summary.as_deref().unwrap_or("")),
+            }
+            if let Some(summary) = &item.summary {
+                obj["summary"] = json!(summary);
+            }
+            if let Some(published) = item.date.as_deref().and_then(front_matter::parse_date) {
+                obj["date_published"] = json!(rfc3339(published));
+            }
+            if let Some(updated) = updated(item) {
+                obj["date_modified"] = json!(rfc3339(updated));
+            }
+            if let Some(author) = &item.author {
+                obj["authors"] = json!([{ "name": author }]);
+            }
+            if !item.tags.is_empty() {
+                obj["tags"] = json!(item.tags);
+            }
+            obj
+        })
+        .collect();
+
+    let mut feed = json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": channel.title,
+        "home_page_url": channel.link,
+        "feed_url": channel.feed_url,
+        "items": items,
+    });
+    if !channel.description.is_empty() {
+        feed["description"] = json!(channel.description);
+    }
+    serde_json::to_string_pretty(&feed).unwrap_or_default()
+}
+
+fn has_absolute_base(base_url: &str) -> bool {
+    base_url.starts_with("http://") || base_url.starts_with("https://")
+}
+
+fn item_link(base_url: &str, item: &DirEntry) -> String {
+    format!("{}{}", base_url.trim_end_matches('/'), item.url)
+}
+
+/// Atom IDs must be absolute IRIs. Without a base URL, fall back to a URN
+/// built from the path so IDs are still stable.
+fn atom_id(base_url: &str, link: &str) -> String {
+    if has_absolute_base(base_url) {
+        link.to_string()
+    } else {
+        format!("urn:md-server:{}", link)
+    }
+}
+
+fn content_html(
+    item: &DirEntry,
+    site_pages: &[DirEntry],
+    options: FeedOptions,
+    render: &RenderConfig,
+) -> Option<String> {
+    if options.summary_only {
+        return None;
+    }
+    let links = wiki::Resolver::new(site_pages, &item.url);
+    item.content.as_deref().map(|c| render_markdown(c, &links, render))
+}
+
+/// Last change to an entry: the file's mtime, but never before its
+/// published date.
+fn updated(item: &DirEntry) -> Option<DateTime<FixedOffset>> {
+    let modified = item
+        .modified
+        .map(|t: SystemTime| DateTime::<Utc>::from(t).fixed_offset());
+    let published = item.date.as_deref().and_then(front_matter::parse_date);
+    modified.max(published)
+}
+
+fn rfc3339(dt: DateTime<FixedOffset>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
 /// XML-escape a string for use in element content or attribute values.
-fn esc(s: &str) -> String {
+pub(crate) fn esc(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
         .replace('"', "&quot;")
         .replace('\'', "&apos;")
 }
-
-/// Convert a `YYYY-MM-DD` date string to RFC 822 format required by RSS 2.0.
-/// Falls back to the original string if parsing fails.
-fn to_rfc822(date_str: &str) -> String {
-    NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
-        .map(|d| d.format("%a, %d %b %Y 00:00:00 +0000").to_string())
-        .unwrap_or_else(|_| date_str.to_string())
-}
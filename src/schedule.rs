@@ -0,0 +1,167 @@
+//! Scheduled publishing.
+//!
+//! A page whose `publish_at` (or, without one, `date`) is in the future is
+//! hidden like a draft until that moment (see `FrontMatter::is_hidden`).
+//! Visibility is checked on every request, but cached listings, feeds and the
+//! search and link indexes are only refreshed when files change, so a
+//! background task tracks upcoming publish times and, as each one passes,
+//! touches the page in the render cache — which also reaches the indexers —
+//! and pings any configured WebSub hubs with the feeds that now include it.
+
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{sync::broadcast::error::RecvError, task::JoinSet};
+
+use crate::{front_matter, handler, rss::FeedFormat, state::AppState, tags::tag_url};
+
+/// Longest single sleep, so clock changes and suspends are noticed.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Publish scheduled pages as their time comes.
+pub fn spawn_scheduler(state: &AppState, tasks: &mut JoinSet<()>) {
+    let state = state.clone();
+    // Subscribe before the initial scan so no change slips through the gap.
+    let mut changes = state.cache.subscribe();
+
+    tasks.spawn(async move {
+        let root = state.canonical_root.clone();
+        let mut pending = scan(&root).await;
+        if !pending.is_empty() {
+            tracing::info!("{} page(s) scheduled for publishing", pending.len());
+        }
+
+        loop {
+            let wait = pending
+                .values()
+                .min()
+                .map(|t| (*t - Utc::now()).to_std().unwrap_or_default())
+                .unwrap_or(MAX_SLEEP)
+                .min(MAX_SLEEP);
+
+            tokio::select! {
+                change = changes.recv() => match change {
+                    Ok(path) => update(&mut pending, &root, &path).await,
+                    Err(RecvError::Lagged(_)) => pending = scan(&root).await,
+                    Err(RecvError::Closed) => break,
+                },
+                () = tokio::time::sleep(wait) => {
+                    let now = Utc::now();
+                    let due: Vec<PathBuf> = pending
+                        .iter()
+                        .filter(|(_, t)| **t <= now)
+                        .map(|(p, _)| p.clone())
+                        .collect();
+                    for path in due {
+                        pending.remove(&path);
+                        publish(&state, &path).await;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Future publish time of every non-draft page under `dir`.
+async fn scan(dir: &Path) -> HashMap<PathBuf, DateTime<Utc>> {
+    let mut pending = HashMap::new();
+    for (path, _) in handler::walk_markdown(dir).await {
+        if let Some(at) = scheduled_at(&path).await {
+            pending.insert(path, at);
+        }
+    }
+    pending
+}
+
+async fn update(pending: &mut HashMap<PathBuf, DateTime<Utc>>, root: &Path, path: &Path) {
+    if path == root {
+        *pending = scan(root).await;
+        return;
+    }
+    pending.retain(|p, _| !p.starts_with(path));
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(meta) if meta.is_dir() => pending.extend(scan(path).await),
+        Ok(meta) if meta.is_file() && path.extension().is_some_and(|e| e == "md") => {
+            if let Some(at) = scheduled_at(path).await {
+                pending.insert(path.to_path_buf(), at);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The page's publish time, when it is a non-draft still waiting for it.
+async fn scheduled_at(path: &Path) -> Option<DateTime<Utc>> {
+    let raw = tokio::fs::read_to_string(path).await.ok()?;
+    let fm = front_matter::parse(&raw).front_matter;
+    if fm.draft == Some(true) {
+        return None;
+    }
+    let at = fm.publish_time()?.with_timezone(&Utc);
+    (at > Utc::now()).then_some(at)
+}
+
+async fn publish(state: &AppState, path: &Path) {
+    let rel = handler::rel_path(&state.canonical_root, path);
+    tracing::info!("Publishing scheduled page {}", rel);
+    state.cache.touch(path);
+
+    let hubs = state.ping_urls.as_slice();
+    if hubs.is_empty() {
+        return;
+    }
+    let Some(base) = state.base_url.as_deref().map(|b| b.trim_end_matches('/')) else {
+        tracing::warn!("Not pinging feed hubs for {}: --base-url is not set", rel);
+        return;
+    };
+
+    let (_, url) = handler::page_url(&rel);
+    let mut feed_dirs: Vec<String> = handler::listing_feed_dir(path, &url)
+        .await
+        .into_iter()
+        .collect();
+    if let Ok(raw) = tokio::fs::read_to_string(path).await {
+        let tags = front_matter::parse(&raw)
+            .front_matter
+            .tags
+            .unwrap_or_default();
+        feed_dirs.extend(tags.iter().map(|t| tag_url(t)));
+    }
+    let feeds: Vec<String> = feed_dirs
+        .iter()
+        .flat_map(|dir| FeedFormat::ALL.map(|f| format!("{}{}{}", base, dir, f.file_name())))
+        .collect();
+
+    let client = reqwest::Client::new();
+    for hub in hubs {
+        for feed in &feeds {
+            ping(&client, hub, feed).await;
+        }
+    }
+}
+
+/// Tell a WebSub hub that `feed` has new content.
+async fn ping(client: &reqwest::Client, hub: &str, feed: &str) {
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("hub.mode", "publish")
+        .append_pair("hub.url", feed)
+        .finish();
+    let result = client
+        .post(hub)
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded",
+        )
+        .body(body)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status());
+    match result {
+        Ok(_) => tracing::info!("Pinged {} for {}", hub, feed),
+        Err(e) => tracing::warn!("Ping to {} for {} failed: {}", hub, feed, e),
+    }
+}
@@ -0,0 +1,349 @@
+//! Full-text search over every published markdown page, backed by an SQLite
+//! FTS5 table.
+//!
+//! The index is brought up to date on startup (only files whose mtime changed
+//! are re-read) and then kept current from the render cache's change stream.
+//! Editor and Micropub writes also reindex the file directly, so results are
+//! fresh even when the file watcher is unavailable.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    response::{Html, IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::broadcast::error::RecvError, task::JoinSet};
+
+use crate::{
+    access,
+    db::{self, SearchDoc, SearchHit},
+    error::AppError,
+    front_matter::{self, ParsedDoc},
+    handler,
+    state::AppState,
+    template,
+    wiki,
+};
+
+/// Results per page unless `limit` says otherwise.
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 50;
+
+/// Delimiters FTS5 wraps around matched terms in snippets. Control characters
+/// can't appear in indexed text, so they survive HTML escaping unambiguously.
+const MARK_OPEN: &str = "\u{1}";
+const MARK_CLOSE: &str = "\u{2}";
+
+// ── Indexing ──────────────────────────────────────────────────────────────────
+
+/// Bring the index up to date and keep it current from the file watcher.
+pub fn spawn_indexer(state: &AppState, tasks: &mut JoinSet<()>) {
+    let state = state.clone();
+    // Subscribe before the initial scan so no change slips through the gap.
+    let mut changes = state.cache.subscribe();
+
+    tasks.spawn(async move {
+        reindex_all(&state).await;
+        loop {
+            match changes.recv().await {
+                Ok(path) => update_path(&state, &path).await,
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("Search indexer missed {} changes, rescanning", n);
+                    reindex_all(&state).await;
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Reindex `path` (a file or directory under the canonical root, which may no
+/// longer exist) after a write.
+pub async fn reindex(state: &AppState, path: &Path) {
+    update_path(state, path).await;
+}
+
+/// Index every markdown file whose mtime differs from the indexed copy and
+/// drop entries for files that no longer exist.
+async fn reindex_all(state: &AppState) {
+    let (db, root) = (&state.db, state.canonical_root.as_path());
+    let indexed = match db::search_indexed_mtimes(db).await {
+        Ok(m) => m,
+        Err(e) => {
+            tracing::error!("Cannot read search index: {e:#}");
+            return;
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let mut updated = 0usize;
+    for (path, modified) in handler::walk_markdown(root).await {
+        let rel = handler::rel_path(root, &path);
+        if indexed.get(&rel) != Some(&mtime(modified)) {
+            index_file(state, &path).await;
+            updated += 1;
+        }
+        seen.insert(rel);
+    }
+
+    let mut removed = 0usize;
+    for rel in indexed.keys().filter(|rel| !seen.contains(*rel)) {
+        if let Err(e) = db::delete_search_docs(db, rel).await {
+            tracing::warn!("Cannot remove {} from search index: {e:#}", rel);
+        }
+        removed += 1;
+    }
+
+    if updated > 0 || removed > 0 {
+        tracing::info!("Search index: {} updated, {} removed", updated, removed);
+    }
+}
+
+async fn update_path(state: &AppState, path: &Path) {
+    let root = state.canonical_root.as_path();
+    if path == root {
+        reindex_all(state).await;
+        return;
+    }
+    let Ok(rel) = path.strip_prefix(root) else {
+        return;
+    };
+    if rel
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    {
+        return;
+    }
+
+    match tokio::fs::symlink_metadata(path).await {
+        Ok(meta) if meta.is_dir() => {
+            for (file, _) in handler::walk_markdown(path).await {
+                index_file(state, &file).await;
+            }
+        }
+        Ok(meta) if meta.is_file() => {
+            if path.extension().is_some_and(|e| e == "md") {
+                index_file(state, path).await;
+            }
+        }
+        Ok(_) => {}
+        Err(_) => {
+            // Gone: drop the page, or everything under a removed directory.
+            let rel = handler::rel_path(root, path);
+            if let Err(e) = db::delete_search_docs(&state.db, &rel).await {
+                tracing::warn!("Cannot remove {} from search index: {e:#}", rel);
+            }
+        }
+    }
+}
+
+/// Parse and index one markdown file the same way the page handler renders
+/// it. Drafts are removed from the index instead.
+async fn index_file(state: &AppState, path: &Path) {
+    let db = &state.db;
+    let rel = handler::rel_path(&state.canonical_root, path);
+    let raw = match tokio::fs::read_to_string(path).await {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::warn!("Cannot index {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let modified = tokio::fs::metadata(path)
+        .await
+        .and_then(|m| m.modified())
+        .ok();
+
+    let ParsedDoc {
+        mut front_matter,
+        content,
+    } = front_matter::parse(&raw);
+    front_matter::fill_inferred(&mut front_matter, &content, path).await;
+
+    let result = if front_matter.is_hidden() {
+        db::delete_search_docs(db, &rel).await
+    } else {
+        let (display_name, url) = handler::page_url(&rel);
+        // Only link text is indexed, so wiki links needn't resolve.
+        let links = wiki::Resolver::new(&[], &url);
+        let body = html_to_text(&handler::render_markdown(&content, &links, &state.render));
+        let doc = SearchDoc {
+            url,
+            mtime: mtime(modified),
+            title: front_matter.title.unwrap_or(display_name),
+            summary: front_matter.summary.unwrap_or_default(),
+            body,
+            path: rel,
+        };
+        db::upsert_search_doc(db, &doc).await
+    };
+
+    if let Err(e) = result {
+        tracing::warn!("Cannot index {}: {e:#}", path.display());
+    }
+}
+
+fn mtime(modified: Option<SystemTime>) -> i64 {
+    modified
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or(0)
+}
+
+/// Strip tags from rendered HTML and decode the entities the markdown
+/// renderer emits, leaving whitespace-collapsed plain text.
+pub(crate) fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// ── Querying ──────────────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    pub q: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct SearchResponse<'a> {
+    query: &'a str,
+    results: Vec<SearchResult>,
+}
+
+#[derive(Serialize)]
+struct SearchResult {
+    title: String,
+    summary: String,
+    url: String,
+    /// HTML with matched terms wrapped in `<mark>`.
+    snippet: String,
+}
+
+/// `GET /search?q=` — HTML results page.
+pub async fn get_search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Result<Response, AppError> {
+    let hits = run_query(&state, &params).await?;
+    let css = handler::root_css(&state).await;
+    let hits: Vec<SearchHit> = hits
+        .into_iter()
+        .map(|h| SearchHit {
+            snippet: highlight(&h.snippet),
+            ..h
+        })
+        .collect();
+    let page = template::search_page(params.q.trim(), &hits, css.as_deref());
+    Ok(Html(page.into_string()).into_response())
+}
+
+/// `GET /search.json?q=` — the same results for client-side widgets. URLs are
+/// absolute when `--base-url` is set.
+pub async fn get_search_json(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Result<Response, AppError> {
+    let hits = run_query(&state, &params).await?;
+    let base_url = state.base_url.as_deref().unwrap_or("").trim_end_matches('/');
+    let results = hits
+        .into_iter()
+        .map(|h| SearchResult {
+            title: h.title,
+            summary: h.summary,
+            url: format!("{}{}", base_url, h.url),
+            snippet: highlight(&h.snippet),
+        })
+        .collect();
+    Ok(Json(SearchResponse {
+        query: params.q.trim(),
+        results,
+    })
+    .into_response())
+}
+
+async fn run_query(state: &AppState, params: &SearchParams) -> Result<Vec<SearchHit>, AppError> {
+    let Some(query) = fts_query(&params.q) else {
+        return Ok(Vec::new());
+    };
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+    // Restricted pages stay indexed, so they are left out before paging:
+    // read ranked hits in batches until the requested page is covered.
+    let wanted = (offset + limit) as usize;
+    let mut public = Vec::with_capacity(wanted);
+    let mut read = 0;
+    loop {
+        let batch = db::search(&state.db, &query, (MARK_OPEN, MARK_CLOSE), MAX_LIMIT, read)
+            .await
+            .map_err(|e| AppError::Internal(format!("{e:#}")))?;
+        let exhausted = (batch.len() as i64) < MAX_LIMIT;
+        read += batch.len() as i64;
+        for hit in batch {
+            if access::is_public(state, &hit.url).await {
+                public.push(hit);
+            }
+        }
+        if exhausted || public.len() >= wanted {
+            break;
+        }
+    }
+    Ok(public
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect())
+}
+
+/// Turn free text into an FTS5 query: every word is matched literally (so
+/// operators and punctuation in user input can't cause syntax errors) and the
+/// last word also matches as a prefix, for search-as-you-type.
+fn fts_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .map(|t| t.replace('"', ""))
+        .filter(|t| !t.is_empty())
+        .map(|t| format!("\"{}\"", t))
+        .collect();
+    let (last, rest) = terms.split_last()?;
+    let mut query = rest.join(" ");
+    if !query.is_empty() {
+        query.push(' ');
+    }
+    query.push_str(last);
+    query.push('*');
+    Some(query)
+}
+
+/// HTML-escape a snippet, then turn the FTS5 delimiters into `<mark>` tags.
+fn highlight(snippet: &str) -> String {
+    maud::html! { (snippet) }
+        .into_string()
+        .replace(MARK_OPEN, "<mark>")
+        .replace(MARK_CLOSE, "</mark>")
+}
@@ -0,0 +1,154 @@
+//! Generated `/sitemap.xml` (with `/sitemap-N.xml` parts and a sitemap index
+//! for large sites) and a default `/robots.txt` pointing at it. Sitemap URLs
+//! must be absolute, so without `--base-url` there is no sitemap.
+
+use axum::response::Response;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::time::SystemTime;
+
+use crate::{
+    access,
+    conditional,
+    db,
+    error::AppError,
+    front_matter,
+    rss::esc,
+    state::AppState,
+    template::DirEntry,
+};
+
+/// URLs per sitemap file; the protocol's limit. Larger sites get a sitemap
+/// index at `/sitemap.xml` pointing at `/sitemap-1.xml`, `/sitemap-2.xml`, …
+const MAX_URLS: usize = 50_000;
+
+/// `/sitemap.xml`: the URL set itself, or an index of parts when the site has
+/// more than `MAX_URLS` pages.
+pub async fn serve_sitemap(state: &AppState) -> Result<Response, AppError> {
+    let origin = origin(state).ok_or(AppError::NotFound)?;
+    let pages = sitemap_pages(state).await;
+    let modified = pages.iter().filter_map(|e| e.modified).max();
+
+    let xml = if pages.len() <= MAX_URLS {
+        url_set(origin, &pages)
+    } else {
+        sitemap_index(origin, &pages)
+    };
+    conditional::respond("application/xml; charset=utf-8", xml, modified)
+}
+
+/// `/sitemap-N.xml` (1-based): one part of a split sitemap.
+pub async fn serve_part(state: &AppState, part: usize) -> Result<Response, AppError> {
+    let origin = origin(state).ok_or(AppError::NotFound)?;
+    let pages = sitemap_pages(state).await;
+    if pages.len() <= MAX_URLS || part == 0 {
+        return Err(AppError::NotFound);
+    }
+    let chunk = pages.chunks(MAX_URLS).nth(part - 1).ok_or(AppError::NotFound)?;
+    let modified = chunk.iter().filter_map(|e| e.modified).max();
+    conditional::respond(
+        "application/xml; charset=utf-8",
+        url_set(origin, chunk),
+        modified,
+    )
+}
+
+/// Default `/robots.txt`, used when the www root has none: allow everything
+/// but the editor and advertise the sitemap, if there is one.
+pub fn serve_robots(state: &AppState) -> Result<Response, AppError> {
+    let mut body = String::from("User-agent: *\nDisallow: /edit\n");
+    if let Some(origin) = origin(state) {
+        body.push_str(&format!("\nSitemap: {}/sitemap.xml\n", origin));
+    }
+    conditional::respond("text/plain; charset=utf-8", body, None)
+}
+
+/// Parse the part number out of a `/sitemap-N.xml` path.
+pub fn part_number(path: &str) -> Option<usize> {
+    path.strip_prefix("/sitemap-")?
+        .strip_suffix(".xml")?
+        .parse()
+        .ok()
+}
+
+/// Every published page except uploaded media, in listing order.
+async fn sitemap_pages(state: &AppState) -> Vec<DirEntry> {
+    let media_dir = db::get_micropub_setting(&state.db, "media_dir")
+        .await
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "_media".to_string());
+    let media_prefix = format!("/{}/", media_dir.trim_matches('/'));
+
+    access::public_pages(state)
+        .await
+        .iter()
+        .filter(|e| !e.url.starts_with(&media_prefix))
+        .cloned()
+        .collect()
+}
+
+/// Scheme and host for absolute URLs, from `--base-url`. The request's
+/// `Host` is never used, since the client controls it.
+fn origin(state: &AppState) -> Option<&str> {
+    state
+        .base_url
+        .as_deref()
+        .map(|base| base.trim_end_matches('/'))
+}
+
+fn url_set(origin: &str, pages: &[DirEntry]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for page in pages {
+        xml.push_str("  <url>\n");
+        xml.push_str(&format!("    <loc>{}</loc>\n", esc(&loc(origin, &page.url))));
+        if let Some(lastmod) = lastmod(page) {
+            xml.push_str(&format!("    <lastmod>{}</lastmod>\n", lastmod));
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>");
+    xml
+}
+
+fn sitemap_index(origin: &str, pages: &[DirEntry]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for (i, chunk) in pages.chunks(MAX_URLS).enumerate() {
+        xml.push_str("  <sitemap>\n");
+        xml.push_str(&format!(
+            "    <loc>{}</loc>\n",
+            esc(&format!("{}/sitemap-{}.xml", origin, i + 1))
+        ));
+        if let Some(lastmod) = chunk.iter().filter_map(lastmod).max() {
+            xml.push_str(&format!("    <lastmod>{}</lastmod>\n", lastmod));
+        }
+        xml.push_str("  </sitemap>\n");
+    }
+    xml.push_str("</sitemapindex>");
+    xml
+}
+
+/// Page URLs may contain spaces and non-ASCII characters; `<loc>` needs them
+/// percent-encoded.
+fn loc(origin: &str, url: &str) -> String {
+    use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
+    const LOC: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'`');
+    format!("{}{}", origin, utf8_percent_encode(url, LOC))
+}
+
+/// W3C datetime of the later of the front matter `date` and the file's mtime.
+fn lastmod(page: &DirEntry) -> Option<String> {
+    let date = page
+        .date
+        .as_deref()
+        .and_then(front_matter::parse_date)
+        .map(|d| d.with_timezone(&Utc));
+    let modified = page.modified.map(|t: SystemTime| DateTime::<Utc>::from(t));
+    date.max(modified)
+        .map(|d| d.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
@@ -1,11 +1,25 @@
 use std::{
-    collections::HashMap,
-    path::PathBuf,
-    sync::Arc,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
     time::Instant,
 };
 use sqlx::SqlitePool;
-use tokio::sync::RwLock;
+use tokio::sync::{Notify, OnceCell, RwLock};
+
+use crate::{
+    backlinks::SiteDefault,
+    cache::RenderCache,
+    compress::CompressedCache,
+    db,
+    git::{GitConfig, GitRepo},
+    headers::RulesCache,
+    highlight::HighlightConfig,
+    images::ImageConfig,
+    micropub::new_token,
+    redirects::RedirectCache,
+    rss::FeedOptions,
+};
 
 #[derive(Clone)]
 pub struct AppState {
@@ -17,6 +31,149 @@ pub struct AppState {
     pub base_url: Option<String>,
     /// SQLite connection pool — shared across all request handlers.
     pub db: SqlitePool,
-    /// Active editor sessions: token → last-used instant.
-    pub sessions: Arc<RwLock<HashMap<String, Instant>>>,
+    /// Active editor sessions, by token.
+    pub sessions: Arc<RwLock<HashMap<String, Session>>>,
+    /// Rendered pages and listings, invalidated by a watcher on `canonical_root`.
+    pub cache: Arc<RenderCache>,
+    /// Compressed response bodies, by path, `ETag` and encoding.
+    pub compressed: Arc<CompressedCache>,
+    /// Pagination and feed limits.
+    pub listing: ListingConfig,
+    /// How markdown is turned into HTML.
+    pub render: RenderConfig,
+    /// WebSub hubs to notify when a scheduled page goes live; pings need
+    /// `base_url` for absolute feed URLs.
+    pub ping_urls: Arc<Vec<String>>,
+    /// The repository content changes are committed to, with `--git`.
+    pub git: Option<Arc<GitRepo>>,
+    /// Where resized images are cached, and how much of them is kept.
+    pub images: Arc<ImageConfig>,
+    /// Parsed response header rules, loaded from the site settings.
+    pub headers: Arc<RulesCache>,
+    /// Parsed `_redirects` rules, reparsed when the file changes.
+    pub redirects: Arc<RedirectCache>,
+    /// Whether pages show backlinks unless their front matter says otherwise.
+    pub backlinks: Arc<SiteDefault>,
+    /// Files being saved, so a version check and the write it allows happen
+    /// as one step.
+    pub writes: Arc<WriteLocks>,
+    /// Key signing draft preview links.
+    pub preview_secret: Arc<SiteSecret>,
+    /// Key for the cookies proving a section password was entered.
+    pub access_secret: Arc<SiteSecret>,
+}
+
+/// A logged-in editor.
+pub struct Session {
+    pub user: String,
+    pub last_used: Instant,
+}
+
+/// Per-file locks for writes that check the file's current version first:
+/// without one, two saves of the same version could both pass the check and
+/// the second would silently replace the first.
+#[derive(Default)]
+pub struct WriteLocks {
+    locked: Mutex<HashSet<PathBuf>>,
+    released: Notify,
+}
+
+/// Held while a file is read, compared and written; unlocks on drop.
+pub struct WriteGuard<'a> {
+    locks: &'a WriteLocks,
+    path: PathBuf,
+}
+
+impl WriteLocks {
+    /// Wait until no one else is writing `path`, then lock it.
+    pub async fn lock(&self, path: &Path) -> WriteGuard<'_> {
+        loop {
+            // Registered before checking, so a release in between isn't missed.
+            let released = self.released.notified();
+            if self
+                .locked
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(path.to_path_buf())
+            {
+                return WriteGuard {
+                    locks: self,
+                    path: path.to_path_buf(),
+                };
+            }
+            released.await;
+        }
+    }
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.locks
+            .locked
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.path);
+        self.locks.released.notify_waiters();
+    }
+}
+
+/// A signing key kept in the site settings, generated on first use.
+pub struct SiteSecret {
+    setting: &'static str,
+    value: OnceCell<String>,
+}
+
+impl SiteSecret {
+    pub fn new(setting: &'static str) -> Self {
+        Self {
+            setting,
+            value: OnceCell::new(),
+        }
+    }
+
+    pub async fn get(&self, pool: &SqlitePool) -> &str {
+        self.value
+            .get_or_init(|| async {
+                match db::get_site_setting(pool, self.setting).await {
+                    Ok(Some(secret)) => secret,
+                    _ => {
+                        let secret = new_token();
+                        if let Err(e) = db::set_site_setting(pool, self.setting, &secret).await {
+                            tracing::warn!("Cannot store {}: {e:#}", self.setting);
+                        }
+                        secret
+                    }
+                }
+            })
+            .await
+    }
+}
+
+/// Limits on how much of a directory is rendered at once, set from the
+/// command line.
+#[derive(Debug, Clone, Copy)]
+pub struct ListingConfig {
+    /// Entries per listing page; 0 disables pagination.
+    pub page_size: usize,
+    pub feed: FeedOptions,
+}
+
+/// Markdown rendering options, set from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct RenderConfig {
+    pub highlight: HighlightConfig,
+    /// Append a `#` link to each heading.
+    pub heading_anchors: bool,
+}
+
+/// Command-line settings copied into every `AppState`, kept by the TUI so a
+/// restart builds the new state from the same values.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub listing: ListingConfig,
+    pub render: RenderConfig,
+    pub ping_urls: Arc<Vec<String>>,
+    /// Git mode, when on; the repository is opened from the www root.
+    pub git: Option<GitConfig>,
+    pub images: Arc<ImageConfig>,
 }
@@ -0,0 +1,136 @@
+//! Generated taxonomy pages built from front matter `tags`:
+//! `/tags/` (every tag with its post count), `/tags/<tag>/` (posts with that
+//! tag across the whole tree) and its feeds (`/tags/<tag>/feed.xml`,
+//! `atom.xml`, `feed.json`).
+
+use axum::response::{IntoResponse, Redirect, Response};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use std::collections::BTreeMap;
+
+use crate::{
+    access, conditional,
+    error::AppError,
+    handler, rss,
+    state::AppState,
+    template::{self, DirEntry, TagCount},
+};
+
+/// URL prefix under which tag pages are generated.
+pub const TAGS_PREFIX: &str = "/tags/";
+
+const HTML: &str = "text/html; charset=utf-8";
+
+/// Characters left unescaped in tag URL segments.
+const TAG_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Canonical URL of a tag page. Tags are matched case-insensitively, so the
+/// URL always uses the lowercased form.
+pub fn tag_url(tag: &str) -> String {
+    format!(
+        "{}{}/",
+        TAGS_PREFIX,
+        utf8_percent_encode(&tag_key(tag), TAG_SEGMENT)
+    )
+}
+
+fn tag_key(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Serve a path below `/tags/`. `rest` is the percent-decoded remainder.
+pub async fn serve(state: &AppState, rest: &str) -> Result<Response, AppError> {
+    let pages = access::public_pages(state).await;
+    let groups = group_by_tag(&pages);
+
+    if rest.is_empty() || rest == "index.html" {
+        let tags: Vec<TagCount> = groups
+            .values()
+            .map(|(name, entries)| TagCount {
+                url: tag_url(name),
+                name: name.to_string(),
+                count: entries.len(),
+            })
+            .collect();
+        let css = handler::root_css(state).await;
+        let body = template::tag_index(&tags, css.as_deref()).into_string();
+        let modified = pages.iter().filter_map(|e| e.modified).max();
+        return conditional::respond(HTML, body, modified);
+    }
+
+    let (segment, file) = match rest.split_once('/') {
+        Some((segment, file)) => (segment, file),
+        None => {
+            // `/tags/rust` → `/tags/rust/`
+            return Ok(Redirect::permanent(&tag_url(rest)).into_response());
+        }
+    };
+
+    let Some((name, entries)) = groups.get(&tag_key(segment)) else {
+        return Err(AppError::NotFound);
+    };
+    let entries: Vec<DirEntry> = entries.iter().map(|&e| e.clone()).collect();
+    let modified = entries.iter().filter_map(|e| e.modified).max();
+
+    match file {
+        "" | "index.html" => {
+            let css = handler::root_css(state).await;
+            let body = template::tag_listing(name, &entries, css.as_deref()).into_string();
+            conditional::respond(HTML, body, modified)
+        }
+        file => {
+            let format = rss::FeedFormat::from_file_name(file).ok_or(AppError::NotFound)?;
+            let base_url = state.base_url.as_deref().unwrap_or("");
+            let base = base_url.trim_end_matches('/');
+            let channel_link = format!("{}{}", base, tag_url(name));
+            let self_link = format!("{}{}", channel_link, format.file_name());
+            let channel = rss::Channel {
+                title: &format!("Tagged “{}”", name),
+                link: &channel_link,
+                feed_url: &self_link,
+                description: "",
+            };
+            let body = rss::build(
+                format,
+                &channel,
+                &entries,
+                &pages,
+                base_url,
+                state.listing.feed,
+                &state.render,
+            );
+            conditional::respond(&format.content_type(), body, modified)
+        }
+    }
+}
+
+/// URL of every tag page, for the static export.
+pub async fn tag_urls(state: &AppState) -> Vec<String> {
+    let pages = access::public_pages(state).await;
+    group_by_tag(&pages).values().map(|(name, _)| tag_url(name)).collect()
+}
+
+/// Group pages by lowercased tag. The display name is the spelling used by the
+/// first (most recent) page carrying the tag; entries keep the input order.
+fn group_by_tag(pages: &[DirEntry]) -> BTreeMap<String, (&str, Vec<&DirEntry>)> {
+    let mut groups: BTreeMap<String, (&str, Vec<&DirEntry>)> = BTreeMap::new();
+    for page in pages {
+        for tag in &page.tags {
+            let key = tag_key(tag);
+            if key.is_empty() {
+                continue;
+            }
+            let (_, entries) = groups.entry(key).or_insert_with(|| (tag.trim(), Vec::new()));
+            // A page listing the same tag twice (e.g. "Rust" and "rust")
+            // should only appear once.
+            if !entries.last().is_some_and(|last| std::ptr::eq(*last, page)) {
+                entries.push(page);
+            }
+        }
+    }
+    groups
+}
+
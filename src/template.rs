@@ -1,6 +1,7 @@
 use maud::{DOCTYPE, Markup, PreEscaped, html};
+use std::time::SystemTime;
 
-use crate::front_matter::FrontMatter;
+use crate::{db::SearchHit, front_matter::FrontMatter, rss::FeedFormat, tags::tag_url};
 
 pub struct Breadcrumb {
     pub label: String,
@@ -55,6 +56,7 @@ pub fn build_breadcrumbs(url_path: &str) -> Vec<Breadcrumb> {
     crumbs
 }
 
+#[derive(Clone)]
 pub struct DirEntry {
     pub display_name: String,
     pub url: String,
@@ -64,49 +66,41 @@ pub struct DirEntry {
     pub summary: Option<String>,
     pub author: Option<String>,
     pub content: Option<String>,
+    /// Filesystem modification time, used for HTTP validators.
+    pub modified: Option<SystemTime>,
+    /// Front matter `tags`; always empty for directories.
+    pub tags: Vec<String>,
+    /// Front matter `aliases`; always empty for directories.
+    pub aliases: Vec<String>,
+}
+
+/// Inputs to the page template, and to a user `_layout.html`.
+pub struct PageVars<'a> {
+    pub front_matter: &'a FrontMatter,
+    pub content_html: &'a str,
+    pub url: &'a str,
+    pub css_path: Option<&'a str>,
+    pub meta_image: Option<&'a str>,
+    pub breadcrumbs: &'a [Breadcrumb],
+    pub feed_dir: Option<&'a str>,
+    pub micropub_url: Option<&'a str>,
+    /// Table of contents for the sidebar, when the page asks for one with
+    /// `toc: true`.
+    pub toc: Option<&'a str>,
+    /// Pages linking here, when backlinks are shown.
+    pub backlinks: &'a [DirEntry],
+    /// Whether headings carry anchor links, which need a little CSS.
+    pub heading_anchors: bool,
 }
 
 /// Full HTML page wrapping rendered markdown content.
-pub fn page(
-    fm: &FrontMatter,
-    content_html: &str,
-    css_path: Option<&str>,
-    meta_image: Option<&str>,
-    breadcrumbs: &[Breadcrumb],
-    micropub_url: Option<&str>,
-) -> Markup {
-    let title = fm.title.as_deref().unwrap_or("");
+pub fn page(vars: &PageVars) -> Markup {
+    let breadcrumbs = vars.breadcrumbs;
     html! {
         (DOCTYPE)
         html lang="en" {
             head {
-                meta charset="utf-8";
-                meta name="viewport" content="width=device-width, initial-scale=1";
-                title { (title) }
-                @if !title.is_empty() {
-                    meta property="og:title" content=(title);
-                }
-                @if let Some(s) = &fm.summary {
-                    meta name="description" content=(s);
-                    meta property="og:description" content=(s);
-                }
-                @if let Some(img) = meta_image {
-                    meta property="og:image" content=(img);
-                    meta name="twitter:card" content="summary_large_image";
-                    meta name="twitter:image" content=(img);
-                }
-                @if let Some(a) = &fm.author {
-                    meta name="author" content=(a);
-                }
-                @if let Some(d) = &fm.date {
-                    meta property="article:published_time" content=(d);
-                }
-                @if let Some(css) = css_path {
-                    link rel="stylesheet" href=(css);
-                }
-                @if let Some(mp) = micropub_url {
-                    link rel="micropub" href=(mp);
-                }
+                (page_head(vars))
             }
             body {
                 @if breadcrumbs.len() > 1 {
@@ -124,51 +118,297 @@ pub fn page(
                         }
                     }
                 }
+                @if let Some(toc) = vars.toc {
+                    aside class="sidebar" {
+                        (PreEscaped(toc))
+                    }
+                }
                 main {
-                    (PreEscaped(content_html))
+                    (PreEscaped(vars.content_html))
+                }
+                @if !vars.backlinks.is_empty() {
+                    aside class="backlinks" {
+                        h2 { "Linked from" }
+                        ul {
+                            @for entry in vars.backlinks {
+                                li {
+                                    a href=(entry.url) {
+                                        (entry.title.as_deref().unwrap_or(&entry.display_name))
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                @if let Some(tags) = vars.front_matter.tags.as_ref().filter(|t| !t.is_empty()) {
+                    footer {
+                        ul class="tags" {
+                            @for tag in tags {
+                                li { a href=(tag_url(tag)) rel="tag" { (tag) } }
+                            }
+                        }
+                    }
                 }
             }
         }
     }
 }
 
+/// Contents of a page's `<head>`: title, description and OpenGraph tags,
+/// stylesheet and discovery links. Also handed to user layouts.
+pub fn page_head(vars: &PageVars) -> Markup {
+    let fm = vars.front_matter;
+    let title = fm.title.as_deref().unwrap_or("");
+    html! {
+        meta charset="utf-8";
+        meta name="viewport" content="width=device-width, initial-scale=1";
+        title { (title) }
+        @if !title.is_empty() {
+            meta property="og:title" content=(title);
+        }
+        @if let Some(s) = &fm.summary {
+            meta name="description" content=(s);
+            meta property="og:description" content=(s);
+        }
+        @if let Some(img) = vars.meta_image {
+            meta property="og:image" content=(img);
+            meta name="twitter:card" content="summary_large_image";
+            meta name="twitter:image" content=(img);
+        }
+        @if let Some(a) = &fm.author {
+            meta name="author" content=(a);
+        }
+        @if let Some(d) = &fm.date {
+            meta property="article:published_time" content=(d);
+        }
+        @for tag in fm.tags.iter().flatten() {
+            meta property="article:tag" content=(tag);
+        }
+        @if vars.heading_anchors {
+            style { (PreEscaped(ANCHOR_CSS)) }
+        }
+        @if let Some(css) = vars.css_path {
+            link rel="stylesheet" href=(css);
+        }
+        @if let Some(dir) = vars.feed_dir {
+            (feed_links(dir))
+        }
+        @if let Some(mp) = vars.micropub_url {
+            link rel="micropub" href=(mp);
+        }
+    }
+}
+
+/// Heading anchor links stay hidden until the heading is hovered or the
+/// link focused. Emitted before the site stylesheet so it can override.
+const ANCHOR_CSS: &str = ".anchor{visibility:hidden;text-decoration:none}\
+:is(h1,h2,h3,h4,h5,h6):hover .anchor,.anchor:focus{visibility:visible}";
+
+/// Position of a listing page within a paginated directory. Pages are
+/// 1-based; a listing that fits on one page has `total_pages == 1`.
+pub struct Pagination {
+    pub page: usize,
+    pub total_pages: usize,
+}
+
+impl Pagination {
+    /// URL of the previous page, relative to the listing URL `base`. Page 1
+    /// is the bare listing URL so it isn't duplicated as `?page=1`.
+    pub fn prev_url(&self, base: &str) -> Option<String> {
+        match self.page {
+            0 | 1 => None,
+            2 => Some(base.to_string()),
+            n => Some(format!("{}?page={}", base, n - 1)),
+        }
+    }
+
+    pub fn next_url(&self, base: &str) -> Option<String> {
+        (self.page < self.total_pages).then(|| format!("{}?page={}", base, self.page + 1))
+    }
+}
+
 /// Directory listing page.
-pub fn directory_index(dir_url: &str, entries: &[DirEntry], css_path: Option<&str>) -> Markup {
+pub fn directory_index(
+    dir_url: &str,
+    entries: &[DirEntry],
+    pagination: &Pagination,
+    css_path: Option<&str>,
+) -> Markup {
+    let prev = pagination.prev_url(dir_url);
+    let next = pagination.next_url(dir_url);
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                (listing_head(dir_url, pagination, css_path))
+            }
+            body {
+                main {
+                    h1 { "Index of " (dir_url) }
+                    @if entries.is_empty() {
+                        p { em { "Empty directory." } }
+                    } @else {
+                        (entry_list(entries))
+                    }
+                    @if pagination.total_pages > 1 {
+                        nav class="pagination" aria-label="Pagination" {
+                            @if let Some(url) = &prev {
+                                a href=(url) rel="prev" { "← Newer" }
+                                " "
+                            }
+                            span aria-current="page" {
+                                "Page " (pagination.page) " of " (pagination.total_pages)
+                            }
+                            @if let Some(url) = &next {
+                                " "
+                                a href=(url) rel="next" { "Older →" }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Contents of a listing's `<head>`: title, stylesheet, feed discovery and
+/// `rel=prev/next` links. Also handed to user listing templates.
+pub fn listing_head(dir_url: &str, pagination: &Pagination, css_path: Option<&str>) -> Markup {
+    html! {
+        meta charset="utf-8";
+        meta name="viewport" content="width=device-width, initial-scale=1";
+        title {
+            "Index of " (dir_url)
+            @if pagination.total_pages > 1 {
+                " (page " (pagination.page) " of " (pagination.total_pages) ")"
+            }
+        }
+        @if let Some(css) = css_path {
+            link rel="stylesheet" href=(css);
+        }
+        (feed_links(dir_url))
+        @if let Some(url) = pagination.prev_url(dir_url) {
+            link rel="prev" href=(url);
+        }
+        @if let Some(url) = pagination.next_url(dir_url) {
+            link rel="next" href=(url);
+        }
+    }
+}
+
+pub struct TagCount {
+    pub name: String,
+    pub url: String,
+    pub count: usize,
+}
+
+/// Index of every tag with the number of posts carrying it.
+pub fn tag_index(tags: &[TagCount], css_path: Option<&str>) -> Markup {
     html! {
         (DOCTYPE)
         html lang="en" {
             head {
                 meta charset="utf-8";
                 meta name="viewport" content="width=device-width, initial-scale=1";
-                title { "Index of " (dir_url) }
+                title { "Tags" }
                 @if let Some(css) = css_path {
                     link rel="stylesheet" href=(css);
                 }
             }
             body {
                 main {
-                    h1 { "Index of " (dir_url) }
-                    @if entries.is_empty() {
-                        p { em { "Empty directory." } }
+                    h1 { "Tags" }
+                    @if tags.is_empty() {
+                        p { em { "No tags yet." } }
                     } @else {
-                        ul {
-                            @for e in entries {
+                        ul class="tags" {
+                            @for t in tags {
                                 li {
-                                    a href=(e.url) {
-                                        @if e.is_dir {
-                                            (e.display_name) "/"
-                                        } @else {
-                                            (e.title.as_deref().unwrap_or(&e.display_name))
+                                    a href=(t.url) rel="tag" { (t.name) }
+                                    " (" (t.count) ")"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Posts carrying a single tag.
+pub fn tag_listing(tag: &str, entries: &[DirEntry], css_path: Option<&str>) -> Markup {
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                title { "Tagged “" (tag) "”" }
+                @if let Some(css) = css_path {
+                    link rel="stylesheet" href=(css);
+                }
+                (feed_links(&tag_url(tag)))
+            }
+            body {
+                nav aria-label="breadcrumb" {
+                    ol {
+                        li { a href="/" { "Home" } }
+                        li { a href=(crate::tags::TAGS_PREFIX) { "Tags" } }
+                        li { span aria-current="page" { (tag) } }
+                    }
+                }
+                main {
+                    h1 { "Tagged “" (tag) "”" }
+                    (entry_list(entries))
+                }
+            }
+        }
+    }
+}
+
+/// Site search page: a query form and ranked results. Each hit's `snippet`
+/// must already be escaped HTML (see `search::highlight`).
+pub fn search_page(query: &str, hits: &[SearchHit], css_path: Option<&str>) -> Markup {
+    html! {
+        (DOCTYPE)
+        html lang="en" {
+            head {
+                meta charset="utf-8";
+                meta name="viewport" content="width=device-width, initial-scale=1";
+                title {
+                    @if query.is_empty() { "Search" } @else { "Search: " (query) }
+                }
+                @if let Some(css) = css_path {
+                    link rel="stylesheet" href=(css);
+                }
+            }
+            body {
+                nav aria-label="breadcrumb" {
+                    ol {
+                        li { a href="/" { "Home" } }
+                        li { span aria-current="page" { "Search" } }
+                    }
+                }
+                main {
+                    h1 { "Search" }
+                    form method="get" action="/search" role="search" {
+                        input type="search" name="q" value=(query) aria-label="Search" autofocus;
+                        " "
+                        button type="submit" { "Search" }
+                    }
+                    @if !query.is_empty() {
+                        @if hits.is_empty() {
+                            p { "No results for “" (query) "”." }
+                        } @else {
+                            ol class="search-results" {
+                                @for hit in hits {
+                                    li {
+                                        a href=(hit.url) { (hit.title) }
+                                        @if !hit.summary.is_empty() {
+                                            p { (hit.summary) }
                                         }
-                                    }
-                                    @if let Some(d) = &e.date {
-                                        " — " (d)
-                                    }
-                                    @if let Some(a) = &e.author {
-                                        " by " (a)
-                                    }
-                                    @if let Some(s) = &e.summary {
-                                        p { (s) }
+                                        p class="snippet" { (PreEscaped(&hit.snippet)) }
                                     }
                                 }
                             }
@@ -179,3 +419,42 @@ pub fn directory_index(dir_url: &str, entries: &[DirEntry], css_path: Option<&st
         }
     }
 }
+
+/// Feed auto-discovery links for the listing at `dir_url` (with trailing
+/// slash), one per feed format.
+fn feed_links(dir_url: &str) -> Markup {
+    html! {
+        @for format in FeedFormat::ALL {
+            link rel="alternate" type=(format.mime()) title=(format.label())
+                href={ (dir_url) (format.file_name()) };
+        }
+    }
+}
+
+/// `<ul>` of listing entries shared by directory and tag pages.
+fn entry_list(entries: &[DirEntry]) -> Markup {
+    html! {
+        ul {
+            @for e in entries {
+                li {
+                    a href=(e.url) {
+                        @if e.is_dir {
+                            (e.display_name) "/"
+                        } @else {
+                            (e.title.as_deref().unwrap_or(&e.display_name))
+                        }
+                    }
+                    @if let Some(d) = &e.date {
+                        " — " (d)
+                    }
+                    @if let Some(a) = &e.author {
+                        " by " (a)
+                    }
+                    @if let Some(s) = &e.summary {
+                        p { (s) }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Heading IDs and generated tables of contents.
+//!
+//! Every heading the markdown renderer emits gets a slug `id` (using the
+//! Micropub slug rules, deduplicated per page with `-1`, `-2`, …). The
+//! headings collected along the way become a nested list of links, placed
+//! where the page has a `[[toc]]` marker or, with `toc: true` in the front
+//! matter, in the page template's sidebar.
+
+use maud::{Markup, html};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::{micropub::handlers::slugify, search::html_to_text};
+
+/// A `[[toc]]` paragraph, as rendered.
+const MARKER: &str = "<p>[[toc]]</p>";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Heading {
+    pub level: u8,
+    pub id: String,
+    pub text: String,
+}
+
+/// Give each `<h1>`…`<h6>` in `html` an `id`, and return the headings in
+/// document order. With `anchors`, each heading also gets a `#` link to
+/// itself, shown on hover. Headings that already carry attributes (raw HTML)
+/// are left alone.
+pub fn add_heading_ids(html: &str, anchors: bool) -> (String, Vec<Heading>) {
+    let mut used = HashSet::new();
+    let mut headings = Vec::new();
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some((start, level)) = next_heading(rest) {
+        let close_tag = format!("</h{}>", level);
+        let inner_start = start + "<hN>".len();
+        let Some(inner_end) = rest[inner_start..].find(&close_tag).map(|i| inner_start + i)
+        else {
+            break;
+        };
+        let inner = &rest[inner_start..inner_end];
+        let text = html_to_text(inner);
+
+        let base = slugify(&text);
+        let mut id = base.clone();
+        let mut n = 0;
+        while !used.insert(id.clone()) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+
+        out.push_str(&rest[..start]);
+        out.push_str(&format!("<h{} id=\"{}\">{}", level, id, inner));
+        if anchors {
+            out.push_str(&format!(
+                " <a class=\"anchor\" href=\"#{}\" aria-label=\"Link to this section\">#</a>",
+                id
+            ));
+        }
+        out.push_str(&close_tag);
+        rest = &rest[inner_end + close_tag.len()..];
+
+        headings.push(Heading { level, id, text });
+    }
+    out.push_str(rest);
+    (out, headings)
+}
+
+/// Position and level of the next bare `<hN>` tag.
+fn next_heading(html: &str) -> Option<(usize, u8)> {
+    let mut offset = 0;
+    while let Some(pos) = html[offset..].find("<h") {
+        let start = offset + pos;
+        let bytes = &html.as_bytes()[start + 2..];
+        if let [level @ b'1'..=b'6', b'>', ..] = bytes {
+            return Some((start, level - b'0'));
+        }
+        offset = start + 2;
+    }
+    None
+}
+
+/// Replace a `[[toc]]` marker paragraph with the table of contents.
+pub fn expand_marker(html: String, headings: &[Heading]) -> String {
+    if !html.contains(MARKER) {
+        return html;
+    }
+    html.replacen(MARKER, &render(headings).into_string(), 1)
+}
+
+/// The table of contents as a nested list. The page title (`h1`) is left
+/// out unless it is the only level used.
+pub fn render(headings: &[Heading]) -> Markup {
+    let entries: Vec<&Heading> = if headings.iter().any(|h| h.level > 1) {
+        headings.iter().filter(|h| h.level > 1).collect()
+    } else {
+        headings.iter().collect()
+    };
+    html! {
+        nav class="toc" aria-label="Table of contents" {
+            @if !entries.is_empty() {
+                (list(&entries))
+            }
+        }
+    }
+}
+
+/// One level of the list: each heading at the shallowest level present
+/// starts an item, and the deeper headings after it nest beneath.
+fn list(headings: &[&Heading]) -> Markup {
+    let top = headings.iter().map(|h| h.level).min().unwrap_or(1);
+    let mut items: Vec<(&Heading, &[&Heading])> = Vec::new();
+    let mut i = 0;
+    while i < headings.len() {
+        let end = headings[i + 1..]
+            .iter()
+            .position(|h| h.level <= top)
+            .map_or(headings.len(), |p| i + 1 + p);
+        items.push((headings[i], &headings[i + 1..end]));
+        i = end;
+    }
+    html! {
+        ul {
+            @for (heading, children) in items {
+                li {
+                    a href={ "#" (heading.id) } { (heading.text) }
+                    @if !children.is_empty() {
+                        (list(children))
+                    }
+                }
+            }
+        }
+    }
+}
@@ -13,6 +13,7 @@ use std::{
 
 use crate::db::{self, RequestStats};
 use crate::log_capture::LogBuffer;
+use crate::state::Settings;
 
 // ── Public config ─────────────────────────────────────────────────────────────
 
@@ -23,6 +24,7 @@ pub struct TuiConfig {
     pub env_path: PathBuf,
     pub www_root: PathBuf,
     pub base_url: Option<String>,
+    pub settings: Settings,
     pub log_buffer: LogBuffer,
 }
 
@@ -86,6 +88,7 @@ struct App {
     server_addr: String,
     www_root: PathBuf,
     base_url: Option<String>,
+    settings: Settings,
     db: SqlitePool,
     env_path: PathBuf,
     message: Option<(String, bool, Instant)>, // (text, is_error, when)
@@ -130,6 +133,7 @@ pub async fn run(config: TuiConfig) -> Result<()> {
     let initial_state = crate::build_state(
         config.www_root.clone(),
         config.base_url.clone(),
+        config.settings.clone(),
         config.db.clone(),
     )
     .await?;
@@ -176,6 +180,7 @@ pub async fn run(config: TuiConfig) -> Result<()> {
         server_addr,
         www_root: config.www_root,
         base_url: config.base_url,
+        settings: config.settings,
         db: config.db,
         env_path: config.env_path,
         message: None,
@@ -194,8 +199,13 @@ pub async fn run(config: TuiConfig) -> Result<()> {
             server_handle.abort();
             // Wait for the old task to fully stop so the OS releases the port.
             let _ = (&mut server_handle).await;
-            match crate::build_state(app.www_root.clone(), app.base_url.clone(), app.db.clone())
-                .await
+            match crate::build_state(
+                app.www_root.clone(),
+                app.base_url.clone(),
+                app.settings.clone(),
+                app.db.clone(),
+            )
+            .await
             {
                 Ok(new_state) => {
                     let h = host.clone();
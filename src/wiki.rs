@@ -0,0 +1,330 @@
+//! Wiki-style internal links: `[[Page Name]]`, `[[path/to/page|label]]` and
+//! `[[Page#Section]]`.
+//!
+//! A bare name is looked up in the linking page's directory first, then
+//! anywhere in the tree (the shallowest match wins). A name matches a page
+//! whose file name or title is the same ignoring case, or has the same slug,
+//! so `[[Getting Started]]` finds `getting-started.md`. Names containing `/`
+//! are paths, relative to the linking page's directory or, with a leading
+//! `/`, to the root. Either may name a directory. Links resolve to the
+//! clean URLs `handler::handle` serves; unresolved ones render with the
+//! `broken` class and are listed on the editor's `/edit/links` report.
+
+use maud::{PreEscaped, html};
+use markdown::mdast::Node;
+use std::path::Path;
+
+use crate::{
+    handler::{self, page_url, rel_path, walk_markdown},
+    micropub::handlers::slugify,
+    search::html_to_text,
+    state::AppState,
+    template::DirEntry,
+};
+
+/// Whether `source` may contain wiki links; pages that do must be
+/// re-rendered when other pages are added, removed or retitled.
+pub fn has_links(source: &str) -> bool {
+    source.contains("[[")
+}
+
+/// Resolves link targets for one page against the site's pages.
+pub struct Resolver<'a> {
+    pages: &'a [DirEntry],
+    /// URL directory of the linking page, ending in `/`.
+    dir: String,
+}
+
+impl<'a> Resolver<'a> {
+    /// `pages` as from `handler::load_site_pages`; `page_url` is the clean
+    /// URL of the page being rendered.
+    pub fn new(pages: &'a [DirEntry], page_url: &str) -> Self {
+        let dir = match page_url.rfind('/') {
+            Some(i) => page_url[..=i].to_string(),
+            None => String::from("/"),
+        };
+        Resolver { pages, dir }
+    }
+
+    /// URL for `target` (the part before any `|`), or `None` when nothing
+    /// matches.
+    pub fn resolve(&self, target: &str) -> Option<String> {
+        let (path, fragment) = match target.split_once('#') {
+            Some((path, section)) => (path.trim(), format!("#{}", slugify(section))),
+            None => (target.trim(), String::new()),
+        };
+        if path.is_empty() {
+            // `[[#Section]]`: a heading on the same page.
+            return (!fragment.is_empty()).then_some(fragment);
+        }
+        let path = path.strip_suffix(".md").unwrap_or(path).trim_end_matches('/');
+
+        let url = match path.rsplit_once('/') {
+            Some((dir, name)) => {
+                let base = if path.starts_with('/') { "/" } else { &self.dir };
+                self.in_dir(&join(base, dir), name)
+            }
+            None => self
+                .in_dir(&self.dir, path)
+                .or_else(|| self.anywhere(path)),
+        }?;
+        Some(url + &fragment)
+    }
+
+    /// A page or subdirectory named `name` directly inside `dir`.
+    fn in_dir(&self, dir: &str, name: &str) -> Option<String> {
+        let page = self.pages.iter().find(|p| {
+            !p.url.ends_with('/')
+                && p.url.strip_prefix(dir).is_some_and(|rest| !rest.contains('/'))
+                && names_match(&p.display_name, name)
+        });
+        if let Some(page) = page {
+            return Some(page.url.clone());
+        }
+        self.pages.iter().find_map(|p| {
+            let (segment, _) = p.url.strip_prefix(dir)?.split_once('/')?;
+            names_match(segment, name).then(|| format!("{}{}/", dir, segment))
+        })
+    }
+
+    /// The shallowest page or directory anywhere in the tree named `name`,
+    /// then the shallowest page titled `name`.
+    fn anywhere(&self, name: &str) -> Option<String> {
+        let mut candidates: Vec<String> = Vec::new();
+        for page in self.pages {
+            if !page.url.ends_with('/') && names_match(&page.display_name, name) {
+                candidates.push(page.url.clone());
+            }
+            let mut end = 0;
+            while let Some(i) = page.url[end + 1..].find('/') {
+                end += 1 + i;
+                let dir = &page.url[..=end];
+                let segment = dir.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+                if names_match(segment, name) {
+                    candidates.push(dir.to_string());
+                }
+            }
+        }
+        if candidates.is_empty() {
+            candidates = self
+                .pages
+                .iter()
+                .filter(|p| p.title.as_deref().is_some_and(|t| names_match(t, name)))
+                .map(|p| p.url.clone())
+                .collect();
+        }
+        candidates
+            .into_iter()
+            .min_by(|a, b| (a.matches('/').count(), a).cmp(&(b.matches('/').count(), b)))
+    }
+}
+
+/// Same name ignoring case, or the same slug.
+fn names_match(candidate: &str, name: &str) -> bool {
+    if candidate.to_lowercase() == name.to_lowercase() {
+        return true;
+    }
+    let slug = slugify(name);
+    slug != "untitled" && slugify(candidate) == slug
+}
+
+/// Resolve the relative path `rel` against the URL directory `base`,
+/// returning a URL directory ending in `/`.
+fn join(base: &str, rel: &str) -> String {
+    let mut segments: Vec<&str> = base.split('/').filter(|s| !s.is_empty()).collect();
+    for part in rel.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            part => segments.push(part),
+        }
+    }
+    if segments.is_empty() {
+        String::from("/")
+    } else {
+        format!("/{}/", segments.join("/"))
+    }
+}
+
+/// A `[[…]]` found in text: byte range of the whole link, target and
+/// optional label.
+struct Found<'t> {
+    start: usize,
+    end: usize,
+    target: &'t str,
+    label: Option<&'t str>,
+}
+
+/// Every wiki link in `text`. `[[toc]]` is left for the table of contents.
+fn find_links(text: &str) -> Vec<Found<'_>> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find("[[").map(|i| offset + i) {
+        let inner_start = start + 2;
+        let Some(inner_end) = text[inner_start..].find("]]").map(|i| inner_start + i) else {
+            break;
+        };
+        let inner = &text[inner_start..inner_end];
+        if inner.contains(['[', ']', '\n']) || inner.trim().is_empty() || inner.trim() == "toc" {
+            offset = inner_start;
+            continue;
+        }
+        let (target, label) = match inner.split_once('|') {
+            Some((target, label)) => (target, Some(label.trim())),
+            None => (inner, None),
+        };
+        found.push(Found {
+            start,
+            end: inner_end + 2,
+            target: target.trim(),
+            label,
+        });
+        offset = inner_end + 2;
+    }
+    found
+}
+
+// ── Rendering ─────────────────────────────────────────────────────────────────
+
+/// Replace wiki links in the text of rendered `html` with anchors. Text
+/// inside tags, `<code>` and existing links is left alone.
+pub fn link(html: &str, resolver: &Resolver) -> String {
+    if !html.contains("[[") {
+        return html.to_string();
+    }
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    // Depth of enclosing `<code>` / `<a>` elements.
+    let mut skip = 0usize;
+    loop {
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        let text = &rest[..text_end];
+        if skip == 0 {
+            out.push_str(&link_text(text, resolver));
+        } else {
+            out.push_str(text);
+        }
+        rest = &rest[text_end..];
+        if rest.is_empty() {
+            break;
+        }
+        let tag_end = rest.find('>').map_or(rest.len(), |i| i + 1);
+        let tag = &rest[..tag_end];
+        let name = tag
+            .trim_start_matches(['<', '/'])
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .next()
+            .unwrap_or("");
+        if name == "code" || name == "a" {
+            if tag.starts_with("</") {
+                skip = skip.saturating_sub(1);
+            } else {
+                skip += 1;
+            }
+        }
+        out.push_str(tag);
+        rest = &rest[tag_end..];
+    }
+    out
+}
+
+/// Link the wiki links in one run of (escaped) HTML text.
+fn link_text(text: &str, resolver: &Resolver) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for link in find_links(text) {
+        out.push_str(&text[last..link.start]);
+        let label = PreEscaped(link.label.unwrap_or(link.target));
+        let anchor = match resolver.resolve(&html_to_text(link.target)) {
+            Some(url) => html! { a class="wikilink" href=(url) { (label) } },
+            None => html! { a class="wikilink broken" title="Page not found" { (label) } },
+        };
+        out.push_str(&anchor.into_string());
+        last = link.end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+// ── Broken-link report ────────────────────────────────────────────────────────
+
+pub struct BrokenLink {
+    /// Path of the linking file relative to the www root.
+    pub file: String,
+    /// 1-based line within the file.
+    pub line: usize,
+    pub target: String,
+}
+
+/// Every unresolved wiki link in every markdown file (drafts included),
+/// ordered by file and line.
+pub async fn broken_links(state: &AppState) -> Vec<BrokenLink> {
+    let root = &state.canonical_root;
+    let pages = handler::load_site_pages(state).await;
+    let mut broken = Vec::new();
+
+    for (path, _) in walk_markdown(root).await {
+        let Ok(raw) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        if !has_links(&raw) {
+            continue;
+        }
+        let rel = rel_path(root, &path);
+        let (_, url) = page_url(&rel);
+        let resolver = Resolver::new(&pages, &url);
+        for (line, target) in links_in_file(&raw, &path) {
+            if resolver.resolve(&target).is_none() {
+                broken.push(BrokenLink {
+                    file: rel.clone(),
+                    line,
+                    target,
+                });
+            }
+        }
+    }
+    broken.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    broken
+}
+
+/// (line, target) of each wiki link outside code in a raw markdown file.
+pub(crate) fn links_in_file(raw: &str, path: &Path) -> Vec<(usize, String)> {
+    let content = crate::front_matter::parse(raw).content;
+    // Content is a suffix of the file; count the front matter's lines.
+    let offset = raw
+        .strip_suffix(content.as_str())
+        .map_or(0, |front| front.matches('\n').count());
+
+    let mut options = markdown::ParseOptions::gfm();
+    options.constructs.frontmatter = false;
+    let tree = match markdown::to_mdast(&content, &options) {
+        Ok(tree) => tree,
+        Err(e) => {
+            tracing::warn!("Cannot parse {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut links = Vec::new();
+    collect_text_links(&tree, offset, &mut links);
+    links
+}
+
+fn collect_text_links(node: &Node, offset: usize, links: &mut Vec<(usize, String)>) {
+    match node {
+        Node::Text(text) => {
+            let first_line = text.position.as_ref().map_or(1, |p| p.start.line);
+            for link in find_links(&text.value) {
+                let line = first_line + text.value[..link.start].matches('\n').count();
+                links.push((offset + line, link.target.to_string()));
+            }
+        }
+        Node::Link(_) | Node::LinkReference(_) => {}
+        _ => {
+            for child in node.children().into_iter().flatten() {
+                collect_text_links(child, offset, links);
+            }
+        }
+    }
+}